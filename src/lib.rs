@@ -6,5 +6,9 @@ pub mod mb_rpcs;
 #[cfg(feature = "std")]
 pub mod mb_std;
 
-#[cfg(feature = "no_std")]
+//host tests drive the client through mb_std memories
+#[cfg(any(
+    feature = "no_std",
+    all(test, feature = "std", feature = "test_client")
+))]
 pub mod mb_no_std;
@@ -1,4 +1,5 @@
 use crate::mb_rpcs::*;
+use core::task::{Context, Poll};
 
 macro_rules! with_cache_line(
     ($(#[$attribute:meta])* $vis:vis $ty:ident $($tt:tt)*) => {
@@ -95,14 +96,18 @@ macro_rules! io_write_mbptr {
 #[derive(Default, Debug, Copy)]
 #[repr(C)]
 pub struct MBReqEntry {
-    pub action: MBAction,
+    //MBAction for the built-in actions, any other id for custom actions
+    pub action: u32,
     pub words: u32,
     pub args: [MBPtrT; MB_MAX_ARGS],
 }
 
 impl MBReqEntry {
-    pub fn set_action(&mut self, v: MBAction) {
-        io_write32!(&mut self.action as *mut MBAction, v)
+    pub fn set_action<A: Into<u32>>(&mut self, v: A) {
+        io_write32!(&mut self.action, v.into())
+    }
+    pub fn get_action(&self) -> MBAction {
+        MBAction::from(io_read32!(&self.action))
     }
     pub fn set_words(&mut self, v: u32) {
         io_write32!(&mut self.words, v)
@@ -116,7 +121,7 @@ impl Clone for MBReqEntry {
     fn clone(&self) -> Self {
         let mut entry = MBReqEntry {
             words: io_read32!(&self.words),
-            action: MBAction::from(io_read32!(&self.action as *const MBAction)),
+            action: io_read32!(&self.action),
             args: [0; MB_MAX_ARGS],
         };
         for i in 0..MB_MAX_ARGS {
@@ -232,6 +237,22 @@ pub trait MBChannelIf {
     fn ack_req(&mut self) -> MBPtrT;
     fn ack_resp(&mut self) -> MBPtrT;
     fn commit_resp(&mut self) -> MBPtrT;
+    //(addr, size) of the channel in the device's memory, fenced before checking its state
+    fn mem_range(&self) -> (MBPtrT, usize)
+    where
+        Self: Sized,
+    {
+        (self as *const Self as MBPtrT, core::mem::size_of::<Self>())
+    }
+    //hooks of channels in memories accessed asynchronously:
+    //the channel is loaded before the calls above and stored and released after them
+    fn poll_load(&mut self, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn poll_store(&mut self, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn release(&mut self) {}
 }
 
 #[derive(Default, Debug, PartialEq, Eq, Copy, Clone)]
@@ -281,7 +302,7 @@ impl MBChannel {
                 idx_c: MBQueueIdxC(0),
                 queue: [MBReqEntry {
                     words: 0,
-                    action: MBAction::IDLE,
+                    action: MBAction::IDLE as u32,
                     args: [0; MB_MAX_ARGS],
                 }; MB_MAX_ENTRIES],
             },
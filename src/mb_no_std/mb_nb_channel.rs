@@ -42,7 +42,8 @@ impl<CH: 'static + MBChannelIf> MBNbLockRefSender<CH> {
         req: REQ,
         ch: &mut CH,
     ) -> nb::Result<(), ()> {
-        __mb_rfence(ch as *mut _ as MBPtrT, core::mem::size_of::<CH>());
+        let (start, size) = ch.mem_range();
+        __mb_rfence(start, size);
         if !ch.is_ready() {
             return Err(nb::Error::WouldBlock);
         }
@@ -60,7 +61,8 @@ impl<CH: 'static + MBChannelIf> MBNbLockRefSender<CH> {
         rpc: &RPC,
         ch: &mut CH,
     ) -> nb::Result<RESP, MBNbSenderErr> {
-        __mb_rfence(ch as *mut _ as MBPtrT, core::mem::size_of::<CH>());
+        let (start, size) = ch.mem_range();
+        __mb_rfence(start, size);
         if !ch.is_ready() {
             return Err(nb::Error::Other(MBNbSenderErr::NotReady));
         }
@@ -114,7 +116,8 @@ impl<CH: 'static + MBChannelIf> MBNbRefSender<CH> {
         rpc: &RPC,
         req: REQ,
     ) -> nb::Result<(), ()> {
-        __mb_rfence(self.0 as *const _ as MBPtrT, core::mem::size_of::<CH>());
+        let (start, size) = self.0.mem_range();
+        __mb_rfence(start, size);
         if !self.0.is_ready() {
             return Err(nb::Error::WouldBlock);
         }
@@ -131,7 +134,8 @@ impl<CH: 'static + MBChannelIf> MBNbRefSender<CH> {
         &mut self,
         rpc: &RPC,
     ) -> nb::Result<RESP, MBNbSenderErr> {
-        __mb_rfence(self.0 as *const _ as MBPtrT, core::mem::size_of::<CH>());
+        let (start, size) = self.0.mem_range();
+        __mb_rfence(start, size);
         if !self.0.is_ready() {
             return Err(nb::Error::Other(MBNbSenderErr::NotReady));
         }
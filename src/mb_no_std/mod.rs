@@ -4,10 +4,32 @@ use crate::mb_rpcs::MBPtrT;
 pub use mb_nb_channel::*;
 pub use mb_rpcs::*;
 
+#[cfg(not(all(test, feature = "std")))]
 #[linkage = "weak"]
 #[no_mangle]
 extern "C" fn __mb_rfence(_start: MBPtrT, _size: usize) {}
 
+#[cfg(not(all(test, feature = "std")))]
 #[linkage = "weak"]
 #[no_mangle]
 extern "C" fn __mb_wfence(_start: MBPtrT, _size: usize) {}
+
+//fences of host tests go to the handler of the thread, called with (is_wfence, start, size),
+//route them to MBCacheShareMem to check that the client fences correctly
+#[cfg(all(test, feature = "std"))]
+type MBTestFence = Option<Box<dyn FnMut(bool, MBPtrT, usize)>>;
+#[cfg(all(test, feature = "std"))]
+std::thread_local! {
+    pub(crate) static MB_TEST_FENCE: core::cell::RefCell<MBTestFence> =
+        core::cell::RefCell::new(None);
+}
+
+#[cfg(all(test, feature = "std"))]
+fn __mb_rfence(start: MBPtrT, size: usize) {
+    MB_TEST_FENCE.with(|f| f.borrow_mut().as_mut().map(|f| f(false, start, size)));
+}
+
+#[cfg(all(test, feature = "std"))]
+fn __mb_wfence(start: MBPtrT, size: usize) {
+    MB_TEST_FENCE.with(|f| f.borrow_mut().as_mut().map(|f| f(true, start, size)));
+}
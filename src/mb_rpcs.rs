@@ -46,6 +46,12 @@ impl From<u32> for MBAction {
     }
 }
 
+impl From<MBAction> for u32 {
+    fn from(v: MBAction) -> Self {
+        v as u32
+    }
+}
+
 pub trait MBRpc {
     type REQ;
     type RESP;
@@ -58,6 +58,58 @@ macro_rules! export_mb_backdoor_dpi {
                 *data = ptr;
             }
         }
+
+        //return 0 and set addr if found, otherwise -1
+        #[no_mangle]
+        extern "C" fn mb_symbol_addr(
+            space_name: *const std::os::raw::c_char,
+            name: *const std::os::raw::c_char,
+            addr: *mut u64,
+        ) -> i32 {
+            let space_name = unsafe { std::ffi::CStr::from_ptr(space_name) }.to_str().unwrap();
+            let name = unsafe { std::ffi::CStr::from_ptr(name) }.to_str().unwrap();
+            if let Some(s) = $mailbox.get_symbol(space_name, name) {
+                unsafe {
+                    *addr = s.addr as u64;
+                }
+                0
+            } else {
+                -1
+            }
+        }
+
+        //format: null or "" to auto detect
+        #[no_mangle]
+        extern "C" fn mb_backdoor_load_image(
+            space_name: *const std::os::raw::c_char,
+            file: *const std::os::raw::c_char,
+            format: *const std::os::raw::c_char,
+            addr: u64,
+        ) {
+            let space_name = unsafe { std::ffi::CStr::from_ptr(space_name) }.to_str().unwrap();
+            let file = unsafe { std::ffi::CStr::from_ptr(file) }.to_str().unwrap();
+            let format = if format.is_null() {
+                ""
+            } else {
+                unsafe { std::ffi::CStr::from_ptr(format) }.to_str().unwrap()
+            };
+            let opts = crate::mailbox_rs::mb_std::MBLoadOpts {
+                format: if format.is_empty() {
+                    None
+                } else {
+                    Some(crate::mailbox_rs::mb_std::MBImageFormat::parse(format).unwrap())
+                },
+                addr: Some(addr as crate::mailbox_rs::mb_rpcs::MBPtrT),
+                ..Default::default()
+            };
+            $mailbox
+                .get_space(space_name)
+                .expect(format!("space {} does not exist!", space_name).as_str())
+                .lock()
+                .unwrap()
+                .load_image(file, &opts)
+                .unwrap();
+        }
     };
     (@ $t:ty, $mailbox:ident) => {
         crate::mailbox_rs::mb_std::paste::paste!{
@@ -86,9 +138,71 @@ macro_rules! export_mb_backdoor_dpi {
                 let m_data = unsafe { std::slice::from_raw_parts_mut(data as *mut u8, std::mem::size_of::<$t>()) };
                 space.read(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, m_data);
             }
+            #[no_mangle]
+            extern "C" fn [<mb_backdoor_write_symbol_ $t>](
+                space_name: *const std::os::raw::c_char,
+                name: *const std::os::raw::c_char,
+                offset: u64,
+                data: $t,
+            ) {
+                let space_name = unsafe { std::ffi::CStr::from_ptr(space_name) }.to_str().unwrap();
+                let name = unsafe { std::ffi::CStr::from_ptr(name) }.to_str().unwrap();
+                $mailbox
+                    .write_symbol(space_name, name, offset as crate::mailbox_rs::mb_rpcs::MBPtrT, &data.to_ne_bytes())
+                    .unwrap();
+            }
+            #[no_mangle]
+            extern "C" fn [<mb_backdoor_read_symbol_ $t>](
+                space_name: *const std::os::raw::c_char,
+                name: *const std::os::raw::c_char,
+                offset: u64,
+                data: *mut $t,
+            ) {
+                let space_name = unsafe { std::ffi::CStr::from_ptr(space_name) }.to_str().unwrap();
+                let name = unsafe { std::ffi::CStr::from_ptr(name) }.to_str().unwrap();
+                let m_data = unsafe { std::slice::from_raw_parts_mut(data as *mut u8, std::mem::size_of::<$t>()) };
+                $mailbox
+                    .read_symbol(space_name, name, offset as crate::mailbox_rs::mb_rpcs::MBPtrT, m_data)
+                    .unwrap();
+            }
+            #[no_mangle]
+            extern "C" fn [<mb_backdoor_cas_ $t>](
+                space_name: *const std::os::raw::c_char,
+                addr: u64,
+                current: $t,
+                new: $t,
+            ) -> $t {
+                let space_name = unsafe { std::ffi::CStr::from_ptr(space_name) }.to_str().unwrap();
+                $mailbox
+                    .get_space(space_name)
+                    .expect(format!("space {} does not exist!", space_name).as_str())
+                    .lock()
+                    .unwrap()
+                    .atomic_cas(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, current, new)
+                    .unwrap()
+            }
+            export_mb_backdoor_dpi!(@atomic $t, $mailbox, swap, atomic_swap);
+            export_mb_backdoor_dpi!(@atomic $t, $mailbox, fetch_add, atomic_fetch_add);
+            export_mb_backdoor_dpi!(@atomic $t, $mailbox, fetch_and, atomic_fetch_and);
+            export_mb_backdoor_dpi!(@atomic $t, $mailbox, fetch_or, atomic_fetch_or);
         }
 
     };
+    (@atomic $t:ty, $mailbox:ident, $name:ident, $op:ident) => {
+        crate::mailbox_rs::mb_std::paste::paste!{
+            #[no_mangle]
+            extern "C" fn [<mb_backdoor_ $name _ $t>](space_name: *const std::os::raw::c_char, addr: u64, data: $t) -> $t {
+                let space_name = unsafe { std::ffi::CStr::from_ptr(space_name) }.to_str().unwrap();
+                $mailbox
+                    .get_space(space_name)
+                    .expect(format!("space {} does not exist!", space_name).as_str())
+                    .lock()
+                    .unwrap()
+                    .$op(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, data)
+                    .unwrap()
+            }
+        }
+    };
 }
 
 #[macro_export]
@@ -151,18 +265,80 @@ macro_rules! export_mb_backdoor_py {
             let m_data = std::ffi::CString::new(data).unwrap();
             space.write(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, m_data.to_bytes_with_nul());
         }
+        #[pyfunction]
+        fn py_mb_symbol_addr(
+            space_name: &str,
+            name: &str,
+        ) -> Option<u64> {
+            $mailbox.get_symbol(space_name, name).map(|s| s.addr as u64)
+        }
+        //format: "" to auto detect
+        #[pyfunction]
+        fn py_mb_backdoor_load_image(
+            space_name: &str,
+            file: &str,
+            format: &str,
+            addr: u64,
+        ) {
+            let opts = crate::mailbox_rs::mb_std::MBLoadOpts {
+                format: if format.is_empty() {
+                    None
+                } else {
+                    Some(crate::mailbox_rs::mb_std::MBImageFormat::parse(format).unwrap())
+                },
+                addr: Some(addr as crate::mailbox_rs::mb_rpcs::MBPtrT),
+                ..Default::default()
+            };
+            $mailbox
+                .get_space(space_name)
+                .expect(format!("space {} does not exist!", space_name).as_str())
+                .lock()
+                .unwrap()
+                .load_image(file, &opts)
+                .unwrap();
+        }
         fn py_mb_backdoor_add_commom_funcs(m: &PyModule) -> PyResult<()> {
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_get_space, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_load_image, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_symbol_addr, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_string, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_string, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_u8, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_symbol_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_symbol_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_cas_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_swap_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_add_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_and_u8, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_or_u8, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_u16, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_symbol_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_symbol_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_cas_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_swap_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_add_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_and_u16, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_or_u16, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_u32, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_symbol_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_symbol_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_cas_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_swap_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_add_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_and_u32, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_or_u32, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_u64, m)?)?;
             m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_read_symbol_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_write_symbol_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_cas_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_swap_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_add_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_and_u64, m)?)?;
+            m.add_function(crate::pyo3::wrap_pyfunction!(py_mb_backdoor_fetch_or_u64, m)?)?;
             Ok(())
         }
     };
@@ -191,6 +367,52 @@ macro_rules! export_mb_backdoor_py {
                 space.read_sized(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, &mut data);
                 data
             }
+            #[pyfunction]
+            fn [<py_mb_backdoor_write_symbol_ $t>](space_name: &str, name: &str, offset: u64, data: $t) {
+                $mailbox
+                    .write_symbol(space_name, name, offset as crate::mailbox_rs::mb_rpcs::MBPtrT, &data.to_ne_bytes())
+                    .unwrap();
+            }
+            #[pyfunction]
+            fn [<py_mb_backdoor_read_symbol_ $t>](
+                space_name: &str,
+                name: &str,
+                offset: u64,
+            ) -> $t {
+                let mut data = [0u8; std::mem::size_of::<$t>()];
+                $mailbox
+                    .read_symbol(space_name, name, offset as crate::mailbox_rs::mb_rpcs::MBPtrT, &mut data)
+                    .unwrap();
+                <$t>::from_ne_bytes(data)
+            }
+            #[pyfunction]
+            fn [<py_mb_backdoor_cas_ $t>](space_name: &str, addr: u64, current: $t, new: $t) -> $t {
+                $mailbox
+                    .get_space(space_name)
+                    .expect(format!("space {} does not exist!", space_name).as_str())
+                    .lock()
+                    .unwrap()
+                    .atomic_cas(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, current, new)
+                    .unwrap()
+            }
+            export_mb_backdoor_py!(@atomic $t, $mailbox, swap, atomic_swap);
+            export_mb_backdoor_py!(@atomic $t, $mailbox, fetch_add, atomic_fetch_add);
+            export_mb_backdoor_py!(@atomic $t, $mailbox, fetch_and, atomic_fetch_and);
+            export_mb_backdoor_py!(@atomic $t, $mailbox, fetch_or, atomic_fetch_or);
+        }
+    };
+    (@atomic $t:ty, $mailbox:ident, $name:ident, $op:ident) => {
+        crate::mailbox_rs::mb_std::paste::paste!{
+            #[pyfunction]
+            fn [<py_mb_backdoor_ $name _ $t>](space_name: &str, addr: u64, data: $t) -> $t {
+                $mailbox
+                    .get_space(space_name)
+                    .expect(format!("space {} does not exist!", space_name).as_str())
+                    .lock()
+                    .unwrap()
+                    .$op(addr as crate::mailbox_rs::mb_rpcs::MBPtrT, data)
+                    .unwrap()
+            }
         }
     };
 }
@@ -21,6 +21,7 @@ pub struct MBAsyncChannel<CH: MBChannelIf> {
     channel: CH,
     c_waker: Option<Waker>,
     s_waker: Option<Waker>,
+    client_version: MBVersion,
 }
 
 impl<CH: MBChannelIf> MBAsyncChannel<CH> {
@@ -29,8 +30,31 @@ impl<CH: MBChannelIf> MBAsyncChannel<CH> {
             channel: ch,
             c_waker: None,
             s_waker: None,
+            client_version: MBVersion::from_u32(0),
         }
     }
+    //run op on the loaded channel, then store and release it.
+    //the result is kept in done while storing, so op is not run again when polled again
+    fn poll_op<T, F: FnOnce(&mut Self, &mut Context) -> Poll<T>>(
+        &mut self,
+        done: &mut Option<T>,
+        cx: &mut Context,
+        op: F,
+    ) -> Poll<T> {
+        if done.is_none() {
+            std::task::ready!(self.channel.poll_load(cx));
+            match op(self, cx) {
+                Poll::Ready(r) => *done = Some(r),
+                Poll::Pending => {
+                    self.channel.release();
+                    return Poll::Pending;
+                }
+            }
+        }
+        std::task::ready!(self.channel.poll_store(cx));
+        self.channel.release();
+        Poll::Ready(done.take().unwrap())
+    }
 }
 
 pub struct MBAsyncSender<CH: MBChannelIf>(Arc<Mutex<MBAsyncChannel<CH>>>);
@@ -43,56 +67,63 @@ impl<CH: MBChannelIf> MBAsyncSender<CH> {
         &self,
         rpc: &RPC,
         req: REQ,
+        done: &mut Option<MBAsyncChannelResult<()>>,
         cx: &mut Context,
     ) -> Poll<MBAsyncChannelResult<()>> {
-        let mut ch = self.0.lock().unwrap();
-        if !ch.channel.req_can_put() || !ch.channel.is_ready() {
-            ch.c_waker = Some(cx.waker().clone());
-            return Poll::Pending;
-        }
-        ch.channel.put_req(rpc, req);
-        ch.channel.commit_req();
-        if let Some(w) = ch.s_waker.take() {
-            w.wake();
-        }
-        Poll::Ready(Ok(()))
+        self.0.lock().unwrap().poll_op(done, cx, |ch, cx| {
+            if !ch.channel.req_can_put() || !ch.channel.is_ready() {
+                ch.c_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            ch.channel.put_req(rpc, req);
+            ch.channel.commit_req();
+            if let Some(w) = ch.s_waker.take() {
+                w.wake();
+            }
+            Poll::Ready(Ok(()))
+        })
     }
     fn try_recv<RESP, RPC: MBRpc<RESP = RESP>>(
         &self,
         rpc: &RPC,
+        done: &mut Option<MBAsyncChannelResult<RESP>>,
         cx: &mut Context,
     ) -> Poll<MBAsyncChannelResult<RESP>> {
-        let mut ch = self.0.lock().unwrap();
-        if !ch.channel.is_ready() {
+        self.0.lock().unwrap().poll_op(done, cx, |ch, cx| {
+            if !ch.channel.is_ready() {
+                if let Some(w) = ch.s_waker.take() {
+                    w.wake();
+                }
+                return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
+            }
+            if !ch.channel.resp_can_get() {
+                ch.c_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let ret = ch.channel.get_resp(rpc);
+            ch.channel.ack_resp();
             if let Some(w) = ch.s_waker.take() {
                 w.wake();
             }
-            return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
-        }
-        if !ch.channel.resp_can_get() {
-            ch.c_waker = Some(cx.waker().clone());
-            return Poll::Pending;
-        }
-        let ret = ch.channel.get_resp(rpc);
-        ch.channel.ack_resp();
-        if let Some(w) = ch.s_waker.take() {
-            w.wake();
-        }
-        Poll::Ready(Ok(ret))
+            Poll::Ready(Ok(ret))
+        })
     }
 
-    fn reset_req(&self) {
-        let mut ch = self.0.lock().unwrap();
-        ch.channel.reset_req();
-        ch.channel.reset_req_p2();
-        if let Some(w) = ch.s_waker.take() {
-            w.wake();
-        }
+    fn reset_req(&self, done: &mut Option<()>, cx: &mut Context) -> Poll<()> {
+        self.0.lock().unwrap().poll_op(done, cx, |ch, _| {
+            ch.channel.reset_req();
+            ch.channel.reset_req_p2();
+            if let Some(w) = ch.s_waker.take() {
+                w.wake();
+            }
+            Poll::Ready(())
+        })
     }
 
     pub fn reset<'a>(&'a self) -> impl Future<Output = ()> + 'a {
         async {
-            self.reset_req();
+            let mut done = None;
+            async_std::future::poll_fn(|cx| self.reset_req(&mut done, cx)).await;
             async_std::task::yield_now().await;
         }
     }
@@ -106,6 +137,7 @@ impl<CH: MBChannelIf> MBAsyncSender<CH> {
             sender: self,
             rpc: rpc,
             data: req,
+            done: None,
         };
         async {
             let req = req_fut.await.expect("Unexpected reset!");
@@ -120,6 +152,7 @@ impl<CH: MBChannelIf> MBAsyncSender<CH> {
         let resp_fut = MBAsyncSenderResp {
             sender: self,
             rpc: rpc,
+            done: None,
             _marker: PhantomData,
         };
         async {
@@ -134,30 +167,38 @@ struct MBAsyncSenderReq<'a, REQ, RPC, CH: MBChannelIf> {
     sender: &'a MBAsyncSender<CH>,
     rpc: &'a RPC,
     data: REQ,
+    done: Option<MBAsyncChannelResult<()>>,
 }
 
+impl<'a, REQ, RPC, CH: MBChannelIf> Unpin for MBAsyncSenderReq<'a, REQ, RPC, CH> {}
+
 impl<'a, REQ: Copy, RPC: MBRpc<REQ = REQ>, CH: MBChannelIf> Future
     for MBAsyncSenderReq<'a, REQ, RPC, CH>
 {
     type Output = MBAsyncChannelResult<()>;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let ret = self.sender.try_send(self.rpc, self.data, cx);
-        ret
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.sender
+            .try_send(this.rpc, this.data, &mut this.done, cx)
     }
 }
 
 struct MBAsyncSenderResp<'a, RESP, RPC, CH: MBChannelIf> {
     sender: &'a MBAsyncSender<CH>,
     rpc: &'a RPC,
+    done: Option<MBAsyncChannelResult<RESP>>,
     _marker: PhantomData<RESP>,
 }
 
+impl<'a, RESP, RPC, CH: MBChannelIf> Unpin for MBAsyncSenderResp<'a, RESP, RPC, CH> {}
+
 impl<'a, RESP, RPC: MBRpc<RESP = RESP>, CH: MBChannelIf> Future
     for MBAsyncSenderResp<'a, RESP, RPC, CH>
 {
     type Output = MBAsyncChannelResult<RESP>;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.sender.try_recv(self.rpc, cx)
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.sender.try_recv(this.rpc, &mut this.done, cx)
     }
 }
 
@@ -167,74 +208,88 @@ impl<CH: MBChannelIf> MBAsyncReceiver<CH> {
     pub fn new(ch: &Arc<Mutex<MBAsyncChannel<CH>>>) -> MBAsyncReceiver<CH> {
         MBAsyncReceiver(ch.clone())
     }
-    fn try_recv(&self, cx: &mut Context) -> Poll<MBAsyncChannelResult<MBReqEntry>> {
-        let mut ch = self.0.lock().unwrap();
-        if !ch.channel.is_ready() {
-            if let Some(w) = ch.s_waker.take() {
-                w.wake();
+    fn try_recv(
+        &self,
+        done: &mut Option<MBAsyncChannelResult<MBReqEntry>>,
+        cx: &mut Context,
+    ) -> Poll<MBAsyncChannelResult<MBReqEntry>> {
+        self.0.lock().unwrap().poll_op(done, cx, |ch, cx| {
+            if !ch.channel.is_ready() {
+                if let Some(w) = ch.s_waker.take() {
+                    w.wake();
+                }
+                return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
             }
-            return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
-        }
-        if !ch.channel.req_can_get() {
-            ch.s_waker = Some(cx.waker().clone());
+            if !ch.channel.req_can_get() {
+                ch.s_waker = Some(cx.waker().clone());
+                if !ch.channel.is_ready() {
+                    if let Some(w) = ch.s_waker.take() {
+                        w.wake();
+                    }
+                    return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
+                }
+                return Poll::Pending;
+            }
+            let req = ch.channel.get_req();
             if !ch.channel.is_ready() {
                 if let Some(w) = ch.s_waker.take() {
                     w.wake();
                 }
                 return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
             }
-            return Poll::Pending;
-        }
-        let req = ch.channel.get_req();
-        if !ch.channel.is_ready() {
-            if let Some(w) = ch.s_waker.take() {
+            ch.channel.ack_req();
+            if let Some(w) = ch.c_waker.take() {
                 w.wake();
             }
-            return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
-        }
-        ch.channel.ack_req();
-        if let Some(w) = ch.c_waker.take() {
-            w.wake();
-        }
-        Poll::Ready(Ok(req))
+            Poll::Ready(Ok(req))
+        })
     }
 
-    fn try_send(&self, resp: MBRespEntry, cx: &mut Context) -> Poll<MBAsyncChannelResult<()>> {
-        let mut ch = self.0.lock().unwrap();
-        if !ch.channel.is_ready() {
-            if let Some(w) = ch.s_waker.take() {
+    fn try_send(
+        &self,
+        resp: MBRespEntry,
+        done: &mut Option<MBAsyncChannelResult<()>>,
+        cx: &mut Context,
+    ) -> Poll<MBAsyncChannelResult<()>> {
+        self.0.lock().unwrap().poll_op(done, cx, |ch, cx| {
+            if !ch.channel.is_ready() {
+                if let Some(w) = ch.s_waker.take() {
+                    w.wake();
+                }
+                return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
+            }
+            if !ch.channel.resp_can_put() {
+                ch.s_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            ch.channel.put_resp(resp);
+            ch.channel.commit_resp();
+            if let Some(w) = ch.c_waker.take() {
                 w.wake();
             }
-            return Poll::Ready(Err(MBAsyncChannelErr::NotReady));
-        }
-        if !ch.channel.resp_can_put() {
-            ch.s_waker = Some(cx.waker().clone());
-            return Poll::Pending;
-        }
-        ch.channel.put_resp(resp);
-        ch.channel.commit_resp();
-        if let Some(w) = ch.c_waker.take() {
-            w.wake();
-        }
-        Poll::Ready(Ok(()))
+            Poll::Ready(Ok(()))
+        })
     }
 
-    fn wait_reset(&self, cx: &mut Context) -> Poll<()> {
-        let mut ch = self.0.lock().unwrap();
-        if !ch.channel.reset_ready() {
-            ch.s_waker = Some(cx.waker().clone());
-            return Poll::Pending;
-        }
-        ch.channel.reset_pre_ack();
-        ch.channel.reset_ack();
-        if let Some(w) = ch.c_waker.take() {
-            w.wake();
-        }
-        Poll::Ready(())
+    fn wait_reset(&self, done: &mut Option<()>, cx: &mut Context) -> Poll<()> {
+        self.0.lock().unwrap().poll_op(done, cx, |ch, cx| {
+            if !ch.channel.reset_ready() {
+                ch.s_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            //kept for check_version, the channel may not be readable out of poll_op
+            ch.client_version = ch.channel.version();
+            ch.channel.reset_pre_ack();
+            ch.channel.reset_ack();
+            if let Some(w) = ch.c_waker.take() {
+                w.wake();
+            }
+            Poll::Ready(())
+        })
     }
 
     pub fn check_version(&self, server_tag: &str) {
-        let client_version = self.0.lock().unwrap().channel.version();
+        let client_version = self.0.lock().unwrap().client_version;
         if MB_VERSION != client_version {
             panic!(
                 "[{}(server)] versions mismatched! expect client version {}.{}.x, but get {}.{}.x!",
@@ -248,7 +303,10 @@ impl<CH: MBChannelIf> MBAsyncReceiver<CH> {
     }
 
     pub fn reset<'a>(&'a self) -> impl Future<Output = ()> + 'a {
-        let fut = MBAsyncReceiverReset { receiver: self };
+        let fut = MBAsyncReceiverReset {
+            receiver: self,
+            done: None,
+        };
         fut
     }
 
@@ -256,7 +314,10 @@ impl<CH: MBChannelIf> MBAsyncReceiver<CH> {
         &'a self,
         server_tag: &'a str,
     ) -> impl Future<Output = Option<MBReqEntry>> + 'a {
-        let req_fut = MBAsyncReceiverReq { receiver: self };
+        let req_fut = MBAsyncReceiverReq {
+            receiver: self,
+            done: None,
+        };
         async move {
             match req_fut.await {
                 Err(MBAsyncChannelErr::NotReady) => {
@@ -287,6 +348,7 @@ impl<CH: MBChannelIf> MBAsyncReceiver<CH> {
         let resp_fut = MBAsyncReceiverResp {
             receiver: self,
             resp,
+            done: None,
         };
         async move {
             match resp_fut.await {
@@ -302,35 +364,41 @@ impl<CH: MBChannelIf> MBAsyncReceiver<CH> {
 
 struct MBAsyncReceiverReq<'a, CH: MBChannelIf> {
     receiver: &'a MBAsyncReceiver<CH>,
+    done: Option<MBAsyncChannelResult<MBReqEntry>>,
 }
 
 impl<'a, CH: MBChannelIf> Future for MBAsyncReceiverReq<'a, CH> {
     type Output = MBAsyncChannelResult<MBReqEntry>;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.receiver.try_recv(cx)
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.receiver.try_recv(&mut this.done, cx)
     }
 }
 
 struct MBAsyncReceiverReset<'a, CH: MBChannelIf> {
     receiver: &'a MBAsyncReceiver<CH>,
+    done: Option<()>,
 }
 
 impl<'a, CH: MBChannelIf> Future for MBAsyncReceiverReset<'a, CH> {
     type Output = ();
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.receiver.wait_reset(cx)
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.receiver.wait_reset(&mut this.done, cx)
     }
 }
 
 struct MBAsyncReceiverResp<'a, CH: MBChannelIf> {
     receiver: &'a MBAsyncReceiver<CH>,
     resp: MBRespEntry,
+    done: Option<MBAsyncChannelResult<()>>,
 }
 
 impl<'a, CH: MBChannelIf> Future for MBAsyncReceiverResp<'a, CH> {
     type Output = MBAsyncChannelResult<()>;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.receiver.try_send(self.resp, cx)
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.receiver.try_send(this.resp, &mut this.done, cx)
     }
 }
 
@@ -26,7 +26,7 @@ pub fn get_yaml_with_ref<'a>(doc: &'a Yaml, key: &str) -> &'a Yaml {
 pub struct MBShareMemSpaceBuilder<M: MBShareMemBlock, P: MBShareMemParser<MemType = M>> {
     docs: Vec<Yaml>,
     parser: P,
-    shared: HashMap<String, Arc<Mutex<M>>>,
+    shared: HashMap<String, (Arc<Mutex<M>>, MBShareMemPerm)>,
     spaces: HashMap<String, Arc<Mutex<MBShareMemSpace<M>>>>,
 }
 impl<M: MBShareMemBlock, P: MBShareMemParser<MemType = M>> MBShareMemSpaceBuilder<M, P> {
@@ -53,30 +53,98 @@ impl<M: MBShareMemBlock, P: MBShareMemParser<MemType = M>> MBShareMemSpaceBuilde
                 let k = key.as_str().unwrap();
                 self.shared.insert(
                     k.to_string(),
-                    Arc::new(Mutex::new(self.parser.parse(k, mem)?)),
+                    (
+                        Arc::new(Mutex::new(self.parser.parse(k, mem)?)),
+                        Self::get_perm(k, mem)?.unwrap_or_default(),
+                    ),
                 );
             }
         }
         Ok(self)
     }
 
+    fn get_shared(&self, name: &str) -> Result<&(Arc<Mutex<M>>, MBShareMemPerm), String> {
+        self.shared
+            .get(name)
+            .ok_or(format!("Can't get shared mem {:?}!", name))
+    }
+
+    fn get_perm(name: &str, doc: &Yaml) -> Result<Option<MBShareMemPerm>, String> {
+        match &doc["perm"] {
+            Yaml::BadValue => Ok(None),
+            p => p
+                .as_str()
+                .ok_or(format!("{:?}: perm should be string!", name))
+                .and_then(|p| MBShareMemPerm::parse(p).map_err(|e| format!("{:?}: {}", name, e)))
+                .map(Some),
+        }
+    }
+
+    fn add_mem(
+        mem_space: &mut MBShareMemSpace<M>,
+        name: &str,
+        mem: &Arc<Mutex<M>>,
+        perm: MBShareMemPerm,
+    ) -> Result<(), String> {
+        let (base, size) = {
+            let m = mem.lock().unwrap();
+            (m.base(), m.size())
+        };
+        mem_space.add_window(name, mem, base, 0, size, perm)
+    }
+
+    fn add_window(
+        &self,
+        mem_space: &mut MBShareMemSpace<M>,
+        name: &str,
+        shared: &str,
+        doc: &Yaml,
+    ) -> Result<(), String> {
+        let (mem, shared_perm) = self.get_shared(shared)?;
+        let base = doc["base"]
+            .as_i64()
+            .ok_or(format!("{:?}: window base should be integer!", name))?
+            as MBPtrT;
+        let offset = match &doc["offset"] {
+            Yaml::BadValue => 0,
+            o => o
+                .as_i64()
+                .ok_or(format!("{:?}: window offset should be integer!", name))?
+                as MBPtrT,
+        };
+        let size = match &doc["size"] {
+            Yaml::BadValue => mem.lock().unwrap().size().saturating_sub(offset),
+            s => s
+                .as_i64()
+                .ok_or(format!("{:?}: window size should be integer!", name))?
+                as MBPtrT,
+        };
+        let perm = Self::get_perm(name, doc)?.unwrap_or(*shared_perm);
+        mem_space.add_window(name, mem, base, offset, size, perm)
+    }
+
     fn add_space(&self, mem_space: &mut MBShareMemSpace<M>, doc: &Vec<Yaml>) -> Result<(), String> {
         for y in doc.iter() {
             match y {
                 Yaml::Hash(m) => {
                     let (name, v) = m.front().unwrap();
                     let n = name.as_str().unwrap();
-                    mem_space
-                        .add_mem(&Arc::new(Mutex::new(self.parser.parse(n, v)?)))
-                        .map_err(|_| format!("{:?} is overlapped with other memory!", n))?;
+                    if let Some(shared) = v["window"].as_str() {
+                        self.add_window(mem_space, n, shared, v)?;
+                    } else {
+                        let mem = Arc::new(Mutex::new(self.parser.parse(n, v)?));
+                        Self::add_mem(
+                            mem_space,
+                            n,
+                            &mem,
+                            Self::get_perm(n, v)?.unwrap_or_default(),
+                        )?;
+                    }
+                }
+                Yaml::String(m) => {
+                    let (mem, perm) = self.get_shared(m)?;
+                    Self::add_mem(mem_space, m, mem, *perm)?
                 }
-                Yaml::String(m) => mem_space
-                    .add_mem(
-                        self.shared
-                            .get(m)
-                            .ok_or(format!("Can't get shared mem {:?}!", m))?,
-                    )
-                    .map_err(|_| format!("{:?} is overlapped with other memory!", m))?,
                 Yaml::Array(a) => self.add_space(mem_space, &a.to_vec())?,
                 _ => return Err(format!("Invalid type {:?}!", y)),
             }
@@ -115,6 +183,10 @@ pub struct MBChannelShareMemSys<SM: MBShareMem> {
     chs: HashMap<String, Arc<Mutex<MBAsyncChannel<MBChannelShareMem<SM>>>>>,
     space_map: HashMap<String, Arc<Mutex<SM>>>,
     ch_space_map: HashMap<String, String>,
+    symbols: HashMap<String, MBSymbolTable>,
+    sinks: HashMap<String, MBOutputSinks>,
+    error_policy: MBErrorPolicy,
+    tracer: Option<MBTracer>,
     fs: Arc<Option<MBFs>>,
 }
 impl<SM: MBShareMem> MBChannelShareMemSys<SM> {
@@ -123,6 +195,10 @@ impl<SM: MBShareMem> MBChannelShareMemSys<SM> {
             chs: HashMap::new(),
             space_map,
             ch_space_map: HashMap::new(),
+            symbols: HashMap::new(),
+            sinks: HashMap::new(),
+            error_policy: MBErrorPolicy::default(),
+            tracer: None,
             fs: Arc::new(None),
         }
     }
@@ -132,6 +208,79 @@ impl<SM: MBShareMem> MBChannelShareMemSys<SM> {
     pub fn get_ch_space_name(&self, ch_name: &str) -> Option<&str> {
         self.ch_space_map.get(ch_name).map(|s| s.as_str())
     }
+    pub fn get_symbols(&self, space_name: &str) -> Option<&MBSymbolTable> {
+        self.symbols.get(space_name)
+    }
+    pub fn get_symbol(&self, space_name: &str, name: &str) -> Option<&MBSymbol> {
+        self.get_symbols(space_name).and_then(|t| t.get(name))
+    }
+    pub fn lookup_symbol(&self, space_name: &str, addr: MBPtrT) -> Option<&MBSymbol> {
+        self.get_symbols(space_name).and_then(|t| t.lookup(addr))
+    }
+    pub fn lookup_symbols(&self, space_name: &str, addr: MBPtrT) -> Vec<&MBSymbol> {
+        self.get_symbols(space_name)
+            .map_or(vec![], |t| t.lookup_all(addr))
+    }
+    fn add_symbols(&mut self, space_name: &str, file: &str) -> Result<(), String> {
+        self.symbols
+            .entry(space_name.to_string())
+            .or_default()
+            .add_file(file)
+    }
+    fn symbol_addr(
+        &self,
+        space_name: &str,
+        name: &str,
+        offset: MBPtrT,
+        len: usize,
+    ) -> Result<(&Arc<Mutex<SM>>, MBPtrT), String> {
+        let space = self
+            .get_space(space_name)
+            .ok_or(format!("space {} does not exist!", space_name))?;
+        let symbol = self.get_symbol(space_name, name).ok_or(format!(
+            "symbol {} not found in space {}!",
+            name, space_name
+        ))?;
+        let overflow = || format!("offset {:#x} of symbol {} overflows!", offset, name);
+        let end = (offset as usize).checked_add(len).ok_or_else(overflow)?;
+        if symbol.size != 0 && end > symbol.size {
+            return Err(format!(
+                "{} bytes @ offset {:#x} exceeds symbol {}({} bytes)!",
+                len, offset, name, symbol.size
+            ));
+        }
+        let addr = symbol.addr.checked_add(offset).ok_or_else(overflow)?;
+        Ok((space, addr))
+    }
+    //offset and length are checked against the symbol size, unless it is 0
+    pub fn read_symbol(
+        &self,
+        space_name: &str,
+        name: &str,
+        offset: MBPtrT,
+        data: &mut [u8],
+    ) -> Result<(), String> {
+        let (space, addr) = self.symbol_addr(space_name, name, offset, data.len())?;
+        space
+            .lock()
+            .unwrap()
+            .try_read_slice_checked(addr, data)
+            .map_err(|e| format!("read symbol {} fail! {}", name, e))
+    }
+    pub fn write_symbol(
+        &self,
+        space_name: &str,
+        name: &str,
+        offset: MBPtrT,
+        data: &[u8],
+    ) -> Result<(), String> {
+        let (space, addr) = self.symbol_addr(space_name, name, offset, data.len())?;
+        space
+            .lock()
+            .unwrap()
+            .try_write_slice_checked(addr, data)
+            .map_err(|e| format!("write symbol {} fail! {}", name, e))
+    }
     pub fn mailboxes(&self) -> &HashMap<String, Arc<Mutex<MBAsyncChannel<MBChannelShareMem<SM>>>>> {
         &self.chs
     }
@@ -155,38 +304,59 @@ impl<SM: MBShareMem> MBChannelShareMemSys<SM> {
         }
     }
 
+    pub fn error_policy(&self) -> MBErrorPolicy {
+        self.error_policy
+    }
+    pub fn set_error_policy(&mut self, policy: MBErrorPolicy) {
+        self.error_policy = policy
+    }
+    pub fn tracer(&self) -> Option<&MBTracer> {
+        self.tracer.as_ref()
+    }
+    //records the served requests of all channels, see MBReplay
+    pub fn set_tracer(&mut self, tracer: Option<&MBTracer>) {
+        self.tracer = tracer.cloned()
+    }
+
     pub fn serve<F: Fn(&MBSMServer<SM>)>(
         &self,
         server_callback: F,
     ) -> Vec<impl Future<Output = (String, u32)> + '_ + std::marker::Unpin> {
+        self.serve_with_resolver(|_, sm| MBSMPtrResolver::new(sm), server_callback)
+    }
+    //serve each channel with the resolver made from the channel name and its space,
+    //e.g. a MBPageTableResolver, keep a clone of it to set the root later
+    pub fn serve_with_resolver<
+        'a,
+        R: MBShareMemResolver<SM = SM> + 'a,
+        G: Fn(&str, &Arc<Mutex<SM>>) -> R,
+        F: Fn(&MBSMServer<SM, R>),
+    >(
+        &'a self,
+        resolver: G,
+        server_callback: F,
+    ) -> Vec<impl Future<Output = (String, u32)> + 'a + std::marker::Unpin> {
+        let stop_all = MBStopAll::new();
         self.chs
             .iter()
             .map(|ch| {
-                let server = MBSMServer::new(
-                    ch.0,
-                    &self.fs,
-                    self.get_space(self.get_ch_space_name(ch.0).unwrap())
-                        .unwrap(),
-                );
+                let space = self
+                    .get_space(self.get_ch_space_name(ch.0).unwrap())
+                    .unwrap();
+                let server = MBSMServer::with_resolver(ch.0, &self.fs, resolver(ch.0, space));
+                if let Some(sinks) = self.sinks.get(ch.0) {
+                    server.set_sinks(sinks.clone());
+                }
+                server.set_tracer(self.tracer.as_ref());
                 server_callback(&server);
                 let receiver = MBAsyncReceiver::new(ch.1);
+                let stop_all = stop_all.clone();
+                let error_policy = self.error_policy;
                 Box::pin(async move {
                     receiver.reset().await;
                     println!("[{}(server)] started!", ch.0);
                     receiver.check_version(ch.0);
-                    loop {
-                        let req = receiver.recv_req(ch.0).await;
-                        match server.do_cmd(&req).await {
-                            Ok(r) => receiver.send_resp(r, ch.0).await,
-                            Err(MBAsyncRPCError::Stop(server_name, code)) => {
-                                break (server_name, code)
-                            }
-                            Err(MBAsyncRPCError::Illegal(action)) => {
-                                panic!("[{}(server)] Illegal cmd {:?}", ch.0, action)
-                            }
-                            _ => {}
-                        }
-                    }
+                    server.serve(&receiver, error_policy, stop_all).await
                 })
             })
             .collect::<Vec<_>>()
@@ -196,6 +366,7 @@ impl<SM: MBShareMem> MBChannelShareMemSys<SM> {
 pub struct MBChannelShareMemBuilder<SM: MBShareMem> {
     docs: Vec<Yaml>,
     sys: MBChannelShareMemSys<SM>,
+    file_sinks: HashMap<String, Arc<MBFileSink>>,
 }
 
 impl<SM: MBShareMem> MBChannelShareMemBuilder<SM> {
@@ -216,9 +387,154 @@ impl<SM: MBShareMem> MBChannelShareMemBuilder<SM> {
         Ok(MBChannelShareMemBuilder {
             docs: YamlLoader::load_from_str(s).map_err(|e| e.to_string())?,
             sys: MBChannelShareMemSys::<SM>::new(space_map),
+            file_sinks: HashMap::new(),
         })
     }
 
+    //a list of images replaces loading elf, which then only locates the mailbox
+    //load:
+    //  - file: fw.elf
+    //    elf_addr: virtual #optional, physical by default
+    //    sections: [.text*, .data] #optional, load by section
+    //    exclude_sections: [.bss] #optional, load by section
+    //  - file: boot.hex #format auto detected
+    //  - file: data.bin
+    //    format: bin
+    //    addr: 0x80000000
+    //  - file: rom.memh
+    //    format: readmemh
+    //    addr: 0x0
+    //    word_size: 4
+    fn get_images(key: &str, load: &Yaml) -> Result<Vec<(String, MBLoadOpts)>, String> {
+        let images = if let Yaml::Array(images) = load {
+            images
+        } else {
+            return Ok(vec![]);
+        };
+        images
+            .iter()
+            .map(|image| {
+                let file = image["file"]
+                    .as_str()
+                    .ok_or(format!("{:?}: file of load image is required!", key))?;
+                let err_handler = |e: String| format!("{:?}: {}", key, e);
+                let format = image["format"]
+                    .as_str()
+                    .map(MBImageFormat::parse)
+                    .transpose()
+                    .map_err(err_handler)?;
+                let elf_addr = image["elf_addr"]
+                    .as_str()
+                    .map_or(Ok(MBElfAddr::default()), MBElfAddr::parse)
+                    .map_err(err_handler)?;
+                let get_sections = |field: &str| match &image[field] {
+                    Yaml::BadValue => Ok(vec![]),
+                    Yaml::Array(a) => a
+                        .iter()
+                        .map(|s| s.as_str().map(|s| s.to_string()))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(err_handler(format!("{} should be list of string!", field))),
+                    _ => Err(err_handler(format!("{} should be list of string!", field))),
+                };
+                Ok((
+                    file.to_string(),
+                    MBLoadOpts {
+                        format,
+                        addr: image["addr"].as_i64().map(|a| a as MBPtrT),
+                        word_size: image["word_size"].as_i64().map(|w| w as usize),
+                        elf_addr,
+                        sections: get_sections("sections")?,
+                        exclude_sections: get_sections("exclude_sections")?,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    //output of PRINT and CPRINT, stdout by default
+    //output:
+    //  - stdout
+    //  - stdout:
+    //    color: true
+    //  - file: core0.log #without prefix by default
+    //  - file: all.log #one file shared by channels, flags left out follow the channel configured first
+    //    prefix: true
+    //    timestamp: true
+    fn get_sinks(
+        file_sinks: &mut HashMap<String, Arc<MBFileSink>>,
+        key: &str,
+        output: &Yaml,
+    ) -> Result<Option<MBOutputSinks>, String> {
+        let outputs = match output {
+            Yaml::BadValue => return Ok(None),
+            Yaml::Array(outputs) => outputs,
+            _ => return Err(format!("{:?}: output should be array!", key)),
+        };
+        let get_flag = |o: &Yaml, field: &str| match &o[field] {
+            Yaml::BadValue => Ok(None),
+            f => f
+                .as_bool()
+                .map(Some)
+                .ok_or(format!("{:?}: output {} should be bool!", key, field)),
+        };
+        let mut sinks: MBOutputSinks = vec![];
+        for o in outputs.iter() {
+            let kind = match o {
+                Yaml::String(kind) => kind.as_str(),
+                Yaml::Hash(h) => h
+                    .front()
+                    .and_then(|(k, _)| k.as_str())
+                    .ok_or(format!("{:?}: invalid output {:?}!", key, o))?,
+                _ => return Err(format!("{:?}: invalid output {:?}!", key, o)),
+            };
+            match kind {
+                "stdout" => sinks.push(Arc::new(MBStdoutSink::new(MBSinkFormat {
+                    prefix: get_flag(o, "prefix")?.unwrap_or(true),
+                    timestamp: get_flag(o, "timestamp")?.unwrap_or(false),
+                    color: get_flag(o, "color")?.unwrap_or(false),
+                }))),
+                "file" => {
+                    let path = o["file"]
+                        .as_str()
+                        .ok_or(format!("{:?}: output file should be string!", key))?;
+                    let (prefix, timestamp, color) = (
+                        get_flag(o, "prefix")?,
+                        get_flag(o, "timestamp")?,
+                        get_flag(o, "color")?,
+                    );
+                    let sink = if let Some(sink) = file_sinks.get(path) {
+                        let f = sink.format();
+                        if prefix.is_some_and(|p| p != f.prefix)
+                            || timestamp.is_some_and(|t| t != f.timestamp)
+                            || color.is_some_and(|c| c != f.color)
+                        {
+                            return Err(format!(
+                                "{:?}: output file {} is shared with a different format {:?}!",
+                                key, path, f
+                            ));
+                        }
+                        sink.clone()
+                    } else {
+                        let format = MBSinkFormat {
+                            prefix: prefix.unwrap_or(false),
+                            timestamp: timestamp.unwrap_or(false),
+                            color: color.unwrap_or(false),
+                        };
+                        let sink = Arc::new(
+                            MBFileSink::new(path, format)
+                                .map_err(|e| format!("{:?}: {}", key, e))?,
+                        );
+                        file_sinks.insert(path.to_string(), sink.clone());
+                        sink
+                    };
+                    sinks.push(sink)
+                }
+                _ => return Err(format!("{:?}: unknown output {:?}!", key, kind)),
+            }
+        }
+        Ok(Some(sinks))
+    }
+
     pub fn cfg_channels(mut self) -> Result<MBChannelShareMemBuilder<SM>, String> {
         if let Yaml::Hash(ref chs) = self.docs[0] {
             for (key, ch) in chs.iter() {
@@ -231,26 +547,36 @@ impl<SM: MBShareMem> MBChannelShareMemBuilder<SM> {
                 let space_k = ch["space"]
                     .as_str()
                     .ok_or(format!("{:?}: No space found!", k))?;
-                let space = self.sys.get_space(space_k).ok_or(format!(
-                    "{:?}: space {:?} not found in current map!",
-                    k, space_k
-                ))?;
+                let space = self
+                    .sys
+                    .get_space(space_k)
+                    .ok_or(format!(
+                        "{:?}: space {:?} not found in current map!",
+                        k, space_k
+                    ))?
+                    .clone();
                 let mb_id = mb_id.unwrap_or(0) as usize;
                 let server = server.as_bool().unwrap_or_else(|| {
                     server.as_str().map_or(true, |ls| {
                         shellexpand::full(ls).map_or(true, |ls| ls != "false")
                     })
                 });
-                let load = load.as_bool().unwrap_or_else(|| {
-                    load.as_str().map_or(true, |ls| {
-                        shellexpand::full(ls).map_or(true, |ls| ls != "false")
-                    })
-                });
+                let images = Self::get_images(k, load)?;
+                let load = images.is_empty()
+                    && load.as_bool().unwrap_or_else(|| {
+                        load.as_str().map_or(true, |ls| {
+                            shellexpand::full(ls).map_or(true, |ls| ls != "false")
+                        })
+                    });
+                if let Some(e) = elf {
+                    self.sys.add_symbols(space_k, e)?;
+                }
                 if server {
+                    let sinks = Self::get_sinks(&mut self.file_sinks, k, &ch["output"])?;
                     let ch = if let Some(e) = elf {
-                        MBChannelShareMem::with_elf(e, space, load, mb_id)
+                        MBChannelShareMem::with_elf(e, &space, load, mb_id)
                     } else if let Some(b) = base {
-                        MBChannelShareMem::new(b as MBPtrT, space)
+                        MBChannelShareMem::new(b as MBPtrT, &space)
                     } else {
                         return Err(format!("{:?}: Neither found elf nor base!", k));
                     };
@@ -260,6 +586,9 @@ impl<SM: MBShareMem> MBChannelShareMemBuilder<SM> {
                     self.sys
                         .ch_space_map
                         .insert(k.to_string(), space_k.to_string());
+                    if let Some(sinks) = sinks {
+                        self.sys.sinks.insert(k.to_string(), sinks);
+                    }
                 } else {
                     if let Some(e) = elf {
                         if load {
@@ -267,6 +596,12 @@ impl<SM: MBShareMem> MBChannelShareMemBuilder<SM> {
                         }
                     }
                 }
+                for (file, opts) in images.iter() {
+                    let report = space.lock().unwrap().load_image(file, opts)?;
+                    if report.format == MBImageFormat::Elf {
+                        self.sys.add_symbols(space_k, file)?;
+                    }
+                }
             }
             Ok(self)
         } else {
@@ -319,6 +654,17 @@ impl<SM: MBShareMem> MBChannelShareMemBuilder<SM> {
         Ok(self)
     }
 
+    pub fn error_policy(mut self, policy: MBErrorPolicy) -> MBChannelShareMemBuilder<SM> {
+        self.sys.error_policy = policy;
+        self
+    }
+
+    //the memory read by the requests is only recorded if SM is a MBTraceShareMem of the same tracer
+    pub fn record(mut self, tracer: &MBTracer) -> MBChannelShareMemBuilder<SM> {
+        self.sys.tracer = Some(tracer.clone());
+        self
+    }
+
     pub fn build(self) -> MBChannelShareMemSys<SM> {
         self.sys
     }
@@ -394,6 +740,17 @@ mod test {
                 base: 4096
                 size: 16384
             - global
+        core3:
+            - global_local:
+                window: global
+                base: 0x10000
+                offset: 0x1000
+                size: 0x1000
+            - global2_local:
+                window: global2
+                base: 0x20000
+                perm: r
+            - global
     ";
     #[test]
     fn sm_yaml_test() {
@@ -406,6 +763,71 @@ mod test {
         println!("space:{:?}", spaces.keys());
     }
 
+    #[test]
+    fn sm_yaml_window_test() {
+        let e = MBShareMemSpaceBuilder::<MyShareMem, MyParser>::from_str(
+            "
+            shared:
+                global:
+                    base: 0x80000000
+                    size: 0x1000
+            space:
+                core0:
+                    - global
+                    - global_local:
+                        window: global
+                        base: 0x80000800
+                        size: 0x100
+            ",
+        )
+        .unwrap()
+        .build_shared()
+        .unwrap()
+        .build_spaces()
+        .err()
+        .unwrap();
+        assert_eq!(e, "\"core0\": \"global_local [0x80000800 - 0x800008ff] is overlapped with global [0x80000000 - 0x80000fff]!\"");
+    }
+
+    #[test]
+    fn sm_yaml_window_access_test() {
+        let spaces =
+            MBShareMemSpaceBuilder::<MBDefaultShareMem, MBDefaultShareMemParser>::from_str(
+                "
+                shared:
+                    global:
+                        type: sparse
+                        base: 0x80000000
+                        size: 0x10000
+                space:
+                    core0:
+                        - global_local:
+                            window: global
+                            base: 0x10000
+                            offset: 0x1000
+                            size: 0x1000
+                        - global
+                ",
+            )
+            .unwrap()
+            .build_shared()
+            .unwrap()
+            .build_spaces()
+            .unwrap();
+        let mut space = spaces["core0"].lock().unwrap();
+        let mut data = [0u8; 4];
+        space.write_slice(0x10010, &[1u8, 2, 3, 4]);
+        space.read_slice(0x80001010, &mut data);
+        assert_eq!(data, [1, 2, 3, 4]);
+        space.write_slice(0x80001ffc, &[5u8, 6, 7, 8]);
+        space.read_slice(0x10ffc, &mut data);
+        assert_eq!(data, [5, 6, 7, 8]);
+        assert_eq!(
+            space.try_read(0x10ffe, &mut data),
+            Err(MBShareMemError::Unmapped(0x11000))
+        );
+    }
+
     #[test]
     fn ch_yaml_test() {
         let s = "
@@ -431,5 +853,206 @@ mod test {
             .unwrap()
             .build();
         assert_eq!(mbs.get_ch_space_name("core0").unwrap(), "core1");
+        //firmware with the mmu on
+        let roots = Mutex::new(vec![]);
+        let servers = mbs.serve_with_resolver(
+            |_, sm| {
+                let r = MBPageTableResolver::new(sm);
+                roots.lock().unwrap().push(r.clone());
+                r
+            },
+            |_| {},
+        );
+        assert_eq!(servers.len(), 3);
+        roots.lock().unwrap()[0].set_satp(8 << 60).unwrap();
+    }
+
+    #[test]
+    fn ch_output_test() {
+        let log = std::env::temp_dir().join("mb_ch_output_test.log");
+        let s = format!(
+            "
+            core0:
+                space: core1
+                base: 0x1000
+                output:
+                    - stdout:
+                      color: true
+                    - file: {0}
+                      prefix: true
+            core1:
+                space: core2
+                base: 0x1000
+                output:
+                    - file: {0}
+            core2:
+                space: core0
+                base: 0x1000
+            ",
+            log.display()
+        );
+        let spaces = MBShareMemSpaceBuilder::<MyShareMem, MyParser>::from_str(SM_YAML)
+            .unwrap()
+            .build_shared()
+            .unwrap()
+            .build_spaces()
+            .unwrap();
+        let mbs = MBChannelShareMemBuilder::<MBShareMemSpace<MyShareMem>>::from_str(&s, spaces)
+            .unwrap()
+            .cfg_channels()
+            .unwrap()
+            .build();
+        assert_eq!(mbs.sinks["core0"].len(), 2);
+        assert!(std::ptr::addr_eq(
+            Arc::as_ptr(&mbs.sinks["core0"][1]),
+            Arc::as_ptr(&mbs.sinks["core1"][0])
+        ));
+        assert!(!mbs.sinks.contains_key("core2"));
+        mbs.sinks["core0"][1].write("core0", "a\n");
+        mbs.sinks["core1"][0].write("core1", "b\n");
+        assert_eq!(fs::read_to_string(&log).unwrap(), "[core0] a\n[core1] b\n");
+        fs::remove_file(&log).unwrap();
+
+        //a file shared with another format
+        let spaces = MBShareMemSpaceBuilder::<MyShareMem, MyParser>::from_str(SM_YAML)
+            .unwrap()
+            .build_shared()
+            .unwrap()
+            .build_spaces()
+            .unwrap();
+        let unused = std::env::temp_dir().join("mb_ch_output_test_unused.log");
+        let s = format!(
+            "
+            core0:
+                space: core1
+                base: 0x1000
+                output:
+                    - file: {0}
+                      timestamp: true
+            core1:
+                space: core2
+                base: 0x1000
+                server: false
+                output:
+                    - file: {1}
+            core2:
+                space: core0
+                base: 0x1000
+                output:
+                    - file: {0}
+                      timestamp: false
+            ",
+            log.display(),
+            unused.display()
+        );
+        let e = MBChannelShareMemBuilder::<MBShareMemSpace<MyShareMem>>::from_str(&s, spaces)
+            .unwrap()
+            .cfg_channels()
+            .err()
+            .unwrap();
+        assert!(e.contains("is shared with a different format"), "{}", e);
+        //no sink for channels without server
+        assert!(!unused.exists());
+        fs::remove_file(&log).unwrap();
+    }
+
+    //symbols g_test_result and its alias g_result @ 0x1100 (8), g_config @ 0x1000 (4)
+    fn symbol_elf() -> Vec<u8> {
+        use crate::mb_std::mb_loader::test_elf::*;
+        let symbol = |name, info, value, size| MBTestElfSymbol {
+            name,
+            info,
+            section: 1,
+            value,
+            size,
+        };
+        mb_test_elf(
+            &[MBTestElfSection {
+                name: ".data",
+                addr: 0x1000,
+                data: Some(&[0; 0x108]),
+                size: 0x108,
+            }],
+            None,
+            //a section symbol is skipped
+            &[
+                symbol("", 3, 0, 0),
+                symbol("g_test_result", 0x11, 0x1100, 8),
+                symbol("g_result", 0x11, 0x1100, 8),
+                symbol("g_config", 0x12, 0x1000, 4),
+            ],
+        )
+    }
+
+    #[test]
+    fn ch_symbol_test() {
+        let mut space = MBShareMemSpace::<MBDefaultShareMem>::new();
+        space
+            .add_named_mem(
+                "ram",
+                &Arc::new(Mutex::new(MBDefaultShareMem::Sparse(
+                    MBSparseShareMem::new(0x1000, 0x1000),
+                ))),
+            )
+            .unwrap();
+        let elf = std::env::temp_dir().join(format!("ch_symbol_test_{}.elf", std::process::id()));
+        fs::write(&elf, symbol_elf()).unwrap();
+        let mbs = MBChannelShareMemBuilder::from_str(
+            &format!(
+                "
+                core0:
+                    space: core0
+                    elf: {}
+                    load: false
+                    server: false
+                ",
+                elf.to_str().unwrap()
+            ),
+            HashMap::from([("core0".to_string(), Arc::new(Mutex::new(space)))]),
+        )
+        .unwrap()
+        .cfg_channels()
+        .unwrap()
+        .build();
+        fs::remove_file(&elf).unwrap();
+        assert_eq!(mbs.get_symbols("core0").unwrap().len(), 3);
+        assert_eq!(mbs.get_symbol("core0", "g_config").unwrap().addr, 0x1000);
+        assert_eq!(
+            mbs.lookup_symbols("core0", 0x1104)
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            ["g_test_result", "g_result"]
+        );
+        mbs.write_symbol("core0", "g_test_result", 4, &0x5au32.to_ne_bytes())
+            .unwrap();
+        let mut data = [0u8; 4];
+        mbs.read_symbol("core0", "g_test_result", 4, &mut data)
+            .unwrap();
+        assert_eq!(u32::from_ne_bytes(data), 0x5a);
+        assert_eq!(
+            mbs.lookup_symbol("core0", 0x1107).unwrap().name,
+            "g_test_result"
+        );
+        assert!(mbs
+            .read_symbol("core0", "g_test_result", 6, &mut data)
+            .is_err());
+        assert!(mbs.read_symbol("core0", "g_config", 1, &mut data).is_err());
+        assert!(mbs.read_symbol("core0", "g_missing", 0, &mut data).is_err());
+        assert!(mbs
+            .read_symbol("core0", "g_config", MBPtrT::MAX, &mut data)
+            .is_err());
+        //errors instead of panics in read-only memories
+        mbs.get_space("core0")
+            .unwrap()
+            .lock()
+            .unwrap()
+            .set_perm("ram", MBShareMemPerm::RO)
+            .unwrap();
+        let e = mbs
+            .write_symbol("core0", "g_config", 0, &[1u8])
+            .unwrap_err();
+        assert!(e.starts_with("write symbol g_config fail!"), "{}", e);
+        mbs.read_symbol("core0", "g_config", 0, &mut data).unwrap();
     }
 }
@@ -0,0 +1,602 @@
+use super::mb_ptr_resolver::*;
+use super::mb_share_mem::*;
+use crate::mb_rpcs::*;
+use async_std::task::{Context, Poll};
+use std::cell::{Cell, RefCell};
+
+pub const fn mb_align_up(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+//a type as laid out in the device memory: pointers are MBPtrT wide and fields aligned by the device's rules.
+//reading is split so that the pointers of a structure are followed after all its fields are read,
+//e.g. a buffer pointer followed with the length field behind it
+//the device controls pointers and lengths, a cyclic list or a corrupted length must not exhaust the server
+pub const MB_DEVICE_MAX_DEPTH: usize = 64;
+pub const MB_DEVICE_MAX_ELEMENTS: usize = 1 << 20;
+
+//state of one read_device, the pointers followed and the array elements read so far
+#[derive(Debug, Default)]
+pub struct MBDeviceCtx {
+    depth: Cell<usize>,
+    elements: Cell<usize>,
+}
+
+impl MBDeviceCtx {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    //follow a pointer, f reads the structure it points to
+    pub fn nested<T>(&self, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        let depth = self.depth.get();
+        if depth >= MB_DEVICE_MAX_DEPTH {
+            return Err(format!("nested deeper than {}!", MB_DEVICE_MAX_DEPTH));
+        }
+        self.depth.set(depth + 1);
+        let ret = f();
+        self.depth.set(depth);
+        ret
+    }
+    pub fn take_elements(&self, len: usize) -> Result<(), String> {
+        let elements = self.elements.get().saturating_add(len);
+        if elements > MB_DEVICE_MAX_ELEMENTS {
+            return Err(format!("more than {} elements!", MB_DEVICE_MAX_ELEMENTS));
+        }
+        self.elements.set(elements);
+        Ok(())
+    }
+}
+
+//length field of a MBDeviceArray, negative lengths are rejected
+pub fn mb_device_len<T: TryInto<usize> + Copy + std::fmt::Display>(
+    len: T,
+) -> Result<usize, String> {
+    len.try_into()
+        .map_err(|_| format!("invalid length {}!", len))
+}
+
+pub trait MBDeviceType: Sized {
+    //value as stored in the device memory, MBPtrT for pointers
+    type Raw;
+    const SIZE: usize;
+    const ALIGN: usize;
+    fn mb_read_raw<R: MBPtrResolver>(
+        r: &R,
+        ptr: MBPtrT,
+        ctx: &MBDeviceCtx,
+    ) -> Result<Self::Raw, String>;
+    //follow the pointers in raw, len is the number of elements MBDeviceArray points to
+    fn mb_deref<R: MBPtrResolver>(
+        r: &R,
+        raw: Self::Raw,
+        len: usize,
+        ctx: &MBDeviceCtx,
+    ) -> Result<Self, String>;
+    fn mb_read<R: MBPtrResolver>(r: &R, ptr: MBPtrT, ctx: &MBDeviceCtx) -> Result<Self, String> {
+        let raw = Self::mb_read_raw(r, ptr, ctx)?;
+        Self::mb_deref(r, raw, 0, ctx)
+    }
+}
+
+pub(crate) fn mb_device_read_bytes<R: MBPtrResolver>(
+    r: &R,
+    ptr: MBPtrT,
+    buf: &mut [u8],
+) -> Result<(), String> {
+    r.check_access(ptr, buf.len(), MBMemAccess::Read)
+        .map_err(|e| e.to_string())?;
+    let actual = r.try_read_slice(ptr as *const u8, buf);
+    if actual != buf.len() {
+        return Err(MBShareMemError::Partial {
+            addr: ptr,
+            expected: buf.len(),
+            actual,
+        }
+        .to_string());
+    }
+    Ok(())
+}
+
+//resolver loading every range before reading it, remembers if a load is pending
+struct MBDeviceLoader<'a, 'b, 'c, R: MBPtrResolver> {
+    r: &'a R,
+    cx: RefCell<&'b mut Context<'c>>,
+    pending: Cell<bool>,
+}
+
+impl<R: MBPtrResolver> MBDeviceLoader<'_, '_, '_, R> {
+    fn loaded(&self, poll: Poll<()>) -> bool {
+        if poll.is_pending() {
+            self.pending.set(true);
+        }
+        poll.is_ready()
+    }
+}
+
+impl<R: MBPtrResolver> MBPtrResolver for MBDeviceLoader<'_, '_, '_, R> {
+    type READER = R::READER;
+    type WRITER = R::WRITER;
+    fn reader<T: Sized>(&self, ptr: *const T) -> Self::READER {
+        self.r.reader(ptr)
+    }
+    fn writer<T: Sized>(&self, ptr: *mut T) -> Self::WRITER {
+        self.r.writer(ptr)
+    }
+    fn check_access(
+        &self,
+        ptr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        self.r.check_access(ptr, len, access)
+    }
+    fn try_read_slice<T: Sized + Copy>(&self, ptr: *const T, data: &mut [T]) -> usize {
+        let len = std::mem::size_of_val(data);
+        if !self.loaded(
+            self.r
+                .poll_load(ptr as MBPtrT, len, &mut self.cx.borrow_mut()),
+        ) {
+            return 0;
+        }
+        self.r.try_read_slice(ptr, data)
+    }
+    fn read_c_str_with(&self, ptr: *const u8, limits: &MBStrLimits) -> Result<MBStr, String> {
+        let poll = self
+            .r
+            .poll_load_c_str(ptr as MBPtrT, limits, &mut self.cx.borrow_mut());
+        if !self.loaded(poll) {
+            return Err(format!("string @ {:#x} is not loaded!", ptr as MBPtrT));
+        }
+        self.r.read_c_str_with(ptr, limits)
+    }
+}
+
+//read again from the start after the pending loads complete, loaded ranges are ready at once
+pub(crate) fn mb_poll_read_device<T: MBDeviceType, R: MBPtrResolver>(
+    r: &R,
+    ptr: MBPtrT,
+    cx: &mut Context,
+) -> Poll<Result<T, String>> {
+    let loader = MBDeviceLoader {
+        r,
+        cx: RefCell::new(cx),
+        pending: Cell::new(false),
+    };
+    let ret = T::mb_read(&loader, ptr, &MBDeviceCtx::new());
+    if loader.pending.get() {
+        Poll::Pending
+    } else {
+        Poll::Ready(ret)
+    }
+}
+
+macro_rules! impl_device_primitive {
+    ($($t:ty),+) => {
+        $(
+            impl MBDeviceType for $t {
+                type Raw = $t;
+                const SIZE: usize = std::mem::size_of::<$t>();
+                const ALIGN: usize = std::mem::size_of::<$t>();
+                fn mb_read_raw<R: MBPtrResolver>(
+                    r: &R,
+                    ptr: MBPtrT,
+                    _ctx: &MBDeviceCtx,
+                ) -> Result<$t, String> {
+                    let mut bytes = [0u8; std::mem::size_of::<$t>()];
+                    mb_device_read_bytes(r, ptr, &mut bytes)?;
+                    Ok(<$t>::from_ne_bytes(bytes))
+                }
+                fn mb_deref<R: MBPtrResolver>(
+                    _r: &R,
+                    raw: $t,
+                    _len: usize,
+                    _ctx: &MBDeviceCtx,
+                ) -> Result<$t, String> {
+                    Ok(raw)
+                }
+            }
+        )+
+    };
+}
+impl_device_primitive!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+#[cfg(feature = "ptrhost")]
+impl_device_primitive!(usize, isize);
+
+impl MBDeviceType for bool {
+    type Raw = u8;
+    const SIZE: usize = 1;
+    const ALIGN: usize = 1;
+    fn mb_read_raw<R: MBPtrResolver>(r: &R, ptr: MBPtrT, ctx: &MBDeviceCtx) -> Result<u8, String> {
+        u8::mb_read_raw(r, ptr, ctx)
+    }
+    fn mb_deref<R: MBPtrResolver>(
+        _r: &R,
+        raw: u8,
+        _len: usize,
+        _ctx: &MBDeviceCtx,
+    ) -> Result<bool, String> {
+        Ok(raw != 0)
+    }
+}
+
+impl<T: MBDeviceType, const N: usize> MBDeviceType for [T; N] {
+    type Raw = Vec<T::Raw>;
+    const SIZE: usize = mb_align_up(T::SIZE, T::ALIGN) * N;
+    const ALIGN: usize = T::ALIGN;
+    fn mb_read_raw<R: MBPtrResolver>(
+        r: &R,
+        ptr: MBPtrT,
+        ctx: &MBDeviceCtx,
+    ) -> Result<Vec<T::Raw>, String> {
+        let stride = mb_align_up(T::SIZE, T::ALIGN);
+        (0..N)
+            .map(|i| T::mb_read_raw(r, ptr + (i * stride) as MBPtrT, ctx))
+            .collect()
+    }
+    fn mb_deref<R: MBPtrResolver>(
+        r: &R,
+        raw: Vec<T::Raw>,
+        _len: usize,
+        ctx: &MBDeviceCtx,
+    ) -> Result<[T; N], String> {
+        let v = raw
+            .into_iter()
+            .map(|e| T::mb_deref(r, e, 0, ctx))
+            .collect::<Result<Vec<T>, String>>()?;
+        Ok(v.try_into().ok().unwrap())
+    }
+}
+
+//boxed T, for structures pointing to their own type, e.g. MBDevicePtr<Box<Node>>
+impl<T: MBDeviceType> MBDeviceType for Box<T> {
+    type Raw = T::Raw;
+    const SIZE: usize = T::SIZE;
+    const ALIGN: usize = T::ALIGN;
+    fn mb_read_raw<R: MBPtrResolver>(
+        r: &R,
+        ptr: MBPtrT,
+        ctx: &MBDeviceCtx,
+    ) -> Result<T::Raw, String> {
+        T::mb_read_raw(r, ptr, ctx)
+    }
+    fn mb_deref<R: MBPtrResolver>(
+        r: &R,
+        raw: T::Raw,
+        len: usize,
+        ctx: &MBDeviceCtx,
+    ) -> Result<Self, String> {
+        T::mb_deref(r, raw, len, ctx).map(Box::new)
+    }
+}
+
+//T *, None if NULL
+#[derive(Debug, Clone, PartialEq)]
+pub struct MBDevicePtr<T>(pub Option<T>);
+
+impl<T: MBDeviceType> MBDeviceType for MBDevicePtr<T> {
+    type Raw = MBPtrT;
+    const SIZE: usize = std::mem::size_of::<MBPtrT>();
+    const ALIGN: usize = std::mem::size_of::<MBPtrT>();
+    fn mb_read_raw<R: MBPtrResolver>(
+        r: &R,
+        ptr: MBPtrT,
+        ctx: &MBDeviceCtx,
+    ) -> Result<MBPtrT, String> {
+        MBPtrT::mb_read_raw(r, ptr, ctx)
+    }
+    fn mb_deref<R: MBPtrResolver>(
+        r: &R,
+        raw: MBPtrT,
+        _len: usize,
+        ctx: &MBDeviceCtx,
+    ) -> Result<Self, String> {
+        if raw == 0 {
+            Ok(MBDevicePtr(None))
+        } else {
+            ctx.nested(|| T::mb_read(r, raw, ctx))
+                .map(|v| MBDevicePtr(Some(v)))
+        }
+    }
+}
+
+//const char *, None if NULL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBDeviceCStr(pub Option<String>);
+
+impl MBDeviceType for MBDeviceCStr {
+    type Raw = MBPtrT;
+    const SIZE: usize = std::mem::size_of::<MBPtrT>();
+    const ALIGN: usize = std::mem::size_of::<MBPtrT>();
+    fn mb_read_raw<R: MBPtrResolver>(
+        r: &R,
+        ptr: MBPtrT,
+        ctx: &MBDeviceCtx,
+    ) -> Result<MBPtrT, String> {
+        MBPtrT::mb_read_raw(r, ptr, ctx)
+    }
+    fn mb_deref<R: MBPtrResolver>(
+        r: &R,
+        raw: MBPtrT,
+        _len: usize,
+        _ctx: &MBDeviceCtx,
+    ) -> Result<Self, String> {
+        if raw == 0 {
+            Ok(MBDeviceCStr(None))
+        } else {
+            r.read_c_str(raw as *const u8)
+                .map(|s| MBDeviceCStr(Some(s)))
+        }
+    }
+}
+
+//T * pointing to the number of elements in another field of the structure, see mb_device_struct
+#[derive(Debug, Clone, PartialEq)]
+pub struct MBDeviceArray<T>(pub Vec<T>);
+
+impl<T: MBDeviceType> MBDeviceType for MBDeviceArray<T> {
+    type Raw = MBPtrT;
+    const SIZE: usize = std::mem::size_of::<MBPtrT>();
+    const ALIGN: usize = std::mem::size_of::<MBPtrT>();
+    fn mb_read_raw<R: MBPtrResolver>(
+        r: &R,
+        ptr: MBPtrT,
+        ctx: &MBDeviceCtx,
+    ) -> Result<MBPtrT, String> {
+        MBPtrT::mb_read_raw(r, ptr, ctx)
+    }
+    fn mb_deref<R: MBPtrResolver>(
+        r: &R,
+        raw: MBPtrT,
+        len: usize,
+        ctx: &MBDeviceCtx,
+    ) -> Result<Self, String> {
+        if raw == 0 && len != 0 {
+            return Err(format!("NULL array of {} elements!", len));
+        }
+        ctx.take_elements(len)?;
+        let stride = mb_align_up(T::SIZE, T::ALIGN);
+        ctx.nested(|| {
+            (0..len)
+                .map(|i| T::mb_read(r, raw + (i * stride) as MBPtrT, ctx))
+                .collect::<Result<Vec<T>, String>>()
+        })
+        .map(MBDeviceArray)
+    }
+}
+
+//declare a struct laid out as the repr(C) struct of the device and implement MBDeviceType for it.
+//fields are MBDeviceType, a MBDeviceArray field names the integer field holding its length:
+//mb_device_struct! {
+//    #[derive(Debug)]
+//    pub struct MyArgs {
+//        pub name: MBDeviceCStr,
+//        pub buf: MBDeviceArray<u8> = len,
+//        pub len: u32,
+//    }
+//}
+#[macro_export]
+macro_rules! mb_device_struct {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$fattr:meta])* $fvis:vis $field:ident : $fty:ty $(= $len:ident)?),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$fattr])* $fvis $field: $fty),*
+        }
+        impl $crate::mb_std::MBDeviceType for $name {
+            type Raw = $name;
+            const SIZE: usize = {
+                let mut offset = 0;
+                $(
+                    offset = $crate::mb_std::mb_align_up(
+                        offset,
+                        <$fty as $crate::mb_std::MBDeviceType>::ALIGN,
+                    ) + <$fty as $crate::mb_std::MBDeviceType>::SIZE;
+                )*
+                $crate::mb_std::mb_align_up(offset, <Self as $crate::mb_std::MBDeviceType>::ALIGN)
+            };
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(
+                    if <$fty as $crate::mb_std::MBDeviceType>::ALIGN > align {
+                        align = <$fty as $crate::mb_std::MBDeviceType>::ALIGN;
+                    }
+                )*
+                align
+            };
+            #[allow(unused_assignments)]
+            fn mb_read_raw<R: $crate::mb_std::MBPtrResolver>(
+                r: &R,
+                ptr: $crate::mb_rpcs::MBPtrT,
+                ctx: &$crate::mb_std::MBDeviceCtx,
+            ) -> Result<$name, String> {
+                let mut offset = 0;
+                $(
+                    offset = $crate::mb_std::mb_align_up(
+                        offset,
+                        <$fty as $crate::mb_std::MBDeviceType>::ALIGN,
+                    );
+                    let $field = <$fty as $crate::mb_std::MBDeviceType>::mb_read_raw(
+                        r,
+                        ptr + offset as $crate::mb_rpcs::MBPtrT,
+                        ctx,
+                    )?;
+                    offset += <$fty as $crate::mb_std::MBDeviceType>::SIZE;
+                )*
+                Ok($name {
+                    $(
+                        $field: $crate::mb_device_struct!(@len $($len)?)
+                            .and_then(|len| {
+                                <$fty as $crate::mb_std::MBDeviceType>::mb_deref(r, $field, len, ctx)
+                            })
+                            .map_err(|e| format!("{}.{}: {}", stringify!($name), stringify!($field), e))?,
+                    )*
+                })
+            }
+            fn mb_deref<R: $crate::mb_std::MBPtrResolver>(
+                _r: &R,
+                raw: $name,
+                _len: usize,
+                _ctx: &$crate::mb_std::MBDeviceCtx,
+            ) -> Result<$name, String> {
+                Ok(raw)
+            }
+        }
+    };
+    (@len $len:ident) => {
+        $crate::mb_std::mb_device_len($len)
+    };
+    (@len) => {
+        Ok::<usize, String>(0)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    mb_device_struct! {
+        #[derive(Debug, PartialEq)]
+        struct MBTestInner {
+            flag: bool,
+            value: u64,
+        }
+    }
+
+    mb_device_struct! {
+        #[derive(Debug, PartialEq)]
+        struct MBTestArgs {
+            id: u16,
+            name: MBDeviceCStr,
+            buf: MBDeviceArray<u16> = len,
+            len: u32,
+            inner: MBDevicePtr<MBTestInner>,
+            empty: MBDevicePtr<MBTestInner>,
+            pair: [MBTestInner; 2],
+        }
+    }
+
+    mb_device_struct! {
+        #[derive(Debug, PartialEq)]
+        struct MBTestNode {
+            next: MBDevicePtr<Box<MBTestNode>>,
+            buf: MBDeviceArray<u8> = len,
+            len: i32,
+        }
+    }
+
+    #[test]
+    fn mb_device_struct_test() {
+        let p = std::mem::size_of::<MBPtrT>();
+        assert_eq!(MBTestInner::SIZE, 16);
+        assert_eq!(MBTestArgs::ALIGN, 8);
+        assert_eq!(MBTestArgs::SIZE, mb_align_up(3 * p + 4, p) + 2 * p + 32);
+        let mut sm = MBSparseShareMem::new(0x1000, 0x1000);
+        let args = 0x1000 as MBPtrT;
+        sm.write_sized(args, &7u16);
+        sm.write_sized(args + p as MBPtrT, &(0x1100 as MBPtrT));
+        sm.write(0x1100, b"hello\0");
+        sm.write_sized(args + 2 * p as MBPtrT, &(0x1200 as MBPtrT));
+        sm.write_slice(0x1200, &[1u16, 2, 3]);
+        sm.write_sized(args + 3 * p as MBPtrT, &3u32);
+        let inner = args + mb_align_up(3 * p + 4, p) as MBPtrT;
+        sm.write_sized(inner, &(0x1300 as MBPtrT));
+        sm.write_sized(0x1300, &1u8);
+        sm.write_sized(0x1308, &0x1234u64);
+        let pair = mb_align_up(inner as usize + 2 * p, 8) as MBPtrT;
+        sm.write_sized(pair + 8, &5u64);
+        sm.write_sized(pair + 16, &1u8);
+        let expected = MBTestArgs {
+            id: 7,
+            name: MBDeviceCStr(Some("hello".to_string())),
+            buf: MBDeviceArray(vec![1, 2, 3]),
+            len: 3,
+            inner: MBDevicePtr(Some(MBTestInner {
+                flag: true,
+                value: 0x1234,
+            })),
+            empty: MBDevicePtr(None),
+            pair: [
+                MBTestInner {
+                    flag: false,
+                    value: 5,
+                },
+                MBTestInner {
+                    flag: true,
+                    value: 0,
+                },
+            ],
+        };
+        let sm = Arc::new(Mutex::new(sm));
+        let r = MBSMPtrResolver::new(&sm);
+        assert_eq!(r.read_device::<MBTestArgs>(args).as_ref(), Ok(&expected));
+        assert_eq!(
+            r.read_device::<MBTestArgs>(0x1ff0),
+            Err(MBShareMemError::Partial {
+                addr: 0x2000,
+                expected: p,
+                actual: 0
+            }
+            .to_string())
+        );
+        drop(r);
+        //ranges of a staged memory are loaded before they are read
+        let sm = Arc::try_unwrap(sm).ok().unwrap().into_inner().unwrap();
+        let mem = Arc::new(Mutex::new(MBLatencyShareMem::new(sm, 2)));
+        let staged = MBSMPtrResolver::new(&Arc::new(Mutex::new(MBStagedShareMem::new(&mem))));
+        assert!(staged.read_device::<MBTestArgs>(args).is_err());
+        assert_eq!(
+            async_std::task::block_on(futures::future::poll_fn(|cx| {
+                staged.poll_read_device::<MBTestArgs>(args, cx)
+            })),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn mb_device_limits_test() {
+        let p = std::mem::size_of::<MBPtrT>() as MBPtrT;
+        let mut sm = MBSparseShareMem::new(0x1000, 0x1000);
+        //a list pointing back to itself
+        sm.write_sized(0x1000, &(0x1000 as MBPtrT));
+        let sm = Arc::new(Mutex::new(sm));
+        let r = MBSMPtrResolver::new(&sm);
+        assert!(r
+            .read_device::<MBTestNode>(0x1000)
+            .unwrap_err()
+            .ends_with(&format!("nested deeper than {}!", MB_DEVICE_MAX_DEPTH)));
+        sm.lock().unwrap().write_sized(0x1000, &(0 as MBPtrT));
+        sm.lock()
+            .unwrap()
+            .write_sized(0x1000 + p, &(0x1100 as MBPtrT));
+        sm.lock().unwrap().write_sized(0x1000 + 2 * p, &-1i32);
+        assert_eq!(
+            r.read_device::<MBTestNode>(0x1000),
+            Err("MBTestNode.buf: invalid length -1!".to_string())
+        );
+        sm.lock()
+            .unwrap()
+            .write_sized(0x1000 + 2 * p, &(MB_DEVICE_MAX_ELEMENTS as i32 + 1));
+        assert_eq!(
+            r.read_device::<MBTestNode>(0x1000),
+            Err(format!(
+                "MBTestNode.buf: more than {} elements!",
+                MB_DEVICE_MAX_ELEMENTS
+            ))
+        );
+        sm.lock().unwrap().write_sized(0x1000 + 2 * p, &2i32);
+        sm.lock().unwrap().write(0x1100, &[1, 2]);
+        assert_eq!(
+            r.read_device::<MBTestNode>(0x1000),
+            Ok(MBTestNode {
+                next: MBDevicePtr(None),
+                buf: MBDeviceArray(vec![1, 2]),
+                len: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,748 @@
+use super::mb_share_mem::*;
+use crate::mb_rpcs::*;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use xmas_elf::{program, sections, ElfFile};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MBImageFormat {
+    Elf,
+    IHex,
+    SRec,
+    Bin,
+    ReadMemH,
+}
+
+impl MBImageFormat {
+    pub fn parse(s: &str) -> Result<MBImageFormat, String> {
+        match s.to_lowercase().as_str() {
+            "elf" => Ok(MBImageFormat::Elf),
+            "ihex" => Ok(MBImageFormat::IHex),
+            "srec" | "s19" | "s28" | "s37" | "mot" => Ok(MBImageFormat::SRec),
+            "bin" | "raw" => Ok(MBImageFormat::Bin),
+            "readmemh" | "memh" => Ok(MBImageFormat::ReadMemH),
+            _ => Err(format!(
+                "unknown image format {:?}, should be elf, ihex, srec, bin or readmemh!",
+                s
+            )),
+        }
+    }
+
+    pub fn detect(file: &str, content: &[u8]) -> MBImageFormat {
+        if content.starts_with(b"\x7fELF") {
+            return MBImageFormat::Elf;
+        }
+        if Path::new(file).extension().and_then(|e| e.to_str()) == Some("bin") {
+            return MBImageFormat::Bin;
+        }
+        if let Ok(text) = std::str::from_utf8(content) {
+            let mut chars = text.trim_start().chars();
+            match (chars.next(), chars.next()) {
+                (Some(':'), _) => return MBImageFormat::IHex,
+                (Some('S'), Some(c)) if c.is_ascii_digit() => return MBImageFormat::SRec,
+                _ => {}
+            }
+            if strip_memh_comments(text)
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() || c.is_whitespace() || "@_xXzZ".contains(c))
+            {
+                return MBImageFormat::ReadMemH;
+            }
+        }
+        MBImageFormat::Bin
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            MBImageFormat::Elf => "elf",
+            MBImageFormat::IHex => "ihex",
+            MBImageFormat::SRec => "srec",
+            MBImageFormat::Bin => "bin",
+            MBImageFormat::ReadMemH => "readmemh",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MBElfAddr {
+    #[default]
+    Physical,
+    Virtual,
+}
+
+impl MBElfAddr {
+    pub fn parse(s: &str) -> Result<MBElfAddr, String> {
+        match s.to_lowercase().as_str() {
+            "physical" | "lma" => Ok(MBElfAddr::Physical),
+            "virtual" | "vma" => Ok(MBElfAddr::Virtual),
+            _ => Err(format!(
+                "unknown elf address {:?}, should be physical or virtual!",
+                s
+            )),
+        }
+    }
+}
+
+//format: auto detected if None
+//addr: load address of bin, base address of readmemh, offset added to ihex and srec addresses, ignored by elf
+//word_size: bytes per word of readmemh, inferred from the first word if None
+//elf_addr: load elf at physical or virtual addresses
+//sections/exclude_sections: load allocated elf sections instead of segments if any is given,
+//  patterns ending with '*' match by prefix, empty sections means all
+#[derive(Debug, Clone, Default)]
+pub struct MBLoadOpts {
+    pub format: Option<MBImageFormat>,
+    pub addr: Option<MBPtrT>,
+    pub word_size: Option<usize>,
+    pub elf_addr: MBElfAddr,
+    pub sections: Vec<String>,
+    pub exclude_sections: Vec<String>,
+}
+
+impl MBLoadOpts {
+    fn by_section(&self) -> bool {
+        !self.sections.is_empty() || !self.exclude_sections.is_empty()
+    }
+    fn section_selected(&self, name: &str) -> bool {
+        let matched = |pattern: &String| {
+            pattern
+                .strip_suffix('*')
+                .map_or(pattern == name, |p| name.starts_with(p))
+        };
+        (self.sections.is_empty() || self.sections.iter().any(matched))
+            && !self.exclude_sections.iter().any(matched)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBLoadRegion {
+    pub name: String,
+    pub addr: MBPtrT,
+    pub size: usize,
+    pub zeroed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MBLoadReport {
+    pub file: String,
+    pub format: MBImageFormat,
+    pub regions: Vec<MBLoadRegion>,
+}
+
+impl MBLoadReport {
+    fn new(file: &str, format: MBImageFormat) -> MBLoadReport {
+        MBLoadReport {
+            file: file.to_string(),
+            format,
+            regions: vec![],
+        }
+    }
+    pub fn size(&self) -> usize {
+        self.regions.iter().map(|r| r.size).sum()
+    }
+    fn record(&mut self, name: &str, addr: MBPtrT, size: usize, zeroed: bool) {
+        println!(
+            "{} {} {} {}({}) @ {:#x} - {:#x}!",
+            if zeroed { "zero" } else { "load" },
+            self.format.name(),
+            self.file,
+            name,
+            size,
+            addr,
+            addr.wrapping_add(size as MBPtrT)
+        );
+        self.regions.push(MBLoadRegion {
+            name: name.to_string(),
+            addr,
+            size,
+            zeroed,
+        })
+    }
+    fn write<SM: MBShareMem + ?Sized>(
+        &mut self,
+        sm: &mut SM,
+        name: &str,
+        addr: MBPtrT,
+        data: &[u8],
+    ) -> Result<(), String> {
+        self.record(name, addr, data.len(), false);
+        let len = sm.write(addr, data);
+        if len != data.len() {
+            return Err(format!(
+                "only {} of {} bytes written @ {:#x}!",
+                len,
+                data.len(),
+                addr
+            ));
+        }
+        Ok(())
+    }
+    fn zero<SM: MBShareMem + ?Sized>(
+        &mut self,
+        sm: &mut SM,
+        name: &str,
+        addr: MBPtrT,
+        size: usize,
+    ) -> Result<(), String> {
+        self.record(name, addr, size, true);
+        let zeros = vec![0u8; std::cmp::min(size, 4096)];
+        let mut pos = 0;
+        while pos < size {
+            let l = std::cmp::min(zeros.len(), size - pos);
+            let a = addr.wrapping_add(pos as MBPtrT);
+            if sm.write(a, &zeros[..l]) != l {
+                return Err(format!("zero {} bytes @ {:#x} fail!", l, a));
+            }
+            pos += l;
+        }
+        Ok(())
+    }
+}
+
+impl Display for MBLoadReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {}: {} regions, {} bytes",
+            self.format.name(),
+            self.file,
+            self.regions.len(),
+            self.size()
+        )?;
+        for r in self.regions.iter() {
+            writeln!(
+                f,
+                "  {:<16} {:#018x} - {:#018x} {:>10}{}",
+                r.name,
+                r.addr,
+                r.addr.wrapping_add(r.size as MBPtrT),
+                r.size,
+                if r.zeroed { " zeroed" } else { "" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+//coalesce contiguous records into one write
+struct MBImageSink<'a, SM: MBShareMem + ?Sized> {
+    sm: &'a mut SM,
+    report: MBLoadReport,
+    addr: MBPtrT,
+    buf: Vec<u8>,
+}
+
+impl<'a, SM: MBShareMem + ?Sized> MBImageSink<'a, SM> {
+    fn new(sm: &'a mut SM, format: MBImageFormat, file: &str) -> MBImageSink<'a, SM> {
+        MBImageSink {
+            sm,
+            report: MBLoadReport::new(file, format),
+            addr: 0,
+            buf: vec![],
+        }
+    }
+    fn push(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), String> {
+        if self.buf.is_empty() || self.addr.wrapping_add(self.buf.len() as MBPtrT) != addr {
+            self.flush()?;
+            self.addr = addr;
+        }
+        self.buf.extend_from_slice(data);
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<(), String> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.report.write(self.sm, "block", self.addr, &self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+pub fn mb_load_elf<SM: MBShareMem + ?Sized>(
+    sm: &mut SM,
+    elf: &ElfFile,
+    file: &str,
+    opts: &MBLoadOpts,
+) -> Result<MBLoadReport, String> {
+    let mut report = MBLoadReport::new(file, MBImageFormat::Elf);
+    let segments = elf
+        .program_iter()
+        .filter(|p| p.get_type() == Ok(program::Type::Load))
+        .collect::<Vec<_>>();
+    if !opts.by_section() {
+        for p in segments.iter() {
+            let addr = match opts.elf_addr {
+                MBElfAddr::Physical => p.physical_addr(),
+                MBElfAddr::Virtual => p.virtual_addr(),
+            } as MBPtrT;
+            let mut file_size = 0;
+            if let program::SegmentData::Undefined(d) = p.get_data(elf)? {
+                report.write(sm, "segment", addr, d)?;
+                file_size = d.len();
+            }
+            if p.mem_size() as usize > file_size {
+                report.zero(
+                    sm,
+                    "segment",
+                    addr.wrapping_add(file_size as MBPtrT),
+                    p.mem_size() as usize - file_size,
+                )?;
+            }
+        }
+    } else {
+        for s in elf.section_iter() {
+            if s.flags() & sections::SHF_ALLOC == 0 || s.size() == 0 {
+                continue;
+            }
+            let name = s.get_name(elf)?;
+            if !opts.section_selected(name) {
+                continue;
+            }
+            let vaddr = s.address();
+            let addr = match opts.elf_addr {
+                MBElfAddr::Physical => segments
+                    .iter()
+                    .find(|p| vaddr >= p.virtual_addr() && vaddr < p.virtual_addr() + p.mem_size())
+                    .map_or(vaddr, |p| vaddr - p.virtual_addr() + p.physical_addr()),
+                MBElfAddr::Virtual => vaddr,
+            } as MBPtrT;
+            if s.get_type() == Ok(sections::ShType::NoBits) {
+                report.zero(sm, name, addr, s.size() as usize)?;
+            } else {
+                report.write(sm, name, addr, s.raw_data(elf))?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn hex_bytes(s: &str) -> Result<Vec<u8>, String> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(format!("{:?} is not valid hex bytes!", s));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|e| format!("{:?}: {}", s, e)))
+        .collect()
+}
+
+fn load_ihex<SM: MBShareMem + ?Sized>(
+    sink: &mut MBImageSink<SM>,
+    text: &str,
+    offset: MBPtrT,
+) -> Result<(), String> {
+    let mut base: u64 = 0;
+    for (i, line) in text.lines().enumerate() {
+        let err_handler = |e: String| format!("line {}: {}", i + 1, e);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let rec = line
+            .strip_prefix(':')
+            .ok_or(err_handler("record should start with ':'!".to_string()))?;
+        let bytes = hex_bytes(rec).map_err(err_handler)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(err_handler("record length mismatched!".to_string()));
+        }
+        if bytes.iter().fold(0u8, |s, b| s.wrapping_add(*b)) != 0 {
+            return Err(err_handler("checksum mismatched!".to_string()));
+        }
+        let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as u64;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => sink
+                .push(offset.wrapping_add((base + addr) as MBPtrT), data)
+                .map_err(err_handler)?,
+            0x01 => break,
+            0x02 | 0x04 if data.len() == 2 => {
+                let shift = if bytes[3] == 0x02 { 4 } else { 16 };
+                base = (u16::from_be_bytes([data[0], data[1]]) as u64) << shift
+            }
+            0x03 | 0x05 => {}
+            t => return Err(err_handler(format!("invalid record type {:#x}!", t))),
+        }
+    }
+    Ok(())
+}
+
+fn load_srec<SM: MBShareMem + ?Sized>(
+    sink: &mut MBImageSink<SM>,
+    text: &str,
+    offset: MBPtrT,
+) -> Result<(), String> {
+    for (i, line) in text.lines().enumerate() {
+        let err_handler = |e: String| format!("line {}: {}", i + 1, e);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() < 4 || !line.is_ascii() || !line.starts_with('S') {
+            return Err(err_handler("record should start with 'S'!".to_string()));
+        }
+        let addr_len = match &line[1..2] {
+            "0" | "1" | "5" | "9" => 2,
+            "2" | "6" | "8" => 3,
+            "3" | "7" => 4,
+            t => return Err(err_handler(format!("invalid record type S{}!", t))),
+        };
+        let bytes = hex_bytes(&line[2..]).map_err(err_handler)?;
+        if bytes.len() != bytes[0] as usize + 1 || bytes.len() < addr_len + 2 {
+            return Err(err_handler("record length mismatched!".to_string()));
+        }
+        if bytes.iter().fold(0u8, |s, b| s.wrapping_add(*b)) != 0xff {
+            return Err(err_handler("checksum mismatched!".to_string()));
+        }
+        let addr = bytes[1..1 + addr_len]
+            .iter()
+            .fold(0u64, |a, b| (a << 8) | *b as u64);
+        let data = &bytes[1 + addr_len..bytes.len() - 1];
+        match &line[1..2] {
+            "1" | "2" | "3" => sink
+                .push(offset.wrapping_add(addr as MBPtrT), data)
+                .map_err(err_handler)?,
+            "7" | "8" | "9" => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn strip_memh_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("//") {
+            rest = r.find('\n').map_or("", |p| &r[p..]);
+        } else if let Some(r) = rest.strip_prefix("/*") {
+            rest = r.find("*/").map_or("", |p| &r[p + 2..]);
+            result.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    result
+}
+
+fn load_memh<SM: MBShareMem + ?Sized>(
+    sink: &mut MBImageSink<SM>,
+    text: &str,
+    base: MBPtrT,
+    word_size: Option<usize>,
+) -> Result<(), String> {
+    let mut word_size = word_size;
+    let mut word_addr: u64 = 0;
+    for token in strip_memh_comments(text).split_whitespace() {
+        if let Some(a) = token.strip_prefix('@') {
+            word_addr = u64::from_str_radix(&a.replace('_', ""), 16)
+                .map_err(|e| format!("invalid address {:?}: {}", token, e))?;
+            continue;
+        }
+        let digits = token
+            .replace('_', "")
+            .replace(['x', 'X', 'z', 'Z'], "0")
+            .to_lowercase();
+        let size = *word_size.get_or_insert(digits.len().div_ceil(2));
+        if size == 0 || digits.len() > size * 2 {
+            return Err(format!(
+                "word {:?} exceeds word size {} bytes!",
+                token, size
+            ));
+        }
+        let mut word = hex_bytes(&format!("{:0>width$}", digits, width = size * 2))
+            .map_err(|e| format!("invalid word {:?}: {}", token, e))?;
+        word.reverse();
+        sink.push(
+            base.wrapping_add((word_addr * size as u64) as MBPtrT),
+            &word,
+        )?;
+        word_addr += 1;
+    }
+    Ok(())
+}
+
+pub fn mb_load_image<SM: MBShareMem + ?Sized>(
+    sm: &mut SM,
+    file: &str,
+    opts: &MBLoadOpts,
+) -> Result<MBLoadReport, String> {
+    let file_expand = shellexpand::full(file)
+        .map_err(|e| e.to_string())?
+        .to_string();
+    let err_handler = |e: String| format!("load image {} fail! {}", file_expand, e);
+    let content = fs::read(&file_expand).map_err(|e| err_handler(e.to_string()))?;
+    let format = opts
+        .format
+        .unwrap_or_else(|| MBImageFormat::detect(&file_expand, &content));
+    if format == MBImageFormat::Elf {
+        let elf = ElfFile::new(&content).map_err(|e| err_handler(e.to_string()))?;
+        return mb_load_elf(sm, &elf, &file_expand, opts).map_err(err_handler);
+    }
+    let mut sink = MBImageSink::new(sm, format, &file_expand);
+    let text = || std::str::from_utf8(&content).map_err(|e| e.to_string());
+    match format {
+        MBImageFormat::IHex => load_ihex(&mut sink, text()?, opts.addr.unwrap_or(0)),
+        MBImageFormat::SRec => load_srec(&mut sink, text()?, opts.addr.unwrap_or(0)),
+        MBImageFormat::ReadMemH => {
+            load_memh(&mut sink, text()?, opts.addr.unwrap_or(0), opts.word_size)
+        }
+        MBImageFormat::Bin => opts
+            .addr
+            .ok_or("bin image needs load address!".to_string())
+            .and_then(|addr| sink.push(addr, &content)),
+        MBImageFormat::Elf => unreachable!(),
+    }
+    .and_then(|_| sink.flush())
+    .map_err(err_handler)?;
+    Ok(sink.report)
+}
+
+//ELF64 images built for tests
+#[cfg(test)]
+pub(crate) mod test_elf {
+    pub(crate) struct MBTestElfSection<'a> {
+        pub name: &'a str,
+        pub addr: u64,
+        //none for zeroed sections as .bss
+        pub data: Option<&'a [u8]>,
+        pub size: u64,
+    }
+
+    pub(crate) struct MBTestElfSymbol<'a> {
+        pub name: &'a str,
+        pub info: u8,
+        //index in the sections given, from 1
+        pub section: u16,
+        pub value: u64,
+        pub size: u64,
+    }
+
+    fn push_u16(elf: &mut Vec<u8>, v: u16) {
+        elf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn push_u32(elf: &mut Vec<u8>, v: u32) {
+        elf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn push_u64(elf: &mut Vec<u8>, v: u64) {
+        elf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn add_name(strtab: &mut Vec<u8>, name: &str) -> u32 {
+        let offset = strtab.len() as u32;
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+        offset
+    }
+
+    //sections with data come first, all of them are loaded by one segment @ paddr if given
+    pub(crate) fn mb_test_elf(
+        sections: &[MBTestElfSection],
+        paddr: Option<u64>,
+        symbols: &[MBTestElfSymbol],
+    ) -> Vec<u8> {
+        let phnum = paddr.map_or(0, |_| 1);
+        let data_off = 64 + 56 * phnum as u64;
+        let mut body = vec![];
+        //(name, type, flags, addr, offset, size, link, info, entsize)
+        let mut shdrs = vec![(0u32, 0u32, 0u64, 0u64, 0u64, 0u64, 0u32, 0u32, 0u64)];
+        let mut shstrtab = vec![0u8];
+        for s in sections {
+            let name = add_name(&mut shstrtab, s.name);
+            let offset = data_off + body.len() as u64;
+            let ty = if let Some(data) = s.data {
+                body.extend_from_slice(data);
+                1
+            } else {
+                8
+            };
+            shdrs.push((name, ty, 3, s.addr, offset, s.size, 0, 0, 0));
+        }
+        let filesz = body.len() as u64;
+        if !symbols.is_empty() {
+            let mut strtab = vec![0u8];
+            let symtab_off = data_off + body.len() as u64;
+            body.extend_from_slice(&[0u8; 24]);
+            for s in symbols {
+                let name = add_name(&mut strtab, s.name);
+                push_u32(&mut body, name);
+                body.extend_from_slice(&[s.info, 0]);
+                push_u16(&mut body, s.section);
+                push_u64(&mut body, s.value);
+                push_u64(&mut body, s.size);
+            }
+            let locals = symbols.iter().take_while(|s| s.info >> 4 == 0).count() as u32;
+            let strtab_idx = shdrs.len() as u32 + 1;
+            let name = add_name(&mut shstrtab, ".symtab");
+            let size = (symbols.len() as u64 + 1) * 24;
+            shdrs.push((name, 2, 0, 0, symtab_off, size, strtab_idx, locals + 1, 24));
+            let name = add_name(&mut shstrtab, ".strtab");
+            let offset = data_off + body.len() as u64;
+            body.extend_from_slice(&strtab);
+            shdrs.push((name, 3, 0, 0, offset, strtab.len() as u64, 0, 0, 0));
+        }
+        let name = add_name(&mut shstrtab, ".shstrtab");
+        let offset = data_off + body.len() as u64;
+        shdrs.push((name, 3, 0, 0, offset, 0, 0, 0, 0));
+        body.extend_from_slice(&shstrtab);
+        shdrs.last_mut().unwrap().5 = shstrtab.len() as u64;
+        let shoff = (data_off + body.len() as u64 + 7) & !7;
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        push_u16(&mut elf, 2);
+        push_u16(&mut elf, 0xf3);
+        push_u32(&mut elf, 1);
+        for v in [0u64, if phnum == 0 { 0 } else { 64 }, shoff] {
+            push_u64(&mut elf, v);
+        }
+        push_u32(&mut elf, 0);
+        let shnum = shdrs.len() as u16;
+        for v in [64u16, 56, phnum, 64, shnum, shnum - 1] {
+            push_u16(&mut elf, v);
+        }
+        if let (Some(paddr), Some(first), Some(last)) = (paddr, sections.first(), sections.last()) {
+            push_u32(&mut elf, 1);
+            push_u32(&mut elf, 6);
+            let memsz = last.addr + last.size - first.addr;
+            for v in [data_off, first.addr, paddr, filesz, memsz, 1] {
+                push_u64(&mut elf, v);
+            }
+        }
+        elf.extend_from_slice(&body);
+        elf.resize(shoff as usize, 0);
+        for (name, ty, flags, addr, offset, size, link, info, entsize) in shdrs {
+            push_u32(&mut elf, name);
+            push_u32(&mut elf, ty);
+            for v in [flags, addr, offset, size] {
+                push_u64(&mut elf, v);
+            }
+            push_u32(&mut elf, link);
+            push_u32(&mut elf, info);
+            push_u64(&mut elf, 1);
+            push_u64(&mut elf, entsize);
+        }
+        elf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[derive(Default)]
+    struct ShareMem {
+        mem: Vec<u8>,
+    }
+    impl MBShareMem for ShareMem {
+        fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+            let end = addr as usize + data.len();
+            if self.mem.len() < end {
+                self.mem.resize(end, 0xff);
+            }
+            self.mem[addr as usize..end].copy_from_slice(data);
+            data.len()
+        }
+        fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+            data.copy_from_slice(&self.mem[addr as usize..addr as usize + data.len()]);
+            data.len()
+        }
+    }
+
+    fn load<C: AsRef<[u8]>>(name: &str, content: C, opts: &MBLoadOpts) -> Result<ShareMem, String> {
+        let file = std::env::temp_dir().join(format!("{}_{}", std::process::id(), name));
+        fs::write(&file, content).unwrap();
+        let mut mem = ShareMem::default();
+        let r = mem.load_image(file.to_str().unwrap(), opts);
+        fs::remove_file(&file).unwrap();
+        r.map(|_| mem)
+    }
+
+    #[test]
+    fn mb_load_image_test() {
+        let ihex = "
+            :020000040000FA
+            :0400100001020304E2
+            :02001400AABB85
+            :00000001FF
+        ";
+        let mem = load("ihex.hex", ihex, &MBLoadOpts::default()).unwrap();
+        assert_eq!(mem.mem[0x10..0x16], [1, 2, 3, 4, 0xaa, 0xbb]);
+        let srec = "S00600004844521B\nS107002001020304CE\nS9030000FC\n";
+        let mem = load("srec.s19", srec, &MBLoadOpts::default()).unwrap();
+        assert_eq!(mem.mem[0x20..0x24], [1, 2, 3, 4]);
+        let memh = "// comment\n@2 deadbeef /* block\n comment */ 0102_0304\n";
+        let opts = MBLoadOpts {
+            addr: Some(0x100),
+            ..Default::default()
+        };
+        let mem = load("memh.hex", memh, &opts).unwrap();
+        assert_eq!(mem.mem[0x108..0x110], [0xef, 0xbe, 0xad, 0xde, 4, 3, 2, 1]);
+        let mem = load("raw.bin", "abcd", &opts).unwrap();
+        assert_eq!(mem.mem[0x100..0x104], *b"abcd");
+        assert!(load("raw.bin", "abcd", &MBLoadOpts::default()).is_err());
+        assert!(load("bad.hex", ":0400100001020304E3\n", &MBLoadOpts::default()).is_err());
+    }
+
+    //one load segment @ paddr 0x1000 vaddr 0x8000 with .data(4) and .bss(4)
+    fn elf_image() -> Vec<u8> {
+        use super::test_elf::*;
+        mb_test_elf(
+            &[
+                MBTestElfSection {
+                    name: ".data",
+                    addr: 0x8000,
+                    data: Some(&[1, 2, 3, 4]),
+                    size: 4,
+                },
+                MBTestElfSection {
+                    name: ".bss",
+                    addr: 0x8004,
+                    data: None,
+                    size: 4,
+                },
+            ],
+            Some(0x1000),
+            &[],
+        )
+    }
+
+    #[test]
+    fn mb_load_elf_test() {
+        let opts = MBLoadOpts::default();
+        let mem = load("load.elf", elf_image(), &opts).unwrap();
+        assert_eq!(mem.mem[0x1000..0x1008], [1, 2, 3, 4, 0, 0, 0, 0]);
+        let opts = MBLoadOpts {
+            elf_addr: MBElfAddr::Virtual,
+            ..Default::default()
+        };
+        let mem = load("load.elf", elf_image(), &opts).unwrap();
+        assert_eq!(mem.mem.len(), 0x8008);
+        assert_eq!(mem.mem[0x8000..0x8008], [1, 2, 3, 4, 0, 0, 0, 0]);
+        let opts = MBLoadOpts {
+            exclude_sections: vec![".bss".to_string()],
+            ..Default::default()
+        };
+        let mem = load("load.elf", elf_image(), &opts).unwrap();
+        assert_eq!(mem.mem[0x1000..], [1, 2, 3, 4]);
+        let opts = MBLoadOpts {
+            sections: vec![".b*".to_string()],
+            ..Default::default()
+        };
+        let mut mem = ShareMem::default();
+        let file = std::env::temp_dir().join(format!("{}_sections.elf", std::process::id()));
+        fs::write(&file, elf_image()).unwrap();
+        let report = mem.load_image(file.to_str().unwrap(), &opts).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            report.regions,
+            vec![MBLoadRegion {
+                name: ".bss".to_string(),
+                addr: 0x1004,
+                size: 4,
+                zeroed: true
+            }]
+        );
+        assert_eq!(mem.mem[0x1000..], [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,551 @@
+use super::mb_ptr_resolver::*;
+use super::mb_share_mem::*;
+use crate::mb_rpcs::*;
+use async_std::task::{Context, Poll};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+const MB_PAGE_SHIFT: u32 = 12;
+const MB_PAGE_SIZE: u64 = 1 << MB_PAGE_SHIFT;
+const MB_PTE_SIZE: u64 = 8;
+const MB_PTE_V: u64 = 1 << 0;
+const MB_PTE_R: u64 = 1 << 1;
+const MB_PTE_W: u64 = 1 << 2;
+const MB_PTE_X: u64 = 1 << 3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MBPagingMode {
+    Bare,
+    Sv39,
+    Sv48,
+}
+
+impl MBPagingMode {
+    fn levels(&self) -> u32 {
+        match self {
+            MBPagingMode::Bare => 0,
+            MBPagingMode::Sv39 => 3,
+            MBPagingMode::Sv48 => 4,
+        }
+    }
+    fn va_bits(&self) -> u32 {
+        MB_PAGE_SHIFT + 9 * self.levels()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct MBPageTableRoot {
+    mode: MBPagingMode,
+    //physical address of the root table
+    base: u64,
+}
+
+//resolver for firmware running with the riscv mmu on: pointers are virtual addresses translated by walking
+//the device's page tables in sm. the root is shared by clones, so it can be set when the firmware
+//passes its satp, e.g. from a CALL handler, after the resolver is handed to the server.
+//over a staged memory poll_load loads the ptes walked as well, translating before needs them loaded
+pub struct MBPageTableResolver<SM: MBShareMem> {
+    sm: Arc<Mutex<SM>>,
+    root: Arc<Mutex<MBPageTableRoot>>,
+}
+
+impl<SM: MBShareMem> Clone for MBPageTableResolver<SM> {
+    fn clone(&self) -> Self {
+        MBPageTableResolver {
+            sm: self.sm.clone(),
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<SM: MBShareMem> MBPageTableResolver<SM> {
+    //bare until the root is set
+    pub fn new(sm: &Arc<Mutex<SM>>) -> MBPageTableResolver<SM> {
+        MBPageTableResolver {
+            sm: sm.clone(),
+            root: Arc::new(Mutex::new(MBPageTableRoot {
+                mode: MBPagingMode::Bare,
+                base: 0,
+            })),
+        }
+    }
+    pub fn set_root(&self, mode: MBPagingMode, base: u64) {
+        *self.root.lock().unwrap() = MBPageTableRoot { mode, base };
+    }
+    //rv64 satp: MODE[63:60], ASID[59:44], PPN[43:0]
+    pub fn set_satp(&self, satp: u64) -> Result<(), String> {
+        let mode = match satp >> 60 {
+            0 => MBPagingMode::Bare,
+            8 => MBPagingMode::Sv39,
+            9 => MBPagingMode::Sv48,
+            m => return Err(format!("satp mode {} is not supported!", m)),
+        };
+        self.set_root(mode, (satp & ((1 << 44) - 1)) << MB_PAGE_SHIFT);
+        Ok(())
+    }
+    pub fn mode(&self) -> MBPagingMode {
+        self.root.lock().unwrap().mode
+    }
+    //pte of va at addr, loaded first with cx
+    fn read_pte(
+        &self,
+        va: MBPtrT,
+        addr: u64,
+        cx: &mut Option<&mut Context>,
+    ) -> Poll<Result<u64, MBShareMemError>> {
+        let addr = Self::to_ptr(addr).ok_or(MBShareMemError::Unmapped(va))?;
+        let mut sm = self.sm.lock().unwrap();
+        if let Some(cx) = cx {
+            std::task::ready!(sm.poll_load(addr, MB_PTE_SIZE as usize, cx));
+        }
+        let mut bytes = [0u8; MB_PTE_SIZE as usize];
+        Poll::Ready(
+            sm.try_read(addr, &mut bytes)
+                .map(|_| u64::from_le_bytes(bytes)),
+        )
+    }
+    //none if pa is beyond the pointers of the host
+    fn to_ptr(pa: u64) -> Option<MBPtrT> {
+        MBPtrT::try_from(pa).ok()
+    }
+    //physical address of va and the bytes left in its page
+    pub fn translate(
+        &self,
+        va: MBPtrT,
+        access: MBMemAccess,
+    ) -> Result<(MBPtrT, usize), MBShareMemError> {
+        match self.walk(va, access, &mut None) {
+            Poll::Ready(r) => r,
+            Poll::Pending => unreachable!(),
+        }
+    }
+    fn walk(
+        &self,
+        va: MBPtrT,
+        access: MBMemAccess,
+        cx: &mut Option<&mut Context>,
+    ) -> Poll<Result<(MBPtrT, usize), MBShareMemError>> {
+        let root = *self.root.lock().unwrap();
+        let va_u64 = va as u64;
+        if root.mode == MBPagingMode::Bare {
+            return Poll::Ready(Ok((
+                va,
+                (MB_PAGE_SIZE - (va_u64 & (MB_PAGE_SIZE - 1))) as usize,
+            )));
+        }
+        //upper bits should all equal to the top bit
+        let va_bits = root.mode.va_bits();
+        let upper = (va_u64 as i64) >> (va_bits - 1);
+        if upper != 0 && upper != -1 {
+            return Poll::Ready(Err(MBShareMemError::Unmapped(va)));
+        }
+        let mut table = root.base;
+        for level in (0..root.mode.levels()).rev() {
+            let shift = MB_PAGE_SHIFT + 9 * level;
+            let idx = (va_u64 >> shift) & 0x1ff;
+            let pte = std::task::ready!(self.read_pte(va, table + idx * MB_PTE_SIZE, cx))?;
+            if pte & MB_PTE_V == 0 || (pte & MB_PTE_W != 0 && pte & MB_PTE_R == 0) {
+                return Poll::Ready(Err(MBShareMemError::Unmapped(va)));
+            }
+            let ppn = (pte >> 10) & ((1 << 44) - 1);
+            if pte & (MB_PTE_R | MB_PTE_X) == 0 {
+                table = ppn << MB_PAGE_SHIFT;
+                continue;
+            }
+            let allowed = match access {
+                MBMemAccess::Read => pte & MB_PTE_R != 0,
+                MBMemAccess::Write => pte & MB_PTE_W != 0,
+            };
+            if !allowed {
+                return Poll::Ready(Err(MBShareMemError::Permission(format!(
+                    "{:?} @ {:#x} is not permitted by pte {:#x}!",
+                    access, va, pte
+                ))));
+            }
+            let page_size = 1u64 << shift;
+            //superpages should be aligned
+            if (ppn << MB_PAGE_SHIFT) & (page_size - 1) != 0 {
+                return Poll::Ready(Err(MBShareMemError::Unmapped(va)));
+            }
+            let offset = va_u64 & (page_size - 1);
+            //pages beyond the host pointers, e.g. above 4G with ptr32, are not reachable
+            return Poll::Ready(
+                Self::to_ptr((ppn << MB_PAGE_SHIFT) | offset)
+                    .map(|pa| (pa, (page_size - offset) as usize))
+                    .ok_or(MBShareMemError::Unmapped(va)),
+            );
+        }
+        Poll::Ready(Err(MBShareMemError::Unmapped(va)))
+    }
+    //call f with the physical address and length of each page in [va, va + len),
+    //stop at a page failing to translate or a page accessing less bytes
+    fn for_pages<F: FnMut(&mut SM, MBPtrT, std::ops::Range<usize>) -> usize>(
+        &self,
+        va: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+        mut f: F,
+    ) -> (usize, Option<MBShareMemError>) {
+        let mut pos = 0;
+        while pos < len {
+            let cur = va.wrapping_add(pos as MBPtrT);
+            let (pa, remain) = match self.translate(cur, access) {
+                Ok(r) => r,
+                Err(e) => return (pos, Some(e)),
+            };
+            let l = std::cmp::min(len - pos, remain);
+            let actual = f(&mut self.sm.lock().unwrap(), pa, pos..pos + l);
+            pos += actual;
+            if actual < l {
+                break;
+            }
+        }
+        (pos, None)
+    }
+    //bytes accessed, error if none of a non-empty access is
+    fn bytes_result(
+        len: usize,
+        r: (usize, Option<MBShareMemError>),
+    ) -> Result<usize, MBShareMemError> {
+        match r {
+            (0, Some(e)) if len > 0 => Err(e),
+            (l, _) => Ok(l),
+        }
+    }
+    fn read_bytes(&self, va: MBPtrT, buf: &mut [u8]) -> Result<usize, MBShareMemError> {
+        let len = buf.len();
+        let r = self.for_pages(va, len, MBMemAccess::Read, |sm, pa, range| {
+            sm.read(pa, &mut buf[range])
+        });
+        Self::bytes_result(len, r)
+    }
+    fn write_bytes(&self, va: MBPtrT, buf: &[u8]) -> Result<usize, MBShareMemError> {
+        let r = self.for_pages(va, buf.len(), MBMemAccess::Write, |sm, pa, range| {
+            sm.write(pa, &buf[range])
+        });
+        Self::bytes_result(buf.len(), r)
+    }
+}
+
+pub struct MBPageTableReaderWriter<SM: MBShareMem> {
+    ptr: MBPtrT,
+    resolver: MBPageTableResolver<SM>,
+}
+
+impl<SM: MBShareMem> MBPageTableReaderWriter<SM> {
+    fn read_checked(&self, buf: &mut [u8]) {
+        let len = buf.len();
+        match self.resolver.read_bytes(self.ptr, buf) {
+            Ok(l) if l == len => {}
+            Ok(l) => panic!(
+                "{}",
+                MBShareMemError::Partial {
+                    addr: self.ptr,
+                    expected: len,
+                    actual: l
+                }
+            ),
+            Err(e) => panic!("{}", e),
+        }
+    }
+    fn write_checked(&self, buf: &[u8]) {
+        match self.resolver.write_bytes(self.ptr, buf) {
+            Ok(l) if l == buf.len() => {}
+            Ok(l) => panic!(
+                "{}",
+                MBShareMemError::Partial {
+                    addr: self.ptr,
+                    expected: buf.len(),
+                    actual: l
+                }
+            ),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+impl<SM: MBShareMem> MBPtrReader for MBPageTableReaderWriter<SM> {
+    fn read_slice<T: Sized + Copy>(&mut self, data: &mut [T]) {
+        self.read_checked(unsafe {
+            std::slice::from_raw_parts_mut(
+                data.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(data),
+            )
+        })
+    }
+    fn try_read_slice<T: Sized + Copy>(&mut self, data: &mut [T]) -> usize {
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(
+                data.as_mut_ptr() as *mut u8,
+                std::mem::size_of_val(data),
+            )
+        };
+        self.resolver.read_bytes(self.ptr, buf).unwrap_or(0)
+    }
+    fn read_sized<T: Sized>(&mut self, data: &mut T) {
+        self.read_checked(unsafe {
+            std::slice::from_raw_parts_mut(data as *mut T as *mut u8, std::mem::size_of::<T>())
+        })
+    }
+}
+
+impl<SM: MBShareMem> Read for MBPageTableReaderWriter<SM> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let l = self.resolver.read_bytes(self.ptr, buf)?;
+        self.ptr += l as MBPtrT;
+        Ok(l)
+    }
+}
+
+impl<SM: MBShareMem> MBPtrWriter for MBPageTableReaderWriter<SM> {
+    fn write_slice<T: Sized + Copy>(&mut self, data: &[T]) {
+        self.write_checked(unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        })
+    }
+    fn try_write_slice<T: Sized + Copy>(&mut self, data: &[T]) -> usize {
+        let buf = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        };
+        self.resolver.write_bytes(self.ptr, buf).unwrap_or(0)
+    }
+    fn write_sized<T: Sized>(&mut self, data: &T) {
+        self.write_checked(unsafe {
+            std::slice::from_raw_parts(data as *const T as *const u8, std::mem::size_of::<T>())
+        })
+    }
+}
+
+impl<SM: MBShareMem> Write for MBPageTableReaderWriter<SM> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let l = self.resolver.write_bytes(self.ptr, buf)?;
+        self.ptr += l as MBPtrT;
+        Ok(l)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<SM: MBShareMem> MBShareMemResolver for MBPageTableResolver<SM> {
+    type SM = SM;
+}
+
+impl<SM: MBShareMem> MBPtrResolver for MBPageTableResolver<SM> {
+    type READER = MBPageTableReaderWriter<SM>;
+    type WRITER = MBPageTableReaderWriter<SM>;
+    fn reader<T: Sized>(&self, ptr: *const T) -> Self::READER {
+        MBPageTableReaderWriter {
+            ptr: ptr as MBPtrT,
+            resolver: self.clone(),
+        }
+    }
+    fn writer<T: Sized>(&self, ptr: *mut T) -> Self::WRITER {
+        MBPageTableReaderWriter {
+            ptr: ptr as MBPtrT,
+            resolver: self.clone(),
+        }
+    }
+    fn check_access(
+        &self,
+        ptr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        let mut result = Ok(());
+        if let (_, Some(e)) = self.for_pages(ptr, len, access, |sm, pa, range| {
+            result = result.clone().and(sm.check_access(pa, range.len(), access));
+            if result.is_ok() {
+                range.len()
+            } else {
+                0
+            }
+        }) {
+            return Err(e);
+        }
+        result
+    }
+    fn atomic(&self, ptr: MBPtrT, size: usize, op: MBAtomicOp) -> Result<u64, MBShareMemError> {
+        let (pa, remain) = self.translate(ptr, MBMemAccess::Write)?;
+        if remain < size {
+            return Err(MBShareMemError::Partial {
+                addr: ptr,
+                expected: size,
+                actual: remain,
+            });
+        }
+        let mut sm = self.sm.lock().unwrap();
+        sm.check_access(pa, size, MBMemAccess::Write)?;
+        sm.atomic(pa, size, op)
+    }
+    //load the ptes and the pages of [ptr, ptr + len), up to a page failing to translate
+    fn poll_load(&self, ptr: MBPtrT, len: usize, cx: &mut Context) -> Poll<()> {
+        let mut pos = 0;
+        while pos < len {
+            let cur = ptr.wrapping_add(pos as MBPtrT);
+            let (pa, remain) =
+                match std::task::ready!(self.walk(cur, MBMemAccess::Read, &mut Some(&mut *cx))) {
+                    Ok(r) => r,
+                    Err(_) => break,
+                };
+            let l = std::cmp::min(len - pos, remain);
+            std::task::ready!(self.sm.lock().unwrap().poll_load(pa, l, cx));
+            pos += l;
+        }
+        Poll::Ready(())
+    }
+    //load the batches read_c_str_with reads until the one with nul
+    fn poll_load_c_str(&self, ptr: MBPtrT, limits: &MBStrLimits, cx: &mut Context) -> Poll<()> {
+        let mut next = ptr;
+        let mut remain = limits.max_len.saturating_add(1);
+        while remain > 0 {
+            let batch_len = std::cmp::min(limits.batch_len(), remain);
+            std::task::ready!(self.poll_load(next, batch_len, cx));
+            let mut buf = vec![0u8; batch_len];
+            let l = self.read_bytes(next, &mut buf).unwrap_or(0);
+            if l < batch_len || buf[..l].contains(&0) {
+                break;
+            }
+            next = next.wrapping_add(batch_len as MBPtrT);
+            remain -= batch_len;
+        }
+        Poll::Ready(())
+    }
+    fn poll_store(&self, cx: &mut Context) -> Poll<()> {
+        self.sm.lock().unwrap().poll_store(cx)
+    }
+    fn release(&self) {
+        self.sm.lock().unwrap().release()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //map va to pa with 4K pages in a Sv39 table at 0x10000, tables are allocated from next
+    fn map(sm: &mut MBSparseShareMem, next: &mut u64, va: u64, pa: u64, flags: u64) {
+        let mut table = 0x10000u64;
+        for level in (1..3).rev() {
+            let idx = (va >> (12 + 9 * level)) & 0x1ff;
+            let mut pte = 0u64;
+            sm.read_sized((table + idx * 8) as MBPtrT, &mut pte);
+            if pte & MB_PTE_V == 0 {
+                pte = ((*next >> 12) << 10) | MB_PTE_V;
+                *next += 0x1000;
+                sm.write_sized((table + idx * 8) as MBPtrT, &pte);
+            }
+            table = (pte >> 10) << 12;
+        }
+        let idx = (va >> 12) & 0x1ff;
+        sm.write_sized(
+            (table + idx * 8) as MBPtrT,
+            &(((pa >> 12) << 10) | flags | MB_PTE_V),
+        );
+    }
+
+    #[test]
+    fn mb_page_table_test() {
+        let mut sm = MBSparseShareMem::new(0, 0x100000);
+        let mut next = 0x11000u64;
+        let va = 0xffff_ffc0_0000_1000u64;
+        map(&mut sm, &mut next, va, 0x80000, MB_PTE_R | MB_PTE_W);
+        map(
+            &mut sm,
+            &mut next,
+            va + 0x1000,
+            0x40000,
+            MB_PTE_R | MB_PTE_W,
+        );
+        map(&mut sm, &mut next, va + 0x2000, 0x50000, MB_PTE_R);
+        sm.write(0x80ffd, b"hel");
+        sm.write(0x40000, b"lo\0");
+        let sm = Arc::new(Mutex::new(sm));
+        let r = MBPageTableResolver::new(&sm);
+        r.set_satp((8 << 60) | (0x10000 >> 12)).unwrap();
+        assert_eq!(r.mode(), MBPagingMode::Sv39);
+        let va = va as MBPtrT;
+        assert_eq!(r.read_c_str((va + 0xffd) as *const u8).unwrap(), "hello");
+        r.write_slice((va + 0xffe) as *mut u8, &[1u8, 2, 3, 4]);
+        let mut data = [0u8; 2];
+        sm.lock().unwrap().read(0x40000, &mut data);
+        assert_eq!(data, [3, 4]);
+        assert_eq!(r.atomic_fetch_add((va + 0x1000) as *mut u16, 1), Ok(0x0403));
+        assert_eq!(
+            r.check_access(va + 0x1ff0, 0x20, MBMemAccess::Write),
+            Err(MBShareMemError::Permission(format!(
+                "Write @ {:#x} is not permitted by pte {:#x}!",
+                va + 0x2000,
+                ((0x50000u64 >> 12) << 10) | MB_PTE_R | MB_PTE_V
+            )))
+        );
+        assert_eq!(
+            r.check_access(va + 0x3000, 1, MBMemAccess::Read),
+            Err(MBShareMemError::Unmapped(va + 0x3000))
+        );
+        //not canonical
+        assert_eq!(
+            r.translate(0x0000_0080_0000_0000, MBMemAccess::Read),
+            Err(MBShareMemError::Unmapped(0x0000_0080_0000_0000))
+        );
+        //reads stop at the unmapped page
+        let mut data = [0u8; 8];
+        assert_eq!(r.try_read_slice((va + 0x2ffc) as *const u8, &mut data), 4);
+        assert_eq!(r.try_read_slice((va + 0x3000) as *const u8, &mut data), 0);
+        assert_eq!(r.try_write_slice((va + 0x1ffc) as *mut u8, &data), 4);
+        let mut reader = r.reader((va + 0x2ffc) as *const u8);
+        assert_eq!(reader.read(&mut data).unwrap(), 4);
+        assert!(reader.read(&mut data).is_err());
+        r.set_satp(0).unwrap();
+        assert_eq!(
+            r.translate(0x40002, MBMemAccess::Read),
+            Ok((0x40002, 0xffe))
+        );
+    }
+
+    #[test]
+    fn mb_page_table_staged_test() {
+        use super::super::mb_rpcs::*;
+        use super::super::mb_server::*;
+        use super::super::mb_sink::*;
+        use crate::mb_channel::*;
+        let mut sm = MBSparseShareMem::new(0, 0x100000);
+        let mut next = 0x11000u64;
+        let va = 0xffff_ffc0_0000_1000u64;
+        map(&mut sm, &mut next, va, 0x80000, MB_PTE_R | MB_PTE_W);
+        map(&mut sm, &mut next, va + 0x1000, 0x40000, MB_PTE_R);
+        sm.write(0x80ffd, b"hel");
+        sm.write(0x40000, b"lo\n\0");
+        //the ptes and the pages are loaded before they are read
+        let mem = Arc::new(Mutex::new(MBLatencyShareMem::new(sm, 2)));
+        let staged = Arc::new(Mutex::new(MBStagedShareMem::new(&mem)));
+        let r = MBPageTableResolver::new(&staged);
+        r.set_satp((8 << 60) | (0x10000 >> 12)).unwrap();
+        let va = va as MBPtrT;
+        assert!(r.read_c_str((va + 0xffd) as *const u8).is_err());
+        let server = MBSMServer::with_resolver("server", &Arc::new(None), r.clone());
+        let capture = Arc::new(MBCaptureSink::new());
+        server.set_sinks(vec![capture.clone()]);
+        //handlers check the access before loading, so the ptes should be loaded first
+        async_std::task::block_on(futures::future::poll_fn(|cx| {
+            r.poll_load(va + 0xffd, 6, cx)
+        }));
+        let mut print = MBReqEntry::default();
+        MBPrint::new().put_req(
+            &MBStringArgs {
+                len: 6,
+                ptr: va + 0xffd,
+            },
+            &mut print,
+        );
+        async_std::task::block_on(server.do_cmd(&print)).unwrap();
+        assert_eq!(
+            capture.take(),
+            vec![("server".to_string(), "hello\n".to_string())]
+        );
+        async_std::task::block_on(futures::future::poll_fn(|cx| {
+            r.poll_load_c_str(va + 0xffd, &MBStrLimits::default(), cx)
+        }));
+        assert_eq!(r.read_c_str((va + 0xffd) as *const u8).unwrap(), "hello\n");
+    }
+}
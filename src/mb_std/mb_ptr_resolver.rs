@@ -1,8 +1,11 @@
+use super::mb_device::*;
 use super::mb_share_mem::*;
 use crate::mb_rpcs::*;
+use async_std::task::{Context, Poll};
 use std::io::{BufRead, BufReader, Read, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::MutexGuard;
 
 pub trait MBPtrReader: Read {
     fn read_slice<T: Sized + Copy>(&mut self, data: &mut [T]);
@@ -21,6 +24,96 @@ pub trait MBPtrResolver {
     type WRITER: MBPtrWriter;
     fn reader<T: Sized>(&self, ptr: *const T) -> Self::READER;
     fn writer<T: Sized>(&self, ptr: *mut T) -> Self::WRITER;
+    fn check_access(
+        &self,
+        _ptr: MBPtrT,
+        _len: usize,
+        _access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        Ok(())
+    }
+    //hooks of resolvers over memories accessed asynchronously:
+    //handlers load what they read before reading it, the server stores and releases after the handler
+    fn poll_load(&self, _ptr: MBPtrT, _len: usize, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn poll_load_c_str(&self, _ptr: MBPtrT, _limits: &MBStrLimits, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn poll_store(&self, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn release(&self) {}
+    //apply op to the size bytes word at ptr and return the old value,
+    //the default read-modify-write is only atomic if nothing else accesses the memory meanwhile
+    fn atomic(&self, ptr: MBPtrT, size: usize, op: MBAtomicOp) -> Result<u64, MBShareMemError> {
+        check_atomic_size(size);
+        self.check_access(ptr, size, MBMemAccess::Write)?;
+        let partial = |actual: usize| MBShareMemError::Partial {
+            addr: ptr,
+            expected: size,
+            actual,
+        };
+        let mut bytes = [0u8; 8];
+        let actual = self.try_read_slice(ptr as *const u8, &mut bytes[..size]);
+        if actual != size {
+            return Err(partial(actual));
+        }
+        let old = atomic_from_bytes(&bytes[..size]);
+        if let Some(new) = op.apply(old, size) {
+            atomic_to_bytes(new, &mut bytes[..size]);
+            let actual = self.try_write_slice(ptr as *mut u8, &bytes[..size]);
+            if actual != size {
+                return Err(partial(actual));
+            }
+        }
+        Ok(old)
+    }
+    fn atomic_cas<T: MBAtomicInt>(
+        &self,
+        ptr: *mut T,
+        current: T,
+        new: T,
+    ) -> Result<T, MBShareMemError> {
+        self.atomic(
+            ptr as MBPtrT,
+            std::mem::size_of::<T>(),
+            MBAtomicOp::Cas(current.to_u64(), new.to_u64()),
+        )
+        .map(T::from_u64)
+    }
+    fn atomic_swap<T: MBAtomicInt>(&self, ptr: *mut T, v: T) -> Result<T, MBShareMemError> {
+        self.atomic(
+            ptr as MBPtrT,
+            std::mem::size_of::<T>(),
+            MBAtomicOp::Swap(v.to_u64()),
+        )
+        .map(T::from_u64)
+    }
+    fn atomic_fetch_add<T: MBAtomicInt>(&self, ptr: *mut T, v: T) -> Result<T, MBShareMemError> {
+        self.atomic(
+            ptr as MBPtrT,
+            std::mem::size_of::<T>(),
+            MBAtomicOp::Add(v.to_u64()),
+        )
+        .map(T::from_u64)
+    }
+    fn atomic_fetch_and<T: MBAtomicInt>(&self, ptr: *mut T, v: T) -> Result<T, MBShareMemError> {
+        self.atomic(
+            ptr as MBPtrT,
+            std::mem::size_of::<T>(),
+            MBAtomicOp::And(v.to_u64()),
+        )
+        .map(T::from_u64)
+    }
+    fn atomic_fetch_or<T: MBAtomicInt>(&self, ptr: *mut T, v: T) -> Result<T, MBShareMemError> {
+        self.atomic(
+            ptr as MBPtrT,
+            std::mem::size_of::<T>(),
+            MBAtomicOp::Or(v.to_u64()),
+        )
+        .map(T::from_u64)
+    }
     fn read_slice<T: Sized + Copy>(&self, ptr: *const T, data: &mut [T]) {
         self.reader(ptr).read_slice(data)
     }
@@ -39,13 +132,48 @@ pub trait MBPtrResolver {
     fn write_sized<T: Sized>(&self, ptr: *mut T, data: &T) {
         self.writer(ptr).write_sized(data)
     }
+    //deep read the device structure at ptr, see mb_device_struct
+    fn read_device<T: MBDeviceType>(&self, ptr: MBPtrT) -> Result<T, String>
+    where
+        Self: Sized,
+    {
+        T::mb_read(self, ptr, &MBDeviceCtx::new())
+    }
+    //read_device loading what it reads first, pending until the loads complete
+    fn poll_read_device<T: MBDeviceType>(
+        &self,
+        ptr: MBPtrT,
+        cx: &mut Context,
+    ) -> Poll<Result<T, String>>
+    where
+        Self: Sized,
+    {
+        mb_poll_read_device(self, ptr, cx)
+    }
     fn read_str(&self, str_args: &MBStringArgs) -> Result<String, String> {
-        let str_len = str_args.len as usize;
+        let limits = MBStrLimits {
+            max_len: usize::MAX,
+            ..Default::default()
+        };
+        self.read_str_with(str_args, &limits).map(|s| s.s)
+    }
+    fn read_str_with(
+        &self,
+        str_args: &MBStringArgs,
+        limits: &MBStrLimits,
+    ) -> Result<MBStr, String> {
+        let str_len = std::cmp::min(str_args.len as usize, limits.max_len);
         let raw_ptr = str_args.ptr as *const usize;
         let mut buf_reader = BufReader::new(self.reader(raw_ptr as *const u8));
         let mut buf = vec![0u8; str_len];
         buf_reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
-        Ok(String::from_utf8(buf).map_err(|e| e.to_string())?)
+        if str_len < str_args.len as usize {
+            mb_str_truncate(&mut buf, str_len);
+        }
+        Ok(MBStr {
+            s: limits.decode.decode(buf)?,
+            truncated: str_len < str_args.len as usize,
+        })
     }
     fn read_until_nul(
         &self,
@@ -59,25 +187,121 @@ pub trait MBPtrResolver {
             .map_err(|e| e.to_string())?)
     }
     fn read_c_str(&self, ptr: *const u8) -> Result<String, String> {
-        let max_len = 4096;
-        let batch_len = 128;
-        let max_rep = max_len / batch_len;
+        self.read_c_str_with(ptr, &MBStrLimits::default())
+            .map(|s| s.s)
+    }
+    fn read_c_str_with(&self, ptr: *const u8, limits: &MBStrLimits) -> Result<MBStr, String> {
         let mut buf = vec![];
         let mut next = ptr as usize;
-        for _ in 0..max_rep {
+        //one byte past max_len tells a string of max_len from a longer one
+        let read_len = limits.max_len.saturating_add(1);
+        while buf.len() < read_len {
+            let batch_len = std::cmp::min(limits.batch_len(), read_len - buf.len());
             let len = self.read_until_nul(next as *const u8, &mut buf, batch_len)?;
-            if buf[buf.len() - 1] == 0 {
+            if buf.last() == Some(&0) {
                 buf.pop();
-                break;
-            } else {
-                next += len
+                return Ok(MBStr {
+                    s: limits.decode.decode(buf)?,
+                    truncated: false,
+                });
+            }
+            if len == 0 {
+                return Err(format!("string @ {:#x} is not terminated!", ptr as usize));
+            }
+            next += len
+        }
+        mb_str_truncate(&mut buf, limits.max_len);
+        Ok(MBStr {
+            s: limits.decode.decode(buf)?,
+            truncated: true,
+        })
+    }
+}
+
+//truncate to max_len bytes, a multi-byte char split at max_len is dropped whole
+fn mb_str_truncate(buf: &mut Vec<u8>, max_len: usize) {
+    buf.truncate(max_len);
+    let tail = buf.len().saturating_sub(3);
+    if let Some(start) = (tail..buf.len()).rev().find(|&i| buf[i] & 0xc0 != 0x80) {
+        if let Err(e) = std::str::from_utf8(&buf[start..]) {
+            if e.error_len().is_none() {
+                buf.truncate(start + e.valid_up_to());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MBStrDecode {
+    //invalid utf-8 is an error
+    #[default]
+    Strict,
+    //invalid utf-8 is replaced by U+FFFD
+    Lossy,
+    //invalid utf-8 bytes are escaped as \xNN
+    HexEscape,
+}
+impl MBStrDecode {
+    pub fn decode(&self, bytes: Vec<u8>) -> Result<String, String> {
+        match self {
+            MBStrDecode::Strict => String::from_utf8(bytes).map_err(|e| e.to_string()),
+            MBStrDecode::Lossy => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+            MBStrDecode::HexEscape => {
+                let mut s = String::new();
+                let mut rest = bytes.as_slice();
+                loop {
+                    match std::str::from_utf8(rest) {
+                        Ok(valid) => {
+                            s += valid;
+                            return Ok(s);
+                        }
+                        Err(e) => {
+                            let (valid, invalid) = rest.split_at(e.valid_up_to());
+                            s += unsafe { std::str::from_utf8_unchecked(valid) };
+                            let invalid_len = e.error_len().unwrap_or(invalid.len());
+                            for b in &invalid[..invalid_len] {
+                                s += &format!("\\x{:02x}", b);
+                            }
+                            rest = &invalid[invalid_len..];
+                        }
+                    }
+                }
             }
         }
-        let string = String::from_utf8(buf).map_err(|e| e.to_string())?;
-        Ok(string)
     }
 }
 
+//limits of strings read from the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MBStrLimits {
+    //bytes kept, the rest is dropped and reported as truncated
+    pub max_len: usize,
+    //bytes read at a time when looking for the nul of c strings
+    pub batch_len: usize,
+    pub decode: MBStrDecode,
+}
+impl MBStrLimits {
+    pub(crate) fn batch_len(&self) -> usize {
+        std::cmp::max(self.batch_len, 1)
+    }
+}
+impl Default for MBStrLimits {
+    fn default() -> Self {
+        MBStrLimits {
+            max_len: 4096,
+            batch_len: 128,
+            decode: MBStrDecode::Strict,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBStr {
+    pub s: String,
+    //longer than MBStrLimits::max_len
+    pub truncated: bool,
+}
+
 pub struct MBLocalPtrReader {
     ptr: *const u8,
 }
@@ -176,6 +400,232 @@ impl MBPtrResolver for MBLocalPtrResolver {
     fn writer<T: Sized>(&self, ptr: *mut T) -> Self::WRITER {
         MBLocalPtrWriter::new(ptr as *mut u8)
     }
+    fn atomic(&self, ptr: MBPtrT, size: usize, op: MBAtomicOp) -> Result<u64, MBShareMemError> {
+        unsafe { mb_atomic_ptr(ptr as *mut u8, size, op) }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MBLocalRegion {
+    base: MBPtrT,
+    size: MBPtrT,
+    perm: MBShareMemPerm,
+}
+impl MBLocalRegion {
+    fn end_addr(&self) -> MBPtrT {
+        (self.size - 1) + self.base
+    }
+    fn in_range(&self, addr: MBPtrT) -> bool {
+        addr >= self.base && addr <= self.end_addr()
+    }
+}
+
+//local resolver only dereferencing pointers in the registered regions of the server's own memory.
+//clones share the regions, so they can be updated after the resolver is handed to the server
+#[derive(Debug, Default, Clone)]
+pub struct MBCheckedLocalPtrResolver {
+    regions: Arc<Mutex<Vec<MBLocalRegion>>>,
+}
+impl MBCheckedLocalPtrResolver {
+    pub fn new() -> MBCheckedLocalPtrResolver {
+        Self::default()
+    }
+    pub fn add_region(
+        &self,
+        base: MBPtrT,
+        size: MBPtrT,
+        perm: MBShareMemPerm,
+    ) -> Result<(), String> {
+        if size == 0 {
+            return Err(format!("region @ {:#x} is empty!", base));
+        }
+        if base.checked_add(size - 1).is_none() {
+            return Err(format!(
+                "region [{:#x} - +{:#x}] exceeds the address space!",
+                base, size
+            ));
+        }
+        let region = MBLocalRegion { base, size, perm };
+        let mut regions = self.regions.lock().unwrap();
+        if let Some(r) = regions
+            .iter()
+            .find(|r| r.base <= region.end_addr() && region.base <= r.end_addr())
+        {
+            return Err(format!(
+                "region [{:#x} - {:#x}] overlaps [{:#x} - {:#x}]!",
+                region.base,
+                region.end_addr(),
+                r.base,
+                r.end_addr()
+            ));
+        }
+        regions.push(region);
+        Ok(())
+    }
+    pub fn add_slice<T: Sized>(&self, data: &[T], perm: MBShareMemPerm) -> Result<(), String> {
+        self.add_region(
+            data.as_ptr() as MBPtrT,
+            std::mem::size_of_val(data) as MBPtrT,
+            perm,
+        )
+    }
+    pub fn remove_region(&self, base: MBPtrT) -> Result<(), String> {
+        let mut regions = self.regions.lock().unwrap();
+        let len = regions.len();
+        regions.retain(|r| r.base != base);
+        if regions.len() == len {
+            Err(format!("no region @ {:#x}!", base))
+        } else {
+            Ok(())
+        }
+    }
+    //number of bytes accessible from ptr up to len, error if it is less than len
+    fn accessible(
+        &self,
+        ptr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> (usize, Option<MBShareMemError>) {
+        let regions = self.regions.lock().unwrap();
+        let mut cur = ptr;
+        let mut done = 0;
+        while done < len {
+            let region = if let Some(r) = regions.iter().find(|r| r.in_range(cur)) {
+                r
+            } else {
+                return (done, Some(MBShareMemError::Unmapped(cur)));
+            };
+            if !region.perm.allows(access) {
+                return (
+                    done,
+                    Some(MBShareMemError::Permission(format!(
+                        "{:?} [{:#x} - {:#x}] @ {:#x} is not permitted!",
+                        access,
+                        region.base,
+                        region.end_addr(),
+                        cur
+                    ))),
+                );
+            }
+            let l = std::cmp::min(
+                (len - done) as MBPtrT,
+                (region.end_addr() - cur).saturating_add(1),
+            );
+            done += l as usize;
+            cur = cur.wrapping_add(l);
+        }
+        (done, None)
+    }
+}
+
+pub struct MBCheckedLocalPtrReaderWriter {
+    ptr: MBPtrT,
+    resolver: MBCheckedLocalPtrResolver,
+}
+impl MBCheckedLocalPtrReaderWriter {
+    fn checked(&self, len: usize, access: MBMemAccess) {
+        if let Err(e) = self.resolver.check_access(self.ptr, len, access) {
+            panic!("{}", e)
+        }
+    }
+    //bytes accessible from ptr, error if none of a non-empty access is
+    fn accessible(&self, len: usize, access: MBMemAccess) -> std::io::Result<usize> {
+        match self.resolver.accessible(self.ptr, len, access) {
+            (0, Some(e)) if len > 0 => Err(std::io::Error::from(e)),
+            (l, _) => Ok(l),
+        }
+    }
+}
+impl MBPtrReader for MBCheckedLocalPtrReaderWriter {
+    fn read_slice<T: Sized + Copy>(&mut self, data: &mut [T]) {
+        self.checked(std::mem::size_of_val(data), MBMemAccess::Read);
+        MBLocalPtrReader::new(self.ptr as *const u8).read_slice(data)
+    }
+    fn try_read_slice<T: Sized + Copy>(&mut self, data: &mut [T]) -> usize {
+        let len = std::mem::size_of_val(data);
+        let l = self.resolver.accessible(self.ptr, len, MBMemAccess::Read).0;
+        MBLocalPtrReader::new(self.ptr as *const u8).try_read_slice(unsafe {
+            std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, l)
+        })
+    }
+    fn read_sized<T: Sized>(&mut self, data: &mut T) {
+        self.checked(std::mem::size_of::<T>(), MBMemAccess::Read);
+        MBLocalPtrReader::new(self.ptr as *const u8).read_sized(data)
+    }
+}
+
+impl Read for MBCheckedLocalPtrReaderWriter {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let l = self.accessible(buf.len(), MBMemAccess::Read)?;
+        let l = MBLocalPtrReader::new(self.ptr as *const u8).read(&mut buf[..l])?;
+        self.ptr += l as MBPtrT;
+        Ok(l)
+    }
+}
+
+impl MBPtrWriter for MBCheckedLocalPtrReaderWriter {
+    fn write_slice<T: Sized + Copy>(&mut self, data: &[T]) {
+        self.checked(std::mem::size_of_val(data), MBMemAccess::Write);
+        MBLocalPtrWriter::new(self.ptr as *mut u8).write_slice(data)
+    }
+    fn try_write_slice<T: Sized + Copy>(&mut self, data: &[T]) -> usize {
+        let len = std::mem::size_of_val(data);
+        let l = self
+            .resolver
+            .accessible(self.ptr, len, MBMemAccess::Write)
+            .0;
+        MBLocalPtrWriter::new(self.ptr as *mut u8)
+            .try_write_slice(unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, l) })
+    }
+    fn write_sized<T: Sized>(&mut self, data: &T) {
+        self.checked(std::mem::size_of::<T>(), MBMemAccess::Write);
+        MBLocalPtrWriter::new(self.ptr as *mut u8).write_sized(data)
+    }
+}
+
+impl Write for MBCheckedLocalPtrReaderWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let l = self.accessible(buf.len(), MBMemAccess::Write)?;
+        let l = MBLocalPtrWriter::new(self.ptr as *mut u8).write(&buf[..l])?;
+        self.ptr += l as MBPtrT;
+        Ok(l)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MBPtrResolver for MBCheckedLocalPtrResolver {
+    type READER = MBCheckedLocalPtrReaderWriter;
+    type WRITER = MBCheckedLocalPtrReaderWriter;
+    fn reader<T: Sized>(&self, ptr: *const T) -> Self::READER {
+        MBCheckedLocalPtrReaderWriter {
+            ptr: ptr as MBPtrT,
+            resolver: self.clone(),
+        }
+    }
+    fn writer<T: Sized>(&self, ptr: *mut T) -> Self::WRITER {
+        MBCheckedLocalPtrReaderWriter {
+            ptr: ptr as MBPtrT,
+            resolver: self.clone(),
+        }
+    }
+    fn check_access(
+        &self,
+        ptr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        match self.accessible(ptr, len, access) {
+            (_, Some(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+    fn atomic(&self, ptr: MBPtrT, size: usize, op: MBAtomicOp) -> Result<u64, MBShareMemError> {
+        check_atomic_size(size);
+        self.check_access(ptr, size, MBMemAccess::Write)?;
+        unsafe { mb_atomic_ptr(ptr as *mut u8, size, op) }
+    }
 }
 
 pub struct MBSMPtrReaderWrtier<SM: MBShareMem> {
@@ -189,46 +639,109 @@ impl<SM: MBShareMem> MBSMPtrReaderWrtier<SM> {
             sm: sm.clone(),
         }
     }
+    fn lock_checked(&self, len: usize, access: MBMemAccess) -> MutexGuard<'_, SM> {
+        let sm = self.sm.lock().unwrap();
+        if let Err(e) = sm.check_access(self.ptr, len, access) {
+            panic!("{}", e)
+        }
+        sm
+    }
+}
+
+//bytes accessible from ptr up to len, error if none of a non-empty access is
+fn sm_accessible<SM: MBShareMem>(
+    sm: &SM,
+    ptr: MBPtrT,
+    len: usize,
+    access: MBMemAccess,
+) -> Result<usize, MBShareMemError> {
+    let e = match sm.check_access(ptr, len, access) {
+        Ok(()) => return Ok(len),
+        Err(e) => e,
+    };
+    //check_access only tells if all of them are, search the accessible prefix
+    let (mut ok, mut fail) = (0, len);
+    while fail - ok > 1 {
+        let mid = ok + (fail - ok) / 2;
+        if sm.check_access(ptr, mid, access).is_ok() {
+            ok = mid;
+        } else {
+            fail = mid;
+        }
+    }
+    if ok == 0 {
+        Err(e)
+    } else {
+        Ok(ok)
+    }
 }
 impl<SM: MBShareMem> MBPtrReader for MBSMPtrReaderWrtier<SM> {
     fn read_slice<T: Sized + Copy>(&mut self, data: &mut [T]) {
-        self.sm.lock().unwrap().read_slice(self.ptr, data);
+        self.lock_checked(std::mem::size_of_val(data), MBMemAccess::Read)
+            .read_slice(self.ptr, data);
     }
     fn try_read_slice<T: Sized + Copy>(&mut self, data: &mut [T]) -> usize {
-        self.sm.lock().unwrap().try_read_slice(self.ptr, data)
+        let len = std::mem::size_of_val(data);
+        let sm = self.sm.lock().unwrap();
+        let l = sm_accessible(&*sm, self.ptr, len, MBMemAccess::Read).unwrap_or(0);
+        sm.read(self.ptr, unsafe {
+            std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, l)
+        })
     }
     fn read_sized<T: Sized>(&mut self, data: &mut T) {
-        self.sm.lock().unwrap().read_sized(self.ptr, data);
+        self.lock_checked(std::mem::size_of::<T>(), MBMemAccess::Read)
+            .read_sized(self.ptr, data);
     }
 }
 
+//short reads and writes stop before the first inaccessible byte, the next one continues after them
 impl<SM: MBShareMem> Read for MBSMPtrReaderWrtier<SM> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        Ok(self.sm.lock().unwrap().read(self.ptr, buf))
+        let sm = self.sm.lock().unwrap();
+        let l = sm_accessible(&*sm, self.ptr, buf.len(), MBMemAccess::Read)?;
+        let l = sm.read(self.ptr, &mut buf[..l]);
+        self.ptr = self.ptr.wrapping_add(l as MBPtrT);
+        Ok(l)
     }
 }
 
 impl<SM: MBShareMem> MBPtrWriter for MBSMPtrReaderWrtier<SM> {
     fn write_slice<T: Sized + Copy>(&mut self, data: &[T]) {
-        self.sm.lock().unwrap().write_slice(self.ptr, data);
+        self.lock_checked(std::mem::size_of_val(data), MBMemAccess::Write)
+            .write_slice(self.ptr, data);
     }
     fn try_write_slice<T: Sized + Copy>(&mut self, data: &[T]) -> usize {
-        self.sm.lock().unwrap().try_write_slice(self.ptr, data)
+        let len = std::mem::size_of_val(data);
+        let mut sm = self.sm.lock().unwrap();
+        let l = sm_accessible(&*sm, self.ptr, len, MBMemAccess::Write).unwrap_or(0);
+        sm.write(self.ptr, unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, l)
+        })
     }
     fn write_sized<T: Sized>(&mut self, data: &T) {
-        self.sm.lock().unwrap().write_sized(self.ptr, data);
+        self.lock_checked(std::mem::size_of::<T>(), MBMemAccess::Write)
+            .write_sized(self.ptr, data);
     }
 }
 
 impl<SM: MBShareMem> Write for MBSMPtrReaderWrtier<SM> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        Ok(self.sm.lock().unwrap().write(self.ptr, buf))
+        let mut sm = self.sm.lock().unwrap();
+        let l = sm_accessible(&*sm, self.ptr, buf.len(), MBMemAccess::Write)?;
+        let l = sm.write(self.ptr, &buf[..l]);
+        self.ptr = self.ptr.wrapping_add(l as MBPtrT);
+        Ok(l)
     }
     fn flush(&mut self) -> std::io::Result<()> {
         Ok(())
     }
 }
 
+//resolvers of pointers into sm, the servers over sm take them
+pub trait MBShareMemResolver: MBPtrResolver {
+    type SM: MBShareMem;
+}
+
 pub struct MBSMPtrResolver<SM: MBShareMem> {
     sm: Arc<Mutex<SM>>,
 }
@@ -237,6 +750,9 @@ impl<SM: MBShareMem> MBSMPtrResolver<SM> {
         MBSMPtrResolver { sm: sm.clone() }
     }
 }
+impl<SM: MBShareMem> MBShareMemResolver for MBSMPtrResolver<SM> {
+    type SM = SM;
+}
 impl<SM: MBShareMem> MBPtrResolver for MBSMPtrResolver<SM> {
     type READER = MBSMPtrReaderWrtier<SM>;
     type WRITER = MBSMPtrReaderWrtier<SM>;
@@ -246,4 +762,49 @@ impl<SM: MBShareMem> MBPtrResolver for MBSMPtrResolver<SM> {
     fn writer<T: Sized>(&self, ptr: *mut T) -> Self::WRITER {
         MBSMPtrReaderWrtier::new(ptr as MBPtrT, &self.sm)
     }
+    fn check_access(
+        &self,
+        ptr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        self.sm.lock().unwrap().check_access(ptr, len, access)
+    }
+    fn atomic(&self, ptr: MBPtrT, size: usize, op: MBAtomicOp) -> Result<u64, MBShareMemError> {
+        let mut sm = self.sm.lock().unwrap();
+        sm.check_access(ptr, size, MBMemAccess::Write)?;
+        sm.atomic(ptr, size, op)
+    }
+    fn poll_load(&self, ptr: MBPtrT, len: usize, cx: &mut Context) -> Poll<()> {
+        self.sm.lock().unwrap().poll_load(ptr, len, cx)
+    }
+    //load the batches read_c_str_with reads until the one with nul
+    fn poll_load_c_str(&self, ptr: MBPtrT, limits: &MBStrLimits, cx: &mut Context) -> Poll<()> {
+        let mut next = ptr;
+        let mut remain = limits.max_len.saturating_add(1);
+        while remain > 0 {
+            let batch_len = std::cmp::min(limits.batch_len(), remain);
+            let mut sm = self.sm.lock().unwrap();
+            //a batch crossing the end of the memory is read short, see Read
+            let l = match sm_accessible(&*sm, next, batch_len, MBMemAccess::Read) {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            std::task::ready!(sm.poll_load(next, l, cx));
+            let mut buf = vec![0u8; l];
+            sm.read(next, &mut buf);
+            if l < batch_len || buf.contains(&0) {
+                break;
+            }
+            next += batch_len as MBPtrT;
+            remain -= batch_len;
+        }
+        Poll::Ready(())
+    }
+    fn poll_store(&self, cx: &mut Context) -> Poll<()> {
+        self.sm.lock().unwrap().poll_store(cx)
+    }
+    fn release(&self) {
+        self.sm.lock().unwrap().release()
+    }
 }
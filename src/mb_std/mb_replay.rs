@@ -0,0 +1,228 @@
+use super::mb_ptr_resolver::*;
+use super::mb_server::*;
+use super::mb_share_mem::*;
+use super::mb_trace::*;
+use crate::mb_channel::*;
+use crate::mb_rpcs::MBPtrT;
+use std::sync::{Arc, Mutex};
+
+//a request of the traced channel, with the memory it read and what the server returned
+#[derive(Debug, Clone)]
+pub struct MBReplayReq {
+    pub id: u32,
+    pub req: MBReqEntry,
+    //(addr, data) in the order read
+    pub reads: Vec<(MBPtrT, Vec<u8>)>,
+    //none if the trace ends before the request is done
+    pub resp: Option<MBTraceResp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBReplayDiff {
+    pub id: u32,
+    pub expected: Option<MBTraceResp>,
+    pub actual: MBTraceResp,
+}
+
+//re-drives a server from the requests recorded by MBTracer, without the device
+pub struct MBReplay {
+    channel: String,
+    reqs: Vec<MBReplayReq>,
+}
+
+impl MBReplay {
+    pub fn load(file: &str, channel: &str) -> Result<MBReplay, String> {
+        let mut reqs: Vec<MBReplayReq> = vec![];
+        for e in mb_decode_trace_events(file)? {
+            match e {
+                MBTraceEvent::Req {
+                    channel: ch,
+                    id,
+                    req,
+                } if ch == channel => reqs.push(MBReplayReq {
+                    id,
+                    req,
+                    reads: vec![],
+                    resp: None,
+                }),
+                MBTraceEvent::Resp {
+                    channel: ch,
+                    id,
+                    resp,
+                } if ch == channel => {
+                    if let Some(r) = reqs.iter_mut().rev().find(|r| r.id == id) {
+                        r.resp = Some(resp);
+                    }
+                }
+                MBTraceEvent::Access(MBTraceRecord {
+                    channel: Some(ch),
+                    req: Some(id),
+                    access: MBMemAccess::Read,
+                    addr,
+                    data,
+                    ..
+                }) if ch == channel => {
+                    if let Some(r) = reqs.iter_mut().rev().find(|r| r.id == id) {
+                        r.reads.push((addr, data));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if reqs.is_empty() {
+            return Err(format!("no request of {} in trace {}!", channel, file));
+        }
+        Ok(MBReplay {
+            channel: channel.to_string(),
+            reqs,
+        })
+    }
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+    pub fn reqs(&self) -> &[MBReplayReq] {
+        &self.reqs
+    }
+    //serves the requests in order, restoring what each one read into sm first, read-only memories included,
+    //returns the requests whose result differs from the trace
+    pub async fn run<SM: MBShareMem, R: MBPtrResolver>(
+        &self,
+        server: &MBSMServer<SM, R>,
+        sm: &Arc<Mutex<SM>>,
+    ) -> Result<Vec<MBReplayDiff>, String> {
+        let mut diffs = vec![];
+        for r in self.reqs.iter() {
+            {
+                let mut sm = sm.lock().unwrap();
+                for (addr, data) in r.reads.iter() {
+                    sm.backdoor_write(*addr, data)
+                        .map_err(|e| format!("replay {} #{} fail! {:?}", self.channel, r.id, e))?;
+                }
+            }
+            let actual = MBTraceResp::from(&server.do_cmd(&r.req).await);
+            if r.resp.as_ref() != Some(&actual) {
+                diffs.push(MBReplayDiff {
+                    id: r.id,
+                    expected: r.resp.clone(),
+                    actual,
+                })
+            }
+        }
+        Ok(diffs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::mb_rpcs::*;
+    use super::super::mb_sink::*;
+    use super::*;
+    use crate::mb_rpcs::*;
+    use std::fs;
+    #[test]
+    fn mb_replay_test() {
+        let file = std::env::temp_dir().join(format!("mb_replay_test_{}", std::process::id()));
+        let path = file.to_str().unwrap();
+        let tracer = MBTracer::new(path).unwrap();
+        let sm = Arc::new(Mutex::new(MBTraceShareMem::new(
+            MBSparseShareMem::new(0x1000, 0x1000),
+            &tracer,
+        )));
+        sm.lock().unwrap().write(0x1000, b"hello\n");
+        let server = MBSMServer::new("core0", &Arc::new(None), &sm);
+        server.set_tracer(Some(&tracer));
+        server.set_sinks(vec![Arc::new(MBCaptureSink::new())]);
+        let mut print = MBReqEntry::default();
+        MBPrint::new().put_req(
+            &MBStringArgs {
+                len: 6,
+                ptr: 0x1000,
+            },
+            &mut print,
+        );
+        let fs = MBReqEntry {
+            action: MBAction::FILEACCESS as u32,
+            words: 1,
+            ..Default::default()
+        };
+        async_std::task::block_on(async {
+            server.do_cmd(&print).await.unwrap();
+            server.do_cmd(&fs).await.unwrap_err();
+        });
+        tracer.flush().unwrap();
+
+        let replay = MBReplay::load(path, "core0").unwrap();
+        assert!(MBReplay::load(path, "core1").is_err());
+        fs::remove_file(&file).unwrap();
+        assert_eq!(replay.reqs().len(), 2);
+        //the resolver reads in batches, past the end of the string
+        let reads = &replay.reqs()[0].reads;
+        assert!(reads[0].0 == 0x1000 && reads[0].1.starts_with(b"hello\n"));
+        assert_eq!(
+            replay.reqs()[1].resp,
+            Some(MBTraceResp::Error(
+                "FILEACCESS failed: No mb_fs!".to_string()
+            ))
+        );
+
+        let sm = Arc::new(Mutex::new(MBSparseShareMem::new(0x1000, 0x1000)));
+        let server = MBSMServer::new("core0", &Arc::new(None), &sm);
+        let capture = Arc::new(MBCaptureSink::new());
+        server.set_sinks(vec![capture.clone()]);
+        let diffs = async_std::task::block_on(replay.run(&server, &sm)).unwrap();
+        assert!(diffs.is_empty());
+        assert_eq!(
+            capture.take(),
+            vec![("core0".to_string(), "hello\n".to_string())]
+        );
+        server.unregister_cmd(MBAction::PRINT);
+        let diffs = async_std::task::block_on(replay.run(&server, &sm)).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].id, 0);
+    }
+
+    #[test]
+    fn mb_replay_ro_test() {
+        let file = std::env::temp_dir().join(format!("mb_replay_ro_test_{}", std::process::id()));
+        let path = file.to_str().unwrap();
+        let rom_space = || {
+            let mut space = MBShareMemSpace::<MBSparseShareMem>::new();
+            let rom = Arc::new(Mutex::new(MBSparseShareMem::new(0x1000, 0x1000)));
+            space.add_named_mem("rom", &rom).unwrap();
+            space.set_perm("rom", MBShareMemPerm::RO).unwrap();
+            (space, rom)
+        };
+        let (space, rom) = rom_space();
+        rom.lock().unwrap().write(0x1000, b"hello\n");
+        let tracer = MBTracer::new(path).unwrap();
+        let sm = Arc::new(Mutex::new(MBTraceShareMem::new(space, &tracer)));
+        let server = MBSMServer::new("core0", &Arc::new(None), &sm);
+        server.set_tracer(Some(&tracer));
+        server.set_sinks(vec![Arc::new(MBCaptureSink::new())]);
+        let mut print = MBReqEntry::default();
+        MBPrint::new().put_req(
+            &MBStringArgs {
+                len: 6,
+                ptr: 0x1000,
+            },
+            &mut print,
+        );
+        async_std::task::block_on(server.do_cmd(&print)).unwrap();
+        tracer.flush().unwrap();
+        let replay = MBReplay::load(path, "core0").unwrap();
+        fs::remove_file(&file).unwrap();
+
+        //the string read from the read-only memory is restored
+        let sm = Arc::new(Mutex::new(rom_space().0));
+        let server = MBSMServer::new("core0", &Arc::new(None), &sm);
+        let capture = Arc::new(MBCaptureSink::new());
+        server.set_sinks(vec![capture.clone()]);
+        let diffs = async_std::task::block_on(replay.run(&server, &sm)).unwrap();
+        assert!(diffs.is_empty());
+        assert_eq!(
+            capture.take(),
+            vec![("core0".to_string(), "hello\n".to_string())]
+        );
+        assert!(sm.lock().unwrap().try_write(0x1000, b"h").is_err());
+    }
+}
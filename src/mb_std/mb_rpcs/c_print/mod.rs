@@ -1,13 +1,15 @@
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 mod sprintf;
-use super::{MBAsyncRPC, MBAsyncRPCError, MBAsyncRPCResult};
+use super::{rpc_failed, MBAsyncRPC, MBAsyncRPCError, MBAsyncRPCResult};
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_sink::*;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
 use sprintf::{vsprintf, ConversionSpecifier, ConversionType, Printf, PrintfError};
+use std::cell::RefCell;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::marker::PhantomData;
 use std::sync::Mutex;
@@ -15,13 +17,25 @@ use std::sync::Mutex;
 struct CPrintArg<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>> {
     arg: MBPtrT,
     r: &'a R,
+    limits: &'a MBStrLimits,
+    truncated: &'a RefCell<Vec<MBPtrT>>,
 }
 
 impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
     CPrintArg<'a, RA, WA, R>
 {
-    fn new(arg: MBPtrT, r: &'a R) -> Self {
-        CPrintArg { arg, r }
+    fn new(
+        arg: MBPtrT,
+        r: &'a R,
+        limits: &'a MBStrLimits,
+        truncated: &'a RefCell<Vec<MBPtrT>>,
+    ) -> Self {
+        CPrintArg {
+            arg,
+            r,
+            limits,
+            truncated,
+        }
     }
 }
 
@@ -49,9 +63,12 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
             ConversionType::String => {
                 let c_str = self
                     .r
-                    .read_c_str(self.arg as *const u8)
+                    .read_c_str_with(self.arg as *const u8, self.limits)
                     .map_err(|e| PrintfError::Other(e))?;
-                c_str.format(spec)
+                if c_str.truncated {
+                    self.truncated.borrow_mut().push(self.arg);
+                }
+                c_str.s.format(spec)
             }
             // float format
             ConversionType::SciFloatLower
@@ -89,6 +106,24 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
     }
 }
 
+//records the args formatted as strings, to load them before parsing
+struct CPrintProbeArg<'a> {
+    arg: MBPtrT,
+    c_strs: &'a RefCell<Vec<MBPtrT>>,
+}
+
+impl<'a> Printf for CPrintProbeArg<'a> {
+    fn format(&self, spec: &ConversionSpecifier) -> sprintf::Result<String> {
+        if spec.conversion_type == ConversionType::String {
+            self.c_strs.borrow_mut().push(self.arg);
+        }
+        Ok(String::new())
+    }
+    fn as_int(&self, spec: &ConversionSpecifier) -> Option<i32> {
+        self.arg.as_int(spec)
+    }
+}
+
 #[derive(Debug)]
 struct MBCFmtError {
     e: MBCParseError,
@@ -133,6 +168,8 @@ struct MBCStringFmtParser<
     pos: u32,
     args: &'a [MBPtrT],
     r: &'a R,
+    limits: &'a MBStrLimits,
+    truncated: RefCell<Vec<MBPtrT>>,
 }
 
 impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
@@ -141,37 +178,66 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
     fn new(
         args: &'a MBCStringArgs,
         r: &'a R,
+        limits: &'a MBStrLimits,
     ) -> Result<MBCStringFmtParser<'a, RA, WA, R>, MBCFmtError> {
         let pos = args.pos as u32;
-        let file = r.read_c_str(args.file as *const u8).map_err(|e| {
-            let fmt_e = MBCParseError::IOError(e);
-            MBCFmtError {
-                e: fmt_e,
-                file: "<unknown>".to_string(),
-                pos,
-            }
-        })?;
-        let fmt_str = r.read_c_str(args.fmt_str as *const u8).map_err(|e| {
-            let fmt_e = MBCParseError::IOError(e);
-            MBCFmtError {
-                e: fmt_e,
-                file: file.to_string(),
-                pos,
-            }
-        })?;
+        let truncated = RefCell::new(vec![]);
+        let file = r
+            .read_c_str_with(args.file as *const u8, limits)
+            .map_err(|e| {
+                let fmt_e = MBCParseError::IOError(e);
+                MBCFmtError {
+                    e: fmt_e,
+                    file: "<unknown>".to_string(),
+                    pos,
+                }
+            })?;
+        if file.truncated {
+            truncated.borrow_mut().push(args.file);
+        }
+        let file = file.s;
+        let fmt_str = r
+            .read_c_str_with(args.fmt_str as *const u8, limits)
+            .map_err(|e| {
+                let fmt_e = MBCParseError::IOError(e);
+                MBCFmtError {
+                    e: fmt_e,
+                    file: file.to_string(),
+                    pos,
+                }
+            })?;
+        if fmt_str.truncated {
+            truncated.borrow_mut().push(args.fmt_str);
+        }
+        let fmt_str = fmt_str.s;
         Ok(MBCStringFmtParser {
             fmt_str,
             file,
             pos,
             args: &args.args[..args.args_len()],
             r,
+            limits,
+            truncated,
         })
     }
+    fn c_str_args(&self) -> Vec<MBPtrT> {
+        let c_strs = RefCell::new(vec![]);
+        let args: Vec<CPrintProbeArg> = self
+            .args
+            .iter()
+            .map(|a| CPrintProbeArg {
+                arg: *a,
+                c_strs: &c_strs,
+            })
+            .collect();
+        let _ = vsprintf(&self.fmt_str, &args);
+        c_strs.into_inner()
+    }
     fn parse(&self) -> Result<String, MBCFmtError> {
         let args: Vec<CPrintArg<_, _, _>> = self
             .args
             .iter()
-            .map(|a| CPrintArg::new(*a, self.r))
+            .map(|a| CPrintArg::new(*a, self.r, self.limits, &self.truncated))
             .collect();
         vsprintf(&self.fmt_str, &args).map_err(|e| MBCFmtError {
             e: MBCParseError::ParseError(format!("{:?}", e)),
@@ -179,19 +245,32 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
             pos: self.pos,
         })
     }
+    fn truncated(&self) -> Vec<MBPtrT> {
+        self.truncated.borrow().clone()
+    }
 }
 
 pub struct MBCPrint<'a> {
     buf: Mutex<String>,
+    str_limits: Mutex<MBStrLimits>,
+    sinks: Mutex<MBOutputSinks>,
     _marker: PhantomData<&'a u8>,
 }
 impl<'a> MBCPrint<'a> {
     pub fn new() -> MBCPrint<'a> {
         MBCPrint {
             buf: Mutex::new(String::new()),
+            str_limits: Mutex::new(MBStrLimits::default()),
+            sinks: Mutex::new(mb_default_sinks()),
             _marker: PhantomData,
         }
     }
+    pub fn set_str_limits(&self, limits: MBStrLimits) {
+        *self.str_limits.lock().unwrap() = limits
+    }
+    pub fn set_sinks(&self, sinks: MBOutputSinks) {
+        *self.sinks.lock().unwrap() = sinks
+    }
 }
 
 impl<'a> MBRpc for MBCPrint<'a> {
@@ -226,7 +305,7 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
         server_name: &str,
         r: &R,
         req: &MBReqEntry,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
         let mut c_str_args = MBCStringArgs::default();
         c_str_args.len = req.words;
@@ -237,18 +316,41 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
         for (i, d) in c_str_args.args[..args_len].iter_mut().enumerate() {
             *d = req.args[3 + i];
         }
-        let parser = MBCStringFmtParser::new(&c_str_args, r).unwrap();
-        let s = parser.parse().unwrap();
+        let limits = *self.str_limits.lock().unwrap();
+        std::task::ready!(r.poll_load_c_str(c_str_args.file, &limits, cx));
+        std::task::ready!(r.poll_load_c_str(c_str_args.fmt_str, &limits, cx));
+        let parser = match MBCStringFmtParser::new(&c_str_args, r, &limits) {
+            Ok(parser) => parser,
+            Err(e) => return Poll::Ready(Err(rpc_failed(server_name, MBAction::CPRINT, e))),
+        };
+        for ptr in parser.c_str_args() {
+            std::task::ready!(r.poll_load_c_str(ptr, &limits, cx));
+        }
+        let s = match parser.parse() {
+            Ok(s) => s,
+            Err(e) => return Poll::Ready(Err(rpc_failed(server_name, MBAction::CPRINT, e))),
+        };
+        for ptr in parser.truncated() {
+            println!(
+                "[{}(server)] string @{:#x} is truncated to {} bytes!",
+                server_name, ptr, limits.max_len
+            );
+        }
         {
             let mut buf = self.buf.lock().unwrap();
             *buf += &s;
             if buf.ends_with('\n') {
-                print!("[{}] {}", server_name, buf);
+                for sink in self.sinks.lock().unwrap().iter() {
+                    sink.write(server_name, &buf);
+                }
                 buf.clear();
             }
         }
         Poll::Ready(Err(MBAsyncRPCError::NoResp))
     }
+    fn has_resp(&self, _req: &MBReqEntry) -> bool {
+        false
+    }
 }
 
 impl<'a> MBCPrint<'a> {
@@ -1,8 +1,9 @@
-use super::{MBAsyncRPC, MBAsyncRPCResult};
+use super::{check_access, rpc_failed, MBAsyncRPC, MBAsyncRPCResult};
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::MBMemAccess;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
@@ -27,7 +28,7 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
         server_name: &str,
         r: &R,
         req: &MBReqEntry,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
         let mut args = MBCallArgs {
             len: req.words - 1,
@@ -35,9 +36,29 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
             args: [0; MB_MAX_ARGS - 1],
         };
         args.args[..args.len as usize].copy_from_slice(&req.args[1..req.words as usize]);
-        let ch_name = std::ffi::CString::new(server_name).unwrap();
-        let method_name = r.read_c_str(args.method as *const u8).unwrap();
-        let method_name_c = std::ffi::CString::new(method_name).unwrap();
+        if let Err(e) = check_access(
+            server_name,
+            r,
+            MBAction::CALL,
+            args.method,
+            1,
+            MBMemAccess::Read,
+        ) {
+            return Poll::Ready(Err(e));
+        }
+        std::task::ready!(r.poll_load_c_str(args.method, &MBStrLimits::default(), cx));
+        let names = std::ffi::CString::new(server_name)
+            .map_err(|e| e.to_string())
+            .and_then(|ch_name| {
+                let method_name = r.read_c_str(args.method as *const u8)?;
+                std::ffi::CString::new(method_name)
+                    .map(|method_name_c| (ch_name, method_name_c))
+                    .map_err(|e| e.to_string())
+            });
+        let (ch_name, method_name_c) = match names {
+            Ok(names) => names,
+            Err(e) => return Poll::Ready(Err(rpc_failed(server_name, MBAction::CALL, e))),
+        };
         let mut resp = MBRespEntry::default();
         resp.words = 1;
         let mut status: u32 = 0;
@@ -55,7 +76,11 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
                     resp.rets = ret;
                     Poll::Ready(Ok(resp))
                 }
-                _ => panic!("Unkown status {} for CALL!", status),
+                _ => Poll::Ready(Err(rpc_failed(
+                    server_name,
+                    MBAction::CALL,
+                    format!("Unkown status {}!", status),
+                ))),
             }
         }
     }
@@ -22,6 +22,9 @@ impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA
             req.args[0] as u32,
         )))
     }
+    fn has_resp(&self, _req: &MBReqEntry) -> bool {
+        false
+    }
 }
 
 pub fn mb_exit<CH: MBChannelIf>(
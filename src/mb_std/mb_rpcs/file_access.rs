@@ -1,33 +1,58 @@
-use super::{MBAsyncRPC, MBAsyncRPCError, MBAsyncRPCResult};
+use super::{check_access, rpc_failed, MBAsyncRPC, MBAsyncRPCError, MBAsyncRPCResult};
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_fs::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::MBMemAccess;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
 impl MBFs {
     fn poll_open<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>(
         &self,
+        server_name: &str,
         r: &R,
         args: &MBFOpenArgs,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
+        std::task::ready!(r.poll_load_c_str(args.path, &MBStrLimits::default(), cx));
         let mut resp = MBRespEntry::default();
         resp.words = 1;
-        let path = r.read_c_str(args.path as *const u8).unwrap();
-        resp.rets = self.open(path.as_str(), args.flags).unwrap() as MBPtrT;
-        Poll::Ready(Ok(resp))
+        let fd = r.read_c_str(args.path as *const u8).and_then(|path| {
+            self.open(path.as_str(), args.flags)
+                .map_err(|e| e.to_string())
+        });
+        match fd {
+            Ok(fd) => {
+                resp.rets = fd as MBPtrT;
+                Poll::Ready(Ok(resp))
+            }
+            Err(e) => Poll::Ready(Err(rpc_failed(server_name, MBAction::FILEACCESS, e))),
+        }
     }
-    fn poll_close(&self, fd: u32) -> Poll<MBAsyncRPCResult> {
-        self.close(fd).unwrap();
+    fn poll_close(&self, server_name: &str, fd: u32) -> Poll<MBAsyncRPCResult> {
+        if let Err(e) = self.close(fd) {
+            return Poll::Ready(Err(rpc_failed(server_name, MBAction::FILEACCESS, e)));
+        }
         Poll::Ready(Err(MBAsyncRPCError::NoResp))
     }
     fn poll_read<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>(
         &self,
+        server_name: &str,
         r: &R,
         args: &MBFReadArgs,
     ) -> Poll<MBAsyncRPCResult> {
+        if let Err(e) = check_access(
+            server_name,
+            r,
+            MBAction::FILEACCESS,
+            args.ptr,
+            args.len as usize,
+            MBMemAccess::Write,
+        ) {
+            return Poll::Ready(Err(e));
+        }
         match self.read(r, args.fd, args.ptr as *mut u8, args.len as usize) {
             Ok(len) => {
                 let mut resp = MBRespEntry::default();
@@ -36,14 +61,27 @@ impl MBFs {
                 Poll::Ready(Ok(resp))
             }
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Poll::Pending,
-            Err(e) => panic!("{:?}", e),
+            Err(e) => Poll::Ready(Err(rpc_failed(server_name, MBAction::FILEACCESS, e))),
         }
     }
     fn poll_write<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>(
         &self,
+        server_name: &str,
         r: &R,
         args: &MBFWriteArgs,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
+        if let Err(e) = check_access(
+            server_name,
+            r,
+            MBAction::FILEACCESS,
+            args.ptr,
+            args.len as usize,
+            MBMemAccess::Read,
+        ) {
+            return Poll::Ready(Err(e));
+        }
+        std::task::ready!(r.poll_load(args.ptr, args.len as usize, cx));
         match self.write(r, args.fd, args.ptr as *const u8, args.len as usize) {
             Ok(len) => {
                 let mut resp = MBRespEntry::default();
@@ -52,14 +90,19 @@ impl MBFs {
                 Poll::Ready(Ok(resp))
             }
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Poll::Pending,
-            Err(e) => panic!("{:?}", e),
+            Err(e) => Poll::Ready(Err(rpc_failed(server_name, MBAction::FILEACCESS, e))),
         }
     }
-    fn poll_seek(&self, args: &MBFSeekArgs) -> Poll<MBAsyncRPCResult> {
+    fn poll_seek(&self, server_name: &str, args: &MBFSeekArgs) -> Poll<MBAsyncRPCResult> {
         let mut resp = MBRespEntry::default();
         resp.words = 1;
-        resp.rets = self.seek(args.fd, args.pos as u64).unwrap() as MBPtrT;
-        Poll::Ready(Ok(resp))
+        match self.seek(args.fd, args.pos as u64) {
+            Ok(pos) => {
+                resp.rets = pos as MBPtrT;
+                Poll::Ready(Ok(resp))
+            }
+            Err(e) => Poll::Ready(Err(rpc_failed(server_name, MBAction::FILEACCESS, e))),
+        }
     }
 }
 
@@ -68,10 +111,10 @@ impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA
 {
     fn poll_cmd(
         &self,
-        _server_name: &str,
+        server_name: &str,
         r: &R,
         req: &MBReqEntry,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
         let file_action = req.args[0] as u32;
         match file_action {
@@ -80,16 +123,18 @@ impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA
                     path: req.args[1],
                     flags: req.args[2] as u32,
                 };
-                self.poll_open(r, &args)
+                self.poll_open(server_name, r, &args, cx)
+            }
+            a if a == MBFileAction::CLOSE as u32 => {
+                self.poll_close(server_name, req.args[1] as u32)
             }
-            a if a == MBFileAction::CLOSE as u32 => self.poll_close(req.args[1] as u32),
             a if a == MBFileAction::READ as u32 => {
                 let args = MBFReadArgs {
                     fd: req.args[1] as u32,
                     ptr: req.args[2],
                     len: req.args[3],
                 };
-                self.poll_read(r, &args)
+                self.poll_read(server_name, r, &args)
             }
             a if a == MBFileAction::WRITE as u32 => {
                 let args = MBFWriteArgs {
@@ -97,16 +142,20 @@ impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA
                     ptr: req.args[2],
                     len: req.args[3],
                 };
-                self.poll_write(r, &args)
+                self.poll_write(server_name, r, &args, cx)
             }
             a if a == MBFileAction::SEEK as u32 => {
                 let args = MBFSeekArgs {
                     fd: req.args[1] as u32,
                     pos: req.args[2],
                 };
-                self.poll_seek(&args)
+                self.poll_seek(server_name, &args)
             }
-            _ => panic!("Unkown MBFileAction {:#x}!", file_action),
+            _ => Poll::Ready(Err(rpc_failed(
+                server_name,
+                MBAction::FILEACCESS,
+                format!("Unkown MBFileAction {:#x}!", file_action),
+            ))),
         }
     }
 }
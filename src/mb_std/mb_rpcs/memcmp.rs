@@ -1,8 +1,9 @@
-use super::{MBAsyncRPC, MBAsyncRPCResult};
+use super::{check_access, MBAsyncRPC, MBAsyncRPCResult};
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::MBMemAccess;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
@@ -12,16 +13,31 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
 {
     fn poll_cmd(
         &self,
-        _server_name: &str,
+        server_name: &str,
         r: &R,
         req: &MBReqEntry,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
         let args = MBMemCmpArgs {
             s1: req.args[0],
             s2: req.args[1],
             len: req.args[2],
         };
+        for ptr in [args.s1, args.s2] {
+            if let Err(e) = check_access(
+                server_name,
+                r,
+                MBAction::MEMCMP,
+                ptr,
+                args.len as usize,
+                MBMemAccess::Read,
+            ) {
+                return Poll::Ready(Err(e));
+            }
+        }
+        for ptr in [args.s1, args.s2] {
+            std::task::ready!(r.poll_load(ptr, args.len as usize, cx));
+        }
         let mut resp = MBRespEntry::default();
         resp.words = 1;
         for i in 0..args.len as MBPtrT {
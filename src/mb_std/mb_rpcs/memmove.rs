@@ -1,8 +1,9 @@
-use super::{MBAsyncRPC, MBAsyncRPCResult};
+use super::{check_access, MBAsyncRPC, MBAsyncRPCResult};
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::MBMemAccess;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
@@ -12,16 +13,38 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
 {
     fn poll_cmd(
         &self,
-        _server_name: &str,
+        server_name: &str,
         r: &R,
         req: &MBReqEntry,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
         let args = MBMemMoveArgs {
             dest: req.args[0],
             src: req.args[1],
             len: req.args[2],
         };
+        let checked = check_access(
+            server_name,
+            r,
+            MBAction::MEMMOVE,
+            args.src,
+            args.len as usize,
+            MBMemAccess::Read,
+        )
+        .and_then(|_| {
+            check_access(
+                server_name,
+                r,
+                MBAction::MEMMOVE,
+                args.dest,
+                args.len as usize,
+                MBMemAccess::Write,
+            )
+        });
+        if let Err(e) = checked {
+            return Poll::Ready(Err(e));
+        }
+        std::task::ready!(r.poll_load(args.src, args.len as usize, cx));
         let mut buf = vec![0u8; args.len as usize];
         r.read_slice(args.src as *const u8, &mut buf);
         r.write_slice(args.dest as *mut u8, &buf);
@@ -1,8 +1,9 @@
-use super::{MBAsyncRPC, MBAsyncRPCResult};
+use super::{check_access, MBAsyncRPC, MBAsyncRPCResult};
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::MBMemAccess;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
@@ -12,7 +13,7 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
 {
     fn poll_cmd(
         &self,
-        _server_name: &str,
+        server_name: &str,
         r: &R,
         req: &MBReqEntry,
         _cx: &mut Context,
@@ -22,6 +23,16 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
             data: req.args[1],
             len: req.args[2],
         };
+        if let Err(e) = check_access(
+            server_name,
+            r,
+            MBAction::MEMSET,
+            args.dest,
+            args.len as usize,
+            MBMemAccess::Write,
+        ) {
+            return Poll::Ready(Err(e));
+        }
         let data = args.data as u8;
         for i in 0..args.len as MBPtrT {
             r.write_sized((args.dest + i) as *mut u8, &data);
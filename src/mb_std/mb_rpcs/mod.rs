@@ -16,22 +16,104 @@ pub use memset::*;
 pub use print::*;
 
 use crate::mb_channel::*;
-use crate::mb_rpcs::MBAction;
+use crate::mb_rpcs::{MBAction, MBPtrT};
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::{MBMemAccess, MBShareMemError};
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
+use std::fmt::{self, Display, Formatter};
 use std::pin::Pin;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub enum MBAsyncRPCError {
     NoResp,
     Stop(String, u32),
-    Illegal(MBAction),
+    Illegal(u32),
+    Violation(String, MBAction, MBPtrT, MBShareMemError),
+    //server name, action id and what went wrong in the handler
+    Failed(String, u32, String),
+}
+
+impl Display for MBAsyncRPCError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MBAsyncRPCError::NoResp => write!(f, "no response"),
+            MBAsyncRPCError::Stop(_, code) => write!(f, "stopped with code {}", code),
+            MBAsyncRPCError::Illegal(action) => write!(f, "Illegal cmd {:#x}", action),
+            MBAsyncRPCError::Violation(_, action, addr, e) => {
+                write!(f, "access violation in {:?} @ {:#x}: {}", action, addr, e)
+            }
+            MBAsyncRPCError::Failed(_, action, e) => {
+                write!(f, "{} failed: {}", mb_action_name(*action), e)
+            }
+        }
+    }
+}
+
+fn mb_action_name(action: u32) -> String {
+    match MBAction::from(action) {
+        MBAction::OTHER => format!("action {:#x}", action),
+        a => format!("{:?}", a),
+    }
 }
 
 pub type MBAsyncRPCResult = Result<MBRespEntry, MBAsyncRPCError>;
 
+//response to a failed request under MBErrorPolicy::Log, rets is -1
+pub const MB_ERROR_RESP: MBRespEntry = MBRespEntry {
+    words: 0,
+    rets: MBPtrT::MAX,
+};
+
+pub(crate) fn rpc_failed<E: Display>(server_name: &str, action: MBAction, e: E) -> MBAsyncRPCError {
+    MBAsyncRPCError::Failed(server_name.to_string(), action as u32, e.to_string())
+}
+
+//what a server does on errors other than NoResp and Stop
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MBErrorPolicy {
+    #[default]
+    Panic,
+    //report and serve the next request, the failed one is responded with MB_ERROR_RESP
+    //if the device waits for it
+    Log,
+    //report and stop the channel with the exit code
+    StopChannel(u32),
+    //report and stop all the channels with the exit code
+    StopAll(u32),
+}
+
+impl MBErrorPolicy {
+    //the exit code if the channel should stop
+    pub fn apply(&self, server_name: &str, e: &MBAsyncRPCError) -> Option<u32> {
+        match self {
+            MBErrorPolicy::Panic => panic!("[{}(server)] {}", server_name, e),
+            MBErrorPolicy::Log => {
+                println!("[{}(server)] {}", server_name, e);
+                None
+            }
+            MBErrorPolicy::StopChannel(code) | MBErrorPolicy::StopAll(code) => {
+                println!("[{}(server)] {}, stopped!", server_name, e);
+                Some(*code)
+            }
+        }
+    }
+}
+
+fn check_access<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>(
+    server_name: &str,
+    r: &R,
+    action: MBAction,
+    ptr: MBPtrT,
+    len: usize,
+    access: MBMemAccess,
+) -> Result<(), MBAsyncRPCError> {
+    r.check_access(ptr, len, access)
+        .map_err(|e| MBAsyncRPCError::Violation(server_name.to_string(), action, ptr, e))
+}
+
 pub trait MBAsyncRPC<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>> {
     fn poll_cmd(
         &self,
@@ -40,6 +122,10 @@ pub trait MBAsyncRPC<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER =
         req: &MBReqEntry,
         cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult>;
+    //whether the device waits for a response to req
+    fn has_resp(&self, _req: &MBReqEntry) -> bool {
+        true
+    }
     fn do_cmd<'a>(
         &'a self,
         server_name: &'a str,
@@ -54,10 +140,35 @@ pub trait MBAsyncRPC<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER =
             server_name,
             r,
             req,
+            done: None,
         }
     }
 }
 
+impl<
+        RA: MBPtrReader,
+        WA: MBPtrWriter,
+        R: MBPtrResolver<READER = RA, WRITER = WA>,
+        T: MBAsyncRPC<RA, WA, R> + ?Sized,
+    > MBAsyncRPC<RA, WA, R> for Arc<T>
+{
+    fn poll_cmd(
+        &self,
+        server_name: &str,
+        r: &R,
+        req: &MBReqEntry,
+        cx: &mut Context,
+    ) -> Poll<MBAsyncRPCResult> {
+        (**self).poll_cmd(server_name, r, req, cx)
+    }
+    fn has_resp(&self, req: &MBReqEntry) -> bool {
+        (**self).has_resp(req)
+    }
+}
+
+//handler registered for an action id in the servers
+pub type MBAsyncRPCHandler<RA, WA, R> = Arc<dyn MBAsyncRPC<RA, WA, R> + Send + Sync>;
+
 pub trait CustomAsycRPC<
     RA: MBPtrReader,
     WA: MBPtrWriter,
@@ -78,6 +189,8 @@ pub struct MBAsyncRPCFuture<
     server_name: &'a str,
     r: &'a R,
     req: &'a MBReqEntry,
+    //result kept while storing what the handler wrote
+    done: Option<MBAsyncRPCResult>,
 }
 
 impl<
@@ -89,7 +202,13 @@ impl<
     > Future for MBAsyncRPCFuture<'a, RA, WA, R, RPC>
 {
     type Output = MBAsyncRPCResult;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        self.rpc.poll_cmd(self.server_name, self.r, self.req, cx)
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.done.is_none() {
+            let ret = std::task::ready!(self.rpc.poll_cmd(self.server_name, self.r, self.req, cx));
+            self.done = Some(ret);
+        }
+        std::task::ready!(self.r.poll_store(cx));
+        self.r.release();
+        Poll::Ready(self.done.take().unwrap())
     }
 }
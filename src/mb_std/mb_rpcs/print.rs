@@ -1,8 +1,10 @@
-use super::{MBAsyncRPC, MBAsyncRPCResult};
+use super::{check_access, rpc_failed, MBAsyncRPC, MBAsyncRPCResult};
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
 use crate::mb_std::mb_async_channel::*;
 use crate::mb_std::mb_ptr_resolver::*;
+use crate::mb_std::mb_share_mem::MBMemAccess;
+use crate::mb_std::mb_sink::*;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
@@ -11,15 +13,25 @@ use std::sync::Mutex;
 
 pub struct MBPrint<'a> {
     buf: Mutex<String>,
+    str_limits: Mutex<MBStrLimits>,
+    sinks: Mutex<MBOutputSinks>,
     _marker: PhantomData<&'a u8>,
 }
 impl<'a> MBPrint<'a> {
     pub fn new() -> MBPrint<'a> {
         MBPrint {
             buf: Mutex::new(String::new()),
+            str_limits: Mutex::new(MBStrLimits::default()),
+            sinks: Mutex::new(mb_default_sinks()),
             _marker: PhantomData,
         }
     }
+    pub fn set_str_limits(&self, limits: MBStrLimits) {
+        *self.str_limits.lock().unwrap() = limits
+    }
+    pub fn set_sinks(&self, sinks: MBOutputSinks) {
+        *self.sinks.lock().unwrap() = sinks
+    }
 }
 impl<'a> MBRpc for MBPrint<'a> {
     type REQ = &'a MBStringArgs;
@@ -45,21 +57,48 @@ impl<'a, RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER
         server_name: &str,
         r: &R,
         req: &MBReqEntry,
-        _cx: &mut Context,
+        cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
         let str_args = MBStringArgs {
             len: req.args[0] as u32,
             ptr: req.args[1],
         };
-        let s = r.read_str(&str_args).expect(&format!(
-            "[{}](server), string @{:#x}:",
-            server_name, req.args[1]
-        ));
+        let limits = *self.str_limits.lock().unwrap();
+        let len = std::cmp::min(str_args.len as usize, limits.max_len);
+        if let Err(e) = check_access(
+            server_name,
+            r,
+            MBAction::PRINT,
+            str_args.ptr,
+            len,
+            MBMemAccess::Read,
+        ) {
+            return Poll::Ready(Err(e));
+        }
+        std::task::ready!(r.poll_load(str_args.ptr, len, cx));
+        let s = match r.read_str_with(&str_args, &limits) {
+            Ok(s) => s,
+            Err(e) => {
+                return Poll::Ready(Err(rpc_failed(
+                    server_name,
+                    MBAction::PRINT,
+                    format!("string @{:#x}: {}", req.args[1], e),
+                )))
+            }
+        };
+        if s.truncated {
+            println!(
+                "[{}(server)] string @{:#x} is truncated to {} of {} bytes!",
+                server_name, str_args.ptr, len, str_args.len
+            );
+        }
         {
             let mut buf = self.buf.lock().unwrap();
-            *buf += &s;
+            *buf += &s.s;
             if buf.ends_with('\n') {
-                print!("[{}] {}", server_name, buf);
+                for sink in self.sinks.lock().unwrap().iter() {
+                    sink.write(server_name, &buf);
+                }
                 buf.clear();
             }
         }
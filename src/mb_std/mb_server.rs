@@ -1,49 +1,64 @@
+use super::mb_async_channel::*;
 use super::mb_fs::*;
 use super::mb_ptr_resolver::*;
 use super::mb_rpcs::*;
 use super::mb_share_mem::*;
+use super::mb_sink::*;
+use super::mb_trace::*;
 use crate::mb_channel::*;
 use crate::mb_rpcs::*;
+use async_std::future::poll_fn;
 use async_std::prelude::*;
 use async_std::task::Context;
 use async_std::task::Poll;
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
-struct MBServerInner<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>> {
-    exit: MBExit,
-    print: MBPrint<'static>,
-    cprint: MBCPrint<'static>,
-    memmove: MBMemMove<'static>,
-    memset: MBMemSet<'static>,
-    memcmp: MBMemCmp<'static>,
-    call: MBCall<'static>,
-    fs: Arc<Option<MBFs>>,
-    other_cmds: Mutex<Vec<Box<dyn CustomAsycRPC<RA, WA, R>>>>,
+//the OTHER action, dispatched by the id in args[0]
+struct MBOtherCmds<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>> {
+    cmds: Mutex<Vec<Box<dyn CustomAsycRPC<RA, WA, R>>>>,
 }
+
 impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
-    MBServerInner<RA, WA, R>
+    MBAsyncRPC<RA, WA, R> for MBOtherCmds<RA, WA, R>
 {
-    fn new(fs: &Arc<Option<MBFs>>) -> MBServerInner<RA, WA, R> {
-        MBServerInner {
-            exit: MBExit,
-            print: MBPrint::new(),
-            cprint: MBCPrint::new(),
-            memmove: MBMemMove::new(),
-            memset: MBMemSet::new(),
-            memcmp: MBMemCmp::new(),
-            call: MBCall::new(),
-            fs: fs.clone(),
-            other_cmds: Mutex::new(vec![]),
+    fn poll_cmd(
+        &self,
+        server_name: &str,
+        r: &R,
+        req: &MBReqEntry,
+        cx: &mut Context,
+    ) -> Poll<MBAsyncRPCResult> {
+        let cmds = self.cmds.lock().unwrap();
+        for cmd in cmds.iter() {
+            if cmd.is_me(req.args[0] as u32) {
+                return cmd.poll_cmd(server_name, r, req, cx);
+            }
         }
+        Poll::Ready(Err(rpc_failed(
+            server_name,
+            MBAction::OTHER,
+            format!("OTHER action {:#x} is not support!", req.args[0]),
+        )))
     }
-    fn add_cmd<C: CustomAsycRPC<RA, WA, R> + 'static>(&self, cmd: C) {
-        self.other_cmds.lock().unwrap().push(Box::new(cmd));
+    fn has_resp(&self, req: &MBReqEntry) -> bool {
+        let cmds = self.cmds.lock().unwrap();
+        cmds.iter()
+            .find(|cmd| cmd.is_me(req.args[0] as u32))
+            .map_or(true, |cmd| cmd.has_resp(req))
     }
 }
 
+struct MBServerFs(Arc<Option<MBFs>>);
+
 impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
-    MBAsyncRPC<RA, WA, R> for MBServerInner<RA, WA, R>
+    MBAsyncRPC<RA, WA, R> for MBServerFs
 {
     fn poll_cmd(
         &self,
@@ -52,46 +67,375 @@ impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA
         req: &MBReqEntry,
         cx: &mut Context,
     ) -> Poll<MBAsyncRPCResult> {
-        match req.action {
-            MBAction::EXIT => self.exit.poll_cmd(server_name, r, &req, cx),
-            MBAction::PRINT => self.print.poll_cmd(server_name, r, &req, cx),
-            MBAction::CPRINT => self.cprint.poll_cmd(server_name, r, &req, cx),
-            MBAction::MEMMOVE => self.memmove.poll_cmd(server_name, r, &req, cx),
-            MBAction::MEMSET => self.memset.poll_cmd(server_name, r, &req, cx),
-            MBAction::MEMCMP => self.memcmp.poll_cmd(server_name, r, &req, cx),
-            MBAction::CALL => self.call.poll_cmd(server_name, r, &req, cx),
-            MBAction::FILEACCESS => {
-                if let Some(fs) = &*self.fs {
-                    fs.poll_cmd(server_name, r, &req, cx)
-                } else {
-                    panic!("No mb_fs in {}!", server_name)
+        if let Some(fs) = &*self.0 {
+            fs.poll_cmd(server_name, r, req, cx)
+        } else {
+            Poll::Ready(Err(rpc_failed(
+                server_name,
+                MBAction::FILEACCESS,
+                "No mb_fs!",
+            )))
+        }
+    }
+    fn has_resp(&self, req: &MBReqEntry) -> bool {
+        req.args[0] as u32 != MBFileAction::CLOSE as u32
+    }
+}
+
+//wraps every request served, the first added is the outermost
+pub trait MBMiddleware: Send + Sync {
+    //before dispatching, may rewrite the request or answer it without the handler
+    fn before(&self, _server_name: &str, _req: &mut MBReqEntry) -> Option<MBAsyncRPCResult> {
+        None
+    }
+    //after the handler or an inner middleware answered, may replace the result
+    fn after(&self, _server_name: &str, _req: &MBReqEntry, _ret: &mut MBAsyncRPCResult) {}
+}
+
+//how a request is scheduled against the ones still pending, responses are always in order
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MBOrdering {
+    //starts after all earlier requests are done, later requests are not received until it starts
+    //the default of all actions, PRINT and CPRINT included
+    #[default]
+    Ordered,
+    //starts once received, even if earlier requests are pending, opted in by set_ordering
+    Unordered,
+}
+
+//shared by the channels of a system, fired on MBErrorPolicy::StopAll
+#[derive(Clone)]
+pub struct MBStopAll {
+    tx: Arc<Mutex<Option<oneshot::Sender<u32>>>>,
+    rx: Shared<oneshot::Receiver<u32>>,
+}
+
+impl MBStopAll {
+    pub fn new() -> MBStopAll {
+        let (tx, rx) = oneshot::channel();
+        MBStopAll {
+            tx: Arc::new(Mutex::new(Some(tx))),
+            rx: rx.shared(),
+        }
+    }
+    pub fn stop(&self, code: u32) {
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            let _ = tx.send(code);
+        }
+    }
+    fn poll_stopped(&mut self, cx: &mut Context) -> Poll<u32> {
+        match self.rx.poll_unpin(cx) {
+            Poll::Ready(Ok(code)) => Poll::Ready(code),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl Default for MBStopAll {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum MBServeEvent {
+    Req(MBReqEntry),
+    Done(u64, MBAsyncRPCResult),
+    Stop(u32),
+}
+
+struct MBServerInner<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>> {
+    print: Arc<MBPrint<'static>>,
+    cprint: Arc<MBCPrint<'static>>,
+    //commands added by add_cmd, serving OTHER unless it is registered
+    other_cmds: MBOtherCmds<RA, WA, R>,
+    handlers: Mutex<HashMap<u32, MBAsyncRPCHandler<RA, WA, R>>>,
+    middlewares: Mutex<Vec<Arc<dyn MBMiddleware>>>,
+    orderings: Mutex<HashMap<u32, MBOrdering>>,
+    //records the requests and responses, for MBReplay
+    tracer: Mutex<Option<MBTracer>>,
+    next_req: AtomicU32,
+}
+impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
+    MBServerInner<RA, WA, R>
+{
+    fn new(fs: &Arc<Option<MBFs>>) -> MBServerInner<RA, WA, R> {
+        let inner = MBServerInner {
+            print: Arc::new(MBPrint::new()),
+            cprint: Arc::new(MBCPrint::new()),
+            other_cmds: MBOtherCmds {
+                cmds: Mutex::new(vec![]),
+            },
+            handlers: Mutex::new(HashMap::new()),
+            middlewares: Mutex::new(vec![]),
+            orderings: Mutex::new(HashMap::new()),
+            tracer: Mutex::new(None),
+            next_req: AtomicU32::new(0),
+        };
+        inner.register_cmd(MBAction::EXIT, MBExit);
+        inner.register_cmd(MBAction::PRINT, inner.print.clone());
+        inner.register_cmd(MBAction::CPRINT, inner.cprint.clone());
+        inner.register_cmd(MBAction::MEMMOVE, MBMemMove::new());
+        inner.register_cmd(MBAction::MEMSET, MBMemSet::new());
+        inner.register_cmd(MBAction::MEMCMP, MBMemCmp::new());
+        inner.register_cmd(MBAction::CALL, MBCall::new());
+        inner.register_cmd(MBAction::FILEACCESS, MBServerFs(fs.clone()));
+        inner
+    }
+    fn add_cmd<C: CustomAsycRPC<RA, WA, R> + 'static>(&self, cmd: C) {
+        self.other_cmds.cmds.lock().unwrap().push(Box::new(cmd));
+    }
+    fn register_cmd<C: MBAsyncRPC<RA, WA, R> + Send + Sync + 'static>(
+        &self,
+        action: impl Into<u32>,
+        cmd: C,
+    ) -> Option<MBAsyncRPCHandler<RA, WA, R>> {
+        self.handlers
+            .lock()
+            .unwrap()
+            .insert(action.into(), Arc::new(cmd))
+    }
+    fn unregister_cmd(&self, action: impl Into<u32>) -> Option<MBAsyncRPCHandler<RA, WA, R>> {
+        self.handlers.lock().unwrap().remove(&action.into())
+    }
+    fn set_str_limits(&self, limits: MBStrLimits) {
+        self.print.set_str_limits(limits);
+        self.cprint.set_str_limits(limits);
+    }
+    fn set_sinks(&self, sinks: MBOutputSinks) {
+        self.print.set_sinks(sinks.clone());
+        self.cprint.set_sinks(sinks);
+    }
+    fn add_middleware<M: MBMiddleware + 'static>(&self, middleware: M) {
+        self.middlewares.lock().unwrap().push(Arc::new(middleware));
+    }
+    fn set_ordering(&self, action: impl Into<u32>, ordering: MBOrdering) {
+        self.orderings
+            .lock()
+            .unwrap()
+            .insert(action.into(), ordering);
+    }
+    fn ordering(&self, action: impl Into<u32>) -> MBOrdering {
+        self.orderings
+            .lock()
+            .unwrap()
+            .get(&action.into())
+            .copied()
+            .unwrap_or_default()
+    }
+    fn set_tracer(&self, tracer: Option<&MBTracer>) {
+        *self.tracer.lock().unwrap() = tracer.cloned();
+    }
+    async fn serve_channel<CH: MBChannelIf>(
+        &self,
+        server_name: &str,
+        r: &R,
+        receiver: &MBAsyncReceiver<CH>,
+        policy: MBErrorPolicy,
+        mut stop_all: MBStopAll,
+    ) -> (String, u32) {
+        let trace_ch: Arc<str> = Arc::from(server_name);
+        let start = |id: u64, req: MBReqEntry| {
+            let trace_ch = trace_ch.clone();
+            async move {
+                let ret = mb_trace_scope(
+                    &trace_ch,
+                    Some(req.action),
+                    self.serve_cmd(server_name, r, &req),
+                )
+                .await;
+                (id, ret)
+            }
+        };
+        let mut next_id = 0u64;
+        //(id, action, has_resp, result) in the order received
+        let mut pending: VecDeque<(u64, u32, bool, Option<MBAsyncRPCResult>)> = VecDeque::new();
+        //an ordered request waiting for the earlier ones
+        let mut waiting = None;
+        let mut running = FuturesUnordered::new();
+        //kept across events, receiving is not cancelled half way
+        let mut recv = None;
+        loop {
+            if running.is_empty() {
+                if let Some((id, req)) = waiting.take() {
+                    running.push(start(id, req));
                 }
             }
-            MBAction::OTHER => {
-                let other_cmds = self.other_cmds.lock().unwrap();
-                for cmd in other_cmds.iter() {
-                    if cmd.is_me(req.args[0] as u32) {
-                        return cmd.poll_cmd(server_name, r, &req, cx);
+            while let Some((_, _, _, Some(_))) = pending.front() {
+                let (_, action, has_resp, ret) = pending.pop_front().unwrap();
+                match ret.unwrap() {
+                    Ok(resp) => {
+                        mb_trace_scope(
+                            &trace_ch,
+                            Some(action),
+                            receiver.send_resp(resp, server_name),
+                        )
+                        .await
+                    }
+                    Err(MBAsyncRPCError::NoResp) => {}
+                    Err(MBAsyncRPCError::Stop(server_name, code)) => return (server_name, code),
+                    Err(e) => {
+                        if let Some(code) = policy.apply(server_name, &e) {
+                            if let MBErrorPolicy::StopAll(_) = policy {
+                                stop_all.stop(code);
+                            }
+                            return (server_name.to_string(), code);
+                        }
+                        //the device would wait for it forever
+                        if has_resp {
+                            mb_trace_scope(
+                                &trace_ch,
+                                Some(action),
+                                receiver.send_resp(MB_ERROR_RESP, server_name),
+                            )
+                            .await
+                        }
                     }
                 }
-                panic!("OTHER action {:#x} is not support!", req.args[0])
             }
-            _ => Poll::Ready(Err(MBAsyncRPCError::Illegal(req.action))),
+            let accept = waiting.is_none() && pending.len() < MB_MAX_ENTRIES;
+            if accept && recv.is_none() {
+                recv = Some(Box::pin(mb_trace_scope(
+                    &trace_ch,
+                    None,
+                    receiver.recv_req(server_name),
+                )));
+            }
+            let event = poll_fn(|cx| {
+                if let Poll::Ready(code) = stop_all.poll_stopped(cx) {
+                    return Poll::Ready(MBServeEvent::Stop(code));
+                }
+                if let Poll::Ready(Some((id, ret))) = running.poll_next_unpin(cx) {
+                    return Poll::Ready(MBServeEvent::Done(id, ret));
+                }
+                if let (true, Some(f)) = (accept, recv.as_mut()) {
+                    if let Poll::Ready(req) = f.as_mut().poll(cx) {
+                        return Poll::Ready(MBServeEvent::Req(req));
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+            match event {
+                MBServeEvent::Req(req) => {
+                    recv = None;
+                    let id = next_id;
+                    next_id += 1;
+                    pending.push_back((id, req.action, self.has_resp(&req), None));
+                    if self.ordering(req.action) == MBOrdering::Unordered || running.is_empty() {
+                        running.push(start(id, req));
+                    } else {
+                        waiting = Some((id, req));
+                    }
+                }
+                MBServeEvent::Done(id, ret) => {
+                    if let Some(p) = pending.iter_mut().find(|p| p.0 == id) {
+                        p.3 = Some(ret);
+                    }
+                }
+                MBServeEvent::Stop(code) => return (server_name.to_string(), code),
+            }
+        }
+    }
+    async fn serve_cmd(&self, server_name: &str, r: &R, req: &MBReqEntry) -> MBAsyncRPCResult {
+        let tracer = self.tracer.lock().unwrap().clone();
+        if let Some(tracer) = tracer {
+            let trace_ch: Arc<str> = Arc::from(server_name);
+            let id = self.next_req.fetch_add(1, Ordering::Relaxed);
+            tracer.record_req(&trace_ch, id, req);
+            let ret = mb_trace_req_scope(
+                &trace_ch,
+                req.action,
+                id,
+                self.serve_middlewares(server_name, r, req),
+            )
+            .await;
+            tracer.record_resp(&trace_ch, id, &ret);
+            ret
+        } else {
+            self.serve_middlewares(server_name, r, req).await
+        }
+    }
+    async fn serve_middlewares(
+        &self,
+        server_name: &str,
+        r: &R,
+        req: &MBReqEntry,
+    ) -> MBAsyncRPCResult {
+        let middlewares = self.middlewares.lock().unwrap().clone();
+        if middlewares.is_empty() {
+            return self.do_cmd(server_name, r, req).await;
+        }
+        let mut req = *req;
+        let mut entered = 0;
+        let mut ret = None;
+        for m in middlewares.iter() {
+            ret = m.before(server_name, &mut req);
+            if ret.is_some() {
+                break;
+            }
+            entered += 1;
+        }
+        let mut ret = match ret {
+            Some(ret) => ret,
+            None => self.do_cmd(server_name, r, &req).await,
+        };
+        for m in middlewares[..entered].iter().rev() {
+            m.after(server_name, &req, &mut ret);
         }
+        ret
     }
 }
 
-pub struct MBLocalServer {
+impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
+    MBAsyncRPC<RA, WA, R> for MBServerInner<RA, WA, R>
+{
+    fn poll_cmd(
+        &self,
+        server_name: &str,
+        r: &R,
+        req: &MBReqEntry,
+        cx: &mut Context,
+    ) -> Poll<MBAsyncRPCResult> {
+        //not holding the lock while polling, handlers may register others
+        let handler = self.handlers.lock().unwrap().get(&req.action).cloned();
+        if let Some(handler) = handler {
+            handler.poll_cmd(server_name, r, req, cx)
+        } else if req.action == MBAction::OTHER as u32 {
+            self.other_cmds.poll_cmd(server_name, r, req, cx)
+        } else {
+            Poll::Ready(Err(MBAsyncRPCError::Illegal(req.action)))
+        }
+    }
+    fn has_resp(&self, req: &MBReqEntry) -> bool {
+        let handler = self.handlers.lock().unwrap().get(&req.action).cloned();
+        if let Some(handler) = handler {
+            handler.has_resp(req)
+        } else if req.action == MBAction::OTHER as u32 {
+            self.other_cmds.has_resp(req)
+        } else {
+            true
+        }
+    }
+}
+
+pub struct MBLocalServer<R: MBPtrResolver = MBLocalPtrResolver> {
     name: String,
-    resolver: MBLocalPtrResolver,
-    inner: MBServerInner<MBLocalPtrReader, MBLocalPtrWriter, MBLocalPtrResolver>,
+    resolver: R,
+    inner: MBServerInner<R::READER, R::WRITER, R>,
 }
 
 impl MBLocalServer {
     pub fn new(name: &str, fs: &Arc<Option<MBFs>>) -> MBLocalServer {
+        Self::with_resolver(name, fs, MBLocalPtrResolver::default())
+    }
+}
+
+impl<R: MBPtrResolver> MBLocalServer<R> {
+    //e.g. a MBCheckedLocalPtrResolver, to only serve pointers in registered regions
+    pub fn with_resolver(name: &str, fs: &Arc<Option<MBFs>>, resolver: R) -> MBLocalServer<R> {
         MBLocalServer {
             name: name.to_string(),
-            resolver: MBLocalPtrResolver::default(),
+            resolver,
             inner: MBServerInner::new(fs),
         }
     }
@@ -99,45 +443,150 @@ impl MBLocalServer {
         &'a self,
         req: &'a MBReqEntry,
     ) -> impl Future<Output = MBAsyncRPCResult> + 'a {
-        self.inner.do_cmd(self.name.as_str(), &self.resolver, req)
+        self.inner
+            .serve_cmd(self.name.as_str(), &self.resolver, req)
     }
-    pub fn add_cmd<
-        C: CustomAsycRPC<MBLocalPtrReader, MBLocalPtrWriter, MBLocalPtrResolver> + 'static,
-    >(
+    pub fn add_cmd<C: CustomAsycRPC<R::READER, R::WRITER, R> + 'static>(&self, cmd: C) {
+        self.inner.add_cmd(cmd);
+    }
+    //handles the action, replacing the built-in or previously registered one
+    pub fn register_cmd<C: MBAsyncRPC<R::READER, R::WRITER, R> + Send + Sync + 'static>(
         &self,
+        action: impl Into<u32>,
         cmd: C,
-    ) {
-        self.inner.add_cmd(cmd);
+    ) -> Option<MBAsyncRPCHandler<R::READER, R::WRITER, R>> {
+        self.inner.register_cmd(action, cmd)
+    }
+    pub fn unregister_cmd(
+        &self,
+        action: impl Into<u32>,
+    ) -> Option<MBAsyncRPCHandler<R::READER, R::WRITER, R>> {
+        self.inner.unregister_cmd(action)
+    }
+    //limits of the strings in PRINT and CPRINT
+    pub fn set_str_limits(&self, limits: MBStrLimits) {
+        self.inner.set_str_limits(limits);
+    }
+    //where PRINT and CPRINT output goes, stdout by default
+    pub fn set_sinks(&self, sinks: MBOutputSinks) {
+        self.inner.set_sinks(sinks);
+    }
+    pub fn add_middleware<M: MBMiddleware + 'static>(&self, middleware: M) {
+        self.inner.add_middleware(middleware);
+    }
+    pub fn set_ordering(&self, action: impl Into<u32>, ordering: MBOrdering) {
+        self.inner.set_ordering(action, ordering);
+    }
+    pub fn ordering(&self, action: impl Into<u32>) -> MBOrdering {
+        self.inner.ordering(action)
+    }
+    //records every request, its result and the memory accessed while serving it,
+    //if the memory is traced by the same tracer
+    pub fn set_tracer(&self, tracer: Option<&MBTracer>) {
+        self.inner.set_tracer(tracer);
+    }
+    //serves requests until EXIT or stopped by the error policy
+    pub fn serve<'a, CH: MBChannelIf>(
+        &'a self,
+        receiver: &'a MBAsyncReceiver<CH>,
+        policy: MBErrorPolicy,
+        stop_all: MBStopAll,
+    ) -> impl Future<Output = (String, u32)> + 'a {
+        self.inner.serve_channel(
+            self.name.as_str(),
+            &self.resolver,
+            receiver,
+            policy,
+            stop_all,
+        )
     }
 }
 
-pub struct MBSMServer<SM: MBShareMem> {
+pub struct MBSMServer<SM: MBShareMem, R: MBPtrResolver = MBSMPtrResolver<SM>> {
     name: String,
-    resolver: MBSMPtrResolver<SM>,
-    inner: MBServerInner<MBSMPtrReaderWrtier<SM>, MBSMPtrReaderWrtier<SM>, MBSMPtrResolver<SM>>,
+    resolver: R,
+    inner: MBServerInner<R::READER, R::WRITER, R>,
+    _marker: PhantomData<SM>,
 }
 
 impl<SM: MBShareMem> MBSMServer<SM> {
     pub fn new(name: &str, fs: &Arc<Option<MBFs>>, sm: &Arc<Mutex<SM>>) -> MBSMServer<SM> {
+        Self::with_resolver(name, fs, MBSMPtrResolver::new(sm))
+    }
+}
+
+impl<R: MBShareMemResolver> MBSMServer<R::SM, R> {
+    //e.g. a MBPageTableResolver, for firmware passing virtual addresses
+    pub fn with_resolver(name: &str, fs: &Arc<Option<MBFs>>, resolver: R) -> MBSMServer<R::SM, R> {
         MBSMServer {
             name: name.to_string(),
-            resolver: MBSMPtrResolver::new(sm),
+            resolver,
             inner: MBServerInner::new(fs),
+            _marker: PhantomData,
         }
     }
+}
+
+impl<SM: MBShareMem, R: MBPtrResolver> MBSMServer<SM, R> {
     pub fn do_cmd<'a>(
         &'a self,
         req: &'a MBReqEntry,
     ) -> impl Future<Output = MBAsyncRPCResult> + 'a {
-        self.inner.do_cmd(self.name.as_str(), &self.resolver, req)
+        self.inner
+            .serve_cmd(self.name.as_str(), &self.resolver, req)
+    }
+    pub fn add_cmd<C: CustomAsycRPC<R::READER, R::WRITER, R> + 'static>(&self, cmd: C) {
+        self.inner.add_cmd(cmd);
     }
-    pub fn add_cmd<
-        C: CustomAsycRPC<MBSMPtrReaderWrtier<SM>, MBSMPtrReaderWrtier<SM>, MBSMPtrResolver<SM>>
-            + 'static,
-    >(
+    //handles the action, replacing the built-in or previously registered one
+    pub fn register_cmd<C: MBAsyncRPC<R::READER, R::WRITER, R> + Send + Sync + 'static>(
         &self,
+        action: impl Into<u32>,
         cmd: C,
-    ) {
-        self.inner.add_cmd(cmd);
+    ) -> Option<MBAsyncRPCHandler<R::READER, R::WRITER, R>> {
+        self.inner.register_cmd(action, cmd)
+    }
+    pub fn unregister_cmd(
+        &self,
+        action: impl Into<u32>,
+    ) -> Option<MBAsyncRPCHandler<R::READER, R::WRITER, R>> {
+        self.inner.unregister_cmd(action)
+    }
+    //limits of the strings in PRINT and CPRINT
+    pub fn set_str_limits(&self, limits: MBStrLimits) {
+        self.inner.set_str_limits(limits);
+    }
+    //where PRINT and CPRINT output goes, stdout by default
+    pub fn set_sinks(&self, sinks: MBOutputSinks) {
+        self.inner.set_sinks(sinks);
+    }
+    pub fn add_middleware<M: MBMiddleware + 'static>(&self, middleware: M) {
+        self.inner.add_middleware(middleware);
+    }
+    pub fn set_ordering(&self, action: impl Into<u32>, ordering: MBOrdering) {
+        self.inner.set_ordering(action, ordering);
+    }
+    pub fn ordering(&self, action: impl Into<u32>) -> MBOrdering {
+        self.inner.ordering(action)
+    }
+    //records every request, its result and the memory accessed while serving it,
+    //if the memory is traced by the same tracer
+    pub fn set_tracer(&self, tracer: Option<&MBTracer>) {
+        self.inner.set_tracer(tracer);
+    }
+    //serves requests until EXIT or stopped by the error policy
+    pub fn serve<'a, CH: MBChannelIf>(
+        &'a self,
+        receiver: &'a MBAsyncReceiver<CH>,
+        policy: MBErrorPolicy,
+        stop_all: MBStopAll,
+    ) -> impl Future<Output = (String, u32)> + 'a {
+        self.inner.serve_channel(
+            self.name.as_str(),
+            &self.resolver,
+            receiver,
+            policy,
+            stop_all,
+        )
     }
 }
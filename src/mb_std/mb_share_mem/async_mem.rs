@@ -0,0 +1,200 @@
+use super::share_mem::*;
+use crate::mb_rpcs::*;
+use async_std::task::{Context, Poll};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+//memory whose accesses take time, e.g. a bus functional model in the simulator.
+//poll_read and poll_write are polled with the same arguments until they are ready
+pub trait MBAsyncShareMem {
+    fn poll_write(&mut self, addr: MBPtrT, data: &[u8], cx: &mut Context) -> Poll<usize>;
+    fn poll_read(&mut self, addr: MBPtrT, data: &mut [u8], cx: &mut Context) -> Poll<usize>;
+}
+
+impl<SM: MBShareMem> MBAsyncShareMem for SM {
+    fn poll_write(&mut self, addr: MBPtrT, data: &[u8], _cx: &mut Context) -> Poll<usize> {
+        Poll::Ready(self.write(addr, data))
+    }
+    fn poll_read(&mut self, addr: MBPtrT, data: &mut [u8], _cx: &mut Context) -> Poll<usize> {
+        Poll::Ready(self.read(addr, data))
+    }
+}
+
+//every access of mem completes after latency polls
+#[derive(Debug)]
+pub struct MBLatencyShareMem<SM: MBShareMem> {
+    mem: SM,
+    latency: usize,
+    waited: usize,
+}
+
+impl<SM: MBShareMem> MBLatencyShareMem<SM> {
+    pub fn new(mem: SM, latency: usize) -> MBLatencyShareMem<SM> {
+        MBLatencyShareMem {
+            mem,
+            latency,
+            waited: 0,
+        }
+    }
+    pub fn inner(&self) -> &SM {
+        &self.mem
+    }
+    pub fn inner_mut(&mut self) -> &mut SM {
+        &mut self.mem
+    }
+    fn poll_wait(&mut self, cx: &mut Context) -> Poll<()> {
+        if self.waited < self.latency {
+            self.waited += 1;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.waited = 0;
+        Poll::Ready(())
+    }
+}
+
+impl<SM: MBShareMem> MBAsyncShareMem for MBLatencyShareMem<SM> {
+    fn poll_write(&mut self, addr: MBPtrT, data: &[u8], cx: &mut Context) -> Poll<usize> {
+        self.poll_wait(cx).map(|_| self.mem.write(addr, data))
+    }
+    fn poll_read(&mut self, addr: MBPtrT, data: &mut [u8], cx: &mut Context) -> Poll<usize> {
+        self.poll_wait(cx).map(|_| self.mem.read(addr, data))
+    }
+}
+
+//sync view of an async mem for the channel and the resolver:
+//ranges are loaded by poll_load before they are read, reads stop short at what is not loaded,
+//writes are kept until poll_store,
+//release drops the loaded ranges so that the next load sees what the other side wrote meanwhile.
+//give each channel and server its own stage to avoid dropping the ranges of one another
+#[derive(Debug)]
+pub struct MBStagedShareMem<AM: MBAsyncShareMem> {
+    mem: Arc<Mutex<AM>>,
+    loaded: BTreeMap<MBPtrT, Vec<u8>>,
+    writes: VecDeque<(MBPtrT, Vec<u8>)>,
+    //range being loaded
+    loading: Option<(MBPtrT, Vec<u8>)>,
+}
+
+impl<AM: MBAsyncShareMem> MBStagedShareMem<AM> {
+    pub fn new(mem: &Arc<Mutex<AM>>) -> MBStagedShareMem<AM> {
+        MBStagedShareMem {
+            mem: mem.clone(),
+            loaded: BTreeMap::new(),
+            writes: VecDeque::new(),
+            loading: None,
+        }
+    }
+    pub fn mem(&self) -> &Arc<Mutex<AM>> {
+        &self.mem
+    }
+    fn end(addr: MBPtrT, len: usize) -> MBPtrT {
+        addr.saturating_add(len as MBPtrT)
+    }
+    //the loaded range containing [addr, addr + len)
+    fn find(&self, addr: MBPtrT, len: usize) -> Option<(MBPtrT, &Vec<u8>)> {
+        self.loaded
+            .range(..=addr)
+            .next_back()
+            .filter(|(start, data)| Self::end(**start, data.len()) >= Self::end(addr, len))
+            .map(|(start, data)| (*start, data))
+    }
+    fn is_loaded(&self, addr: MBPtrT, len: usize) -> bool {
+        len == 0 || self.find(addr, len).is_some()
+    }
+    //merge data @ addr into the loaded ranges, data overrides what was loaded
+    fn merge(&mut self, addr: MBPtrT, data: &[u8]) {
+        let end = Self::end(addr, data.len());
+        let mut start = addr;
+        let mut merged_end = end;
+        let overlapped: Vec<MBPtrT> = self
+            .loaded
+            .range(..=end)
+            .filter(|(s, d)| Self::end(**s, d.len()) >= addr)
+            .map(|(s, _)| *s)
+            .collect();
+        let mut parts = vec![];
+        for s in overlapped {
+            let d = self.loaded.remove(&s).unwrap();
+            start = std::cmp::min(start, s);
+            merged_end = std::cmp::max(merged_end, Self::end(s, d.len()));
+            parts.push((s, d));
+        }
+        let mut buf = vec![0u8; (merged_end - start) as usize];
+        for (s, d) in parts {
+            let offset = (s - start) as usize;
+            buf[offset..offset + d.len()].copy_from_slice(&d);
+        }
+        let offset = (addr - start) as usize;
+        buf[offset..offset + data.len()].copy_from_slice(data);
+        self.loaded.insert(start, buf);
+    }
+    fn poll_loading(&mut self, cx: &mut Context) -> Poll<()> {
+        if let Some((addr, buf)) = self.loading.as_mut() {
+            let l = std::task::ready!(self.mem.lock().unwrap().poll_read(*addr, buf, cx));
+            if l < buf.len() {
+                panic!(
+                    "{}",
+                    MBShareMemError::Partial {
+                        addr: *addr,
+                        expected: buf.len(),
+                        actual: l
+                    }
+                )
+            }
+            let (addr, buf) = self.loading.take().unwrap();
+            self.merge(addr, &buf);
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<AM: MBAsyncShareMem> MBShareMem for MBStagedShareMem<AM> {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        self.merge(addr, data);
+        self.writes.push_back((addr, data.to_vec()));
+        data.len()
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        //never block on mem here, the simulator may need the thread to complete the access
+        if let Some((start, loaded)) = self.find(addr, 1) {
+            let offset = (addr - start) as usize;
+            let l = std::cmp::min(data.len(), loaded.len() - offset);
+            data[..l].copy_from_slice(&loaded[offset..offset + l]);
+            l
+        } else {
+            0
+        }
+    }
+    //make [addr, addr + len) readable, pending writes are stored first
+    fn poll_load(&mut self, addr: MBPtrT, len: usize, cx: &mut Context) -> Poll<()> {
+        std::task::ready!(self.poll_store(cx));
+        if self.is_loaded(addr, len) {
+            return Poll::Ready(());
+        }
+        self.loading = Some((addr, vec![0u8; len]));
+        self.poll_loading(cx)
+    }
+    //store the pending writes in the order they were made
+    fn poll_store(&mut self, cx: &mut Context) -> Poll<()> {
+        std::task::ready!(self.poll_loading(cx));
+        while let Some((addr, data)) = self.writes.front() {
+            let l = std::task::ready!(self.mem.lock().unwrap().poll_write(*addr, data, cx));
+            if l < data.len() {
+                panic!(
+                    "{}",
+                    MBShareMemError::Partial {
+                        addr: *addr,
+                        expected: data.len(),
+                        actual: l
+                    }
+                )
+            }
+            self.writes.pop_front();
+        }
+        Poll::Ready(())
+    }
+    fn release(&mut self) {
+        self.loaded.clear()
+    }
+}
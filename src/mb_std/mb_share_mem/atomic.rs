@@ -0,0 +1,161 @@
+use super::share_mem::MBShareMemError;
+use crate::mb_rpcs::MBPtrT;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MBAtomicOp {
+    Swap(u64),
+    Add(u64),
+    And(u64),
+    Or(u64),
+    //current, new
+    Cas(u64, u64),
+}
+
+impl MBAtomicOp {
+    //the value to store over old in a size bytes word, None if old should be kept
+    pub fn apply(&self, old: u64, size: usize) -> Option<u64> {
+        let mask = if size >= 8 {
+            u64::MAX
+        } else {
+            (1u64 << (size * 8)) - 1
+        };
+        match *self {
+            MBAtomicOp::Swap(v) => Some(v & mask),
+            MBAtomicOp::Add(v) => Some(old.wrapping_add(v) & mask),
+            MBAtomicOp::And(v) => Some(old & v & mask),
+            MBAtomicOp::Or(v) => Some((old | v) & mask),
+            MBAtomicOp::Cas(current, new) => {
+                if old == current & mask {
+                    Some(new & mask)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+pub trait MBAtomicInt: Copy + Sized {
+    fn to_u64(self) -> u64;
+    fn from_u64(v: u64) -> Self;
+}
+
+macro_rules! impl_atomic_int {
+    ($($t:ty),+) => {
+        $(
+            impl MBAtomicInt for $t {
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+                fn from_u64(v: u64) -> Self {
+                    v as $t
+                }
+            }
+        )+
+    };
+}
+impl_atomic_int!(u8, u16, u32, u64, usize);
+
+pub(crate) fn check_atomic_size(size: usize) {
+    assert!(
+        matches!(size, 1 | 2 | 4 | 8),
+        "atomic access size {} is not supported!",
+        size
+    );
+}
+
+//native endian word in bytes, bytes.len() is 1, 2, 4 or 8
+pub(crate) fn atomic_from_bytes(bytes: &[u8]) -> u64 {
+    match bytes.len() {
+        1 => bytes[0] as u64,
+        2 => u16::from_ne_bytes(bytes.try_into().unwrap()) as u64,
+        4 => u32::from_ne_bytes(bytes.try_into().unwrap()) as u64,
+        _ => u64::from_ne_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+pub(crate) fn atomic_to_bytes(v: u64, bytes: &mut [u8]) {
+    match bytes.len() {
+        1 => bytes[0] = v as u8,
+        2 => bytes.copy_from_slice(&(v as u16).to_ne_bytes()),
+        4 => bytes.copy_from_slice(&(v as u32).to_ne_bytes()),
+        _ => bytes.copy_from_slice(&v.to_ne_bytes()),
+    }
+}
+
+//apply op to the size bytes word at ptr with cpu atomics, ptr must be aligned to size
+//# Safety
+//ptr must be valid for size bytes
+pub(crate) unsafe fn mb_atomic_ptr(
+    ptr: *mut u8,
+    size: usize,
+    op: MBAtomicOp,
+) -> Result<u64, MBShareMemError> {
+    check_atomic_size(size);
+    macro_rules! atomic {
+        ($a:ty, $t:ty) => {{
+            let a = <$a>::from_ptr(ptr as *mut $t);
+            (match op {
+                MBAtomicOp::Swap(v) => a.swap(v as $t, Ordering::SeqCst),
+                MBAtomicOp::Add(v) => a.fetch_add(v as $t, Ordering::SeqCst),
+                MBAtomicOp::And(v) => a.fetch_and(v as $t, Ordering::SeqCst),
+                MBAtomicOp::Or(v) => a.fetch_or(v as $t, Ordering::SeqCst),
+                MBAtomicOp::Cas(current, new) => a
+                    .compare_exchange(current as $t, new as $t, Ordering::SeqCst, Ordering::SeqCst)
+                    .unwrap_or_else(|old| old),
+            }) as u64
+        }};
+    }
+    if ptr as usize % size != 0 {
+        return Err(MBShareMemError::Unaligned(ptr as MBPtrT));
+    }
+    Ok(match size {
+        1 => atomic!(AtomicU8, u8),
+        2 => atomic!(AtomicU16, u16),
+        4 => atomic!(AtomicU32, u32),
+        _ => atomic!(AtomicU64, u64),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::*;
+    use crate::mb_rpcs::MBPtrT;
+    use crate::mb_std::mb_ptr_resolver::*;
+    use std::sync::{Arc, Mutex};
+    #[test]
+    fn mb_atomic_test() {
+        let mut space = MBShareMemSpace::<MBSparseShareMem>::new();
+        let ram = Arc::new(Mutex::new(MBSparseShareMem::new(0x1000, 0x1000)));
+        space.add_named_mem("ram", &ram).unwrap();
+        space.write_sized(0x1010, &0x10u32);
+        assert_eq!(space.atomic_fetch_add(0x1010, 0xffffffffu32), Ok(0x10));
+        assert_eq!(space.atomic_cas(0x1010, 0x10u32, 0x20), Ok(0xf));
+        assert_eq!(space.atomic_cas(0x1010, 0xfu32, 0x20), Ok(0xf));
+        assert_eq!(space.atomic_fetch_or(0x1010, 0x3u32), Ok(0x20));
+        assert_eq!(space.atomic_fetch_and(0x1010, 0x6u32), Ok(0x23));
+        assert_eq!(space.atomic_swap(0x1011, 0xffu8), Ok(0));
+        let mut data = 0u32;
+        space.read_sized(0x1010, &mut data);
+        assert_eq!(data, 0xff02);
+        assert_eq!(
+            space.atomic_swap(0x1ffe, 0u32),
+            Err(MBShareMemError::Partial {
+                addr: 0x1ffe,
+                expected: 4,
+                actual: 2
+            })
+        );
+        let resolver = MBSMPtrResolver::new(&Arc::new(Mutex::new(space)));
+        assert_eq!(resolver.atomic_fetch_add(0x1010 as *mut u16, 1), Ok(0xff02));
+        let mut local = [0u16; 3];
+        let ptr = unsafe { (local.as_mut_ptr() as *mut u8).add(1) } as *mut u16;
+        assert_eq!(
+            MBLocalPtrResolver.atomic_swap(ptr, 0xffff),
+            Err(MBShareMemError::Unaligned(ptr as MBPtrT))
+        );
+        assert_eq!(MBLocalPtrResolver.atomic_fetch_add(&mut local[2], 3), Ok(0));
+        assert_eq!(local, [0, 0, 3]);
+    }
+}
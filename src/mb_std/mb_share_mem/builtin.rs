@@ -0,0 +1,201 @@
+use super::atomic::*;
+#[cfg(unix)]
+use super::mmap::*;
+use super::mmio::*;
+use super::share_mem::*;
+use super::sparse::*;
+use crate::mb_rpcs::*;
+use crate::mb_std::mb_builder::{MBShareMemParser, Yaml};
+
+#[derive(Debug)]
+pub enum MBDefaultShareMem {
+    Sparse(MBSparseShareMem),
+    #[cfg(unix)]
+    Mmap(MBMmapShareMem),
+    Mmio(MBMmioShareMem),
+}
+
+macro_rules! dispatch_default_share_mem {
+    ($mem:expr, $m:ident => $e:expr) => {
+        match $mem {
+            MBDefaultShareMem::Sparse($m) => $e,
+            #[cfg(unix)]
+            MBDefaultShareMem::Mmap($m) => $e,
+            MBDefaultShareMem::Mmio($m) => $e,
+        }
+    };
+}
+
+impl MBShareMemBlock for MBDefaultShareMem {
+    fn base(&self) -> MBPtrT {
+        dispatch_default_share_mem!(self, m => m.base())
+    }
+    fn size(&self) -> MBPtrT {
+        dispatch_default_share_mem!(self, m => m.size())
+    }
+    fn used_ranges(&self) -> Vec<(MBPtrT, MBPtrT)> {
+        dispatch_default_share_mem!(self, m => m.used_ranges())
+    }
+    fn fill(&self) -> u8 {
+        dispatch_default_share_mem!(self, m => m.fill())
+    }
+    fn discard(&mut self) {
+        dispatch_default_share_mem!(self, m => m.discard())
+    }
+}
+
+impl MBShareMem for MBDefaultShareMem {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        dispatch_default_share_mem!(self, m => m.write(addr, data))
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        dispatch_default_share_mem!(self, m => m.read(addr, data))
+    }
+    fn atomic(
+        &mut self,
+        addr: MBPtrT,
+        size: usize,
+        op: MBAtomicOp,
+    ) -> Result<u64, MBShareMemError> {
+        dispatch_default_share_mem!(self, m => m.atomic(addr, size, op))
+    }
+}
+
+fn get_int(key: &str, doc: &Yaml, field: &str) -> Result<Option<i64>, String> {
+    match &doc[field] {
+        Yaml::BadValue => Ok(None),
+        v => v
+            .as_i64()
+            .map(Some)
+            .ok_or(format!("{:?}: {} should be integer!", key, field)),
+    }
+}
+
+fn get_required_int(key: &str, doc: &Yaml, field: &str) -> Result<i64, String> {
+    get_int(key, doc, field)?.ok_or(format!("{:?}: {} is required!", key, field))
+}
+
+fn get_required_ptr(key: &str, doc: &Yaml, field: &str) -> Result<MBPtrT, String> {
+    let v = get_required_int(key, doc, field)?;
+    MBPtrT::try_from(v).map_err(|_| format!("{:?}: {} {} is out of range!", key, field, v))
+}
+
+//sparse:
+//  type: sparse
+//  base: 0x80000000
+//  size: 0x100000000
+//  page_size: 4096 #optional
+//  fill: 0 #optional
+//mmap:
+//  type: mmap
+//  base: 0x80000000
+//  size: 0x1000000
+//  file: ~/ivshmem #or shm: ivshmem
+#[derive(Default)]
+pub struct MBDefaultShareMemParser;
+impl MBShareMemParser for MBDefaultShareMemParser {
+    type MemType = MBDefaultShareMem;
+    fn parse(&self, key: &str, doc: &Yaml) -> Result<Self::MemType, String> {
+        let base = get_required_ptr(key, doc, "base")?;
+        let size = get_required_ptr(key, doc, "size")?;
+        if size == 0 {
+            return Err(format!("{:?}: size should not be 0!", key));
+        }
+        if base.checked_add(size - 1).is_none() {
+            return Err(format!(
+                "{:?}: base {:#x} with size {:#x} exceeds address space!",
+                key, base, size
+            ));
+        }
+        match doc["type"].as_str() {
+            Some("sparse") => {
+                let page_size =
+                    get_int(key, doc, "page_size")?.unwrap_or(MB_SPARSE_PAGE_SIZE as i64);
+                if page_size <= 0 {
+                    return Err(format!("{:?}: page_size should be positive!", key));
+                }
+                let fill = get_int(key, doc, "fill")?.unwrap_or(0);
+                let fill = u8::try_from(fill)
+                    .map_err(|_| format!("{:?}: fill {} should be a byte!", key, fill))?;
+                Ok(MBDefaultShareMem::Sparse(MBSparseShareMem::with_page_size(
+                    base,
+                    size,
+                    page_size as usize,
+                    fill,
+                )))
+            }
+            #[cfg(unix)]
+            Some("mmap") => {
+                let mem = if let Some(file) = doc["file"].as_str() {
+                    MBMmapShareMem::from_file(file, base, size)
+                } else if let Some(shm) = doc["shm"].as_str() {
+                    MBMmapShareMem::from_shm(shm, base, size)
+                } else {
+                    return Err(format!("{:?}: mmap needs file or shm!", key));
+                };
+                Ok(MBDefaultShareMem::Mmap(
+                    mem.map_err(|e| format!("{:?}: {}", key, e))?,
+                ))
+            }
+            t => Err(format!("{:?}: unknown type {:?}!", key, t)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mb_std::mb_builder::{MBShareMemSpaceBuilder, YamlLoader};
+    #[test]
+    fn mb_sparse_mem_test() {
+        let spaces =
+            MBShareMemSpaceBuilder::<MBDefaultShareMem, MBDefaultShareMemParser>::from_str(
+                "
+                space:
+                    core0:
+                        - ddr:
+                            type: sparse
+                            base: 0x80000000
+                            size: 0x40000000
+                            page_size: 0x100
+                            fill: 0xa5
+                ",
+            )
+            .unwrap()
+            .build_shared()
+            .unwrap()
+            .build_spaces()
+            .unwrap();
+        let mut space = spaces["core0"].lock().unwrap();
+        let mut data = [0u8; 4];
+        space.read_slice(0xa0000000, &mut data);
+        assert_eq!(data, [0xa5; 4]);
+        space.write_slice(0x800000fe, &[1u8, 2, 3, 4]);
+        let mut data = [0u8; 6];
+        space.read_slice(0x800000fd, &mut data);
+        assert_eq!(data, [0xa5, 1, 2, 3, 4, 0xa5]);
+        space.write_slice(0xbffffffe, &[5u8, 6]);
+        space.read_slice(0xbffffffc, &mut data[..4]);
+        assert_eq!(data[..4], [0xa5, 0xa5, 5, 6]);
+        for (doc, e) in [
+            (
+                "{type: sparse, base: 0, size: 0x1000, fill: 0x100}",
+                "fill 256 should be a byte!",
+            ),
+            (
+                "{type: sparse, base: 0, size: 0x1000, fill: -1}",
+                "fill -1 should be a byte!",
+            ),
+            (
+                "{type: sparse, base: 0, size: -1}",
+                "size -1 is out of range!",
+            ),
+        ] {
+            let doc = &YamlLoader::load_from_str(doc).unwrap()[0];
+            assert_eq!(
+                MBDefaultShareMemParser.parse("ddr", doc).err().unwrap(),
+                format!("\"ddr\": {}", e)
+            );
+        }
+    }
+}
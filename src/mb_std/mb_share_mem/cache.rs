@@ -0,0 +1,387 @@
+use super::atomic::*;
+use super::share_mem::*;
+use crate::mb_channel::MB_CACHE_LINE;
+use crate::mb_rpcs::*;
+use std::cell::RefCell;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub const MB_CACHE_DEFAULT_LINE_SIZE: usize = 64;
+
+#[derive(Debug)]
+struct MBCacheLine {
+    data: Box<[u8]>,
+    dirty: bool,
+    //part of the line in mem, block bases and ends need not be line aligned
+    valid: std::ops::Range<usize>,
+}
+
+//one core's write-back, write-allocate cache in front of mem shared with other cores and the server:
+//reads return the cached line until it is invalidated, writes stay in the cache until the line is cleaned,
+//and a clean writes back the whole line, so data sharing a line with the other side gets clobbered as on hardware.
+//route the core's __mb_rfence to rfence and __mb_wfence to wfence to check that the firmware fences correctly
+#[derive(Debug)]
+pub struct MBCacheShareMem<SM: MBShareMem> {
+    mem: Arc<Mutex<SM>>,
+    line_size: usize,
+    lines: RefCell<BTreeMap<MBPtrT, MBCacheLine>>,
+}
+
+impl<SM: MBShareMem> MBCacheShareMem<SM> {
+    pub fn new(mem: &Arc<Mutex<SM>>) -> MBCacheShareMem<SM> {
+        Self::with_line_size(mem, MB_CACHE_LINE.unwrap_or(MB_CACHE_DEFAULT_LINE_SIZE))
+    }
+    pub fn with_line_size(mem: &Arc<Mutex<SM>>, line_size: usize) -> MBCacheShareMem<SM> {
+        assert!(
+            line_size.is_power_of_two(),
+            "line_size {} should be power of 2!",
+            line_size
+        );
+        MBCacheShareMem {
+            mem: mem.clone(),
+            line_size,
+            lines: RefCell::new(BTreeMap::new()),
+        }
+    }
+    pub fn line_size(&self) -> usize {
+        self.line_size
+    }
+    pub fn mem(&self) -> &Arc<Mutex<SM>> {
+        &self.mem
+    }
+    //addresses of lines holding writes not visible to mem yet
+    pub fn dirty_lines(&self) -> Vec<MBPtrT> {
+        self.lines
+            .borrow()
+            .iter()
+            .filter(|(_, l)| l.dirty)
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+    fn line_addr(&self, addr: MBPtrT) -> MBPtrT {
+        addr & !(self.line_size as MBPtrT - 1)
+    }
+    fn line_range(&self, addr: MBPtrT, size: usize) -> Vec<MBPtrT> {
+        if size == 0 {
+            return vec![];
+        }
+        let end = self.line_addr(addr.saturating_add(size as MBPtrT - 1));
+        self.lines
+            .borrow()
+            .range(self.line_addr(addr)..=end)
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+    fn write_back(&mut self, line_addr: MBPtrT) {
+        if let Some(line) = self.lines.get_mut().get_mut(&line_addr) {
+            if line.dirty {
+                self.mem.lock().unwrap().write(
+                    line_addr + line.valid.start as MBPtrT,
+                    &line.data[line.valid.clone()],
+                );
+                line.dirty = false;
+            }
+        }
+    }
+    //write dirty lines overlapping [addr, addr + size) back to mem
+    pub fn clean(&mut self, addr: MBPtrT, size: usize) {
+        for line_addr in self.line_range(addr, size) {
+            self.write_back(line_addr);
+        }
+    }
+    //drop lines overlapping [addr, addr + size), dirty data in them is lost
+    pub fn invalidate(&mut self, addr: MBPtrT, size: usize) {
+        for line_addr in self.line_range(addr, size) {
+            self.lines.get_mut().remove(&line_addr);
+        }
+    }
+    pub fn flush(&mut self, addr: MBPtrT, size: usize) {
+        self.clean(addr, size);
+        self.invalidate(addr, size);
+    }
+    pub fn clean_all(&mut self) {
+        let lines: Vec<MBPtrT> = self.lines.get_mut().keys().copied().collect();
+        for line_addr in lines {
+            self.write_back(line_addr);
+        }
+    }
+    pub fn invalidate_all(&mut self) {
+        self.lines.get_mut().clear()
+    }
+    pub fn rfence(&mut self, start: MBPtrT, size: usize) {
+        self.invalidate(start, size)
+    }
+    pub fn wfence(&mut self, start: MBPtrT, size: usize) {
+        self.clean(start, size)
+    }
+    //fill the line from mem as far as mem can be read around offset
+    fn fill(&self, line_addr: MBPtrT, offset: usize) -> Result<MBCacheLine, MBShareMemError> {
+        let mem = self.mem.lock().unwrap();
+        let mut data = vec![0u8; self.line_size].into_boxed_slice();
+        if mem.try_read(line_addr, &mut data).is_ok() {
+            return Ok(MBCacheLine {
+                data,
+                dirty: false,
+                valid: 0..self.line_size,
+            });
+        }
+        let addr = |o: usize| line_addr + o as MBPtrT;
+        mem.try_read(addr(offset), &mut data[offset..offset + 1])?;
+        let (mut start, mut hi) = (0, offset);
+        while start < hi {
+            let mid = (start + hi) / 2;
+            if mem.try_read(addr(mid), &mut data[mid..offset]).is_ok() {
+                hi = mid
+            } else {
+                start = mid + 1
+            }
+        }
+        let (mut end, mut hi) = (offset + 1, self.line_size);
+        while end < hi {
+            let mid = (end + hi).div_ceil(2);
+            if mem.try_read(addr(offset), &mut data[offset..mid]).is_ok() {
+                end = mid
+            } else {
+                hi = mid - 1
+            }
+        }
+        mem.try_read(addr(start), &mut data[start..end])?;
+        Ok(MBCacheLine {
+            data,
+            dirty: false,
+            valid: start..end,
+        })
+    }
+    //call f with each line overlapping [addr, addr + len) and the ranges in the line and in the data,
+    //lines missing are filled from mem first, stop where mem can not be read
+    fn access<F: FnMut(&mut MBCacheLine, std::ops::Range<usize>, std::ops::Range<usize>)>(
+        &self,
+        addr: MBPtrT,
+        len: usize,
+        mut f: F,
+    ) -> (usize, Option<MBShareMemError>) {
+        let mut lines = self.lines.borrow_mut();
+        let mut pos = 0;
+        while pos < len {
+            let cur = addr + pos as MBPtrT;
+            let line_addr = self.line_addr(cur);
+            let offset = (cur - line_addr) as usize;
+            let line = match lines.entry(line_addr) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => match self.fill(line_addr, offset) {
+                    Ok(line) => e.insert(line),
+                    Err(e) => return (pos, Some(e)),
+                },
+            };
+            if !line.valid.contains(&offset) {
+                return (pos, Some(MBShareMemError::Unmapped(cur)));
+            }
+            let l = std::cmp::min(len - pos, line.valid.end - offset);
+            f(line, offset..offset + l, pos..pos + l);
+            pos += l;
+        }
+        (pos, None)
+    }
+}
+
+impl<SM: MBShareMem> MBShareMem for MBCacheShareMem<SM> {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        self.access(addr, data.len(), |line, range, data_range| {
+            line.data[range].copy_from_slice(&data[data_range]);
+            line.dirty = true;
+        })
+        .0
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        let len = data.len();
+        self.access(addr, len, |line, range, data_range| {
+            data[data_range].copy_from_slice(&line.data[range]);
+        })
+        .0
+    }
+    fn try_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        //lines are written back without checking, so check before caching the write
+        self.check_access(addr, data.len(), MBMemAccess::Write)?;
+        let r = self.access(addr, data.len(), |line, range, data_range| {
+            line.data[range].copy_from_slice(&data[data_range]);
+            line.dirty = true;
+        });
+        mb_access_result(addr, data.len(), r)
+    }
+    fn try_read(&self, addr: MBPtrT, data: &mut [u8]) -> Result<(), MBShareMemError> {
+        let len = data.len();
+        let r = self.access(addr, len, |line, range, data_range| {
+            data[data_range].copy_from_slice(&line.data[range]);
+        });
+        mb_access_result(addr, len, r)
+    }
+    fn check_access(
+        &self,
+        addr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        self.mem.lock().unwrap().check_access(addr, len, access)
+    }
+    fn atomic(
+        &mut self,
+        addr: MBPtrT,
+        size: usize,
+        op: MBAtomicOp,
+    ) -> Result<u64, MBShareMemError> {
+        //atomics are performed at the point of coherency
+        self.flush(addr, size);
+        self.mem.lock().unwrap().atomic(addr, size, op)
+    }
+    fn backdoor_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        self.flush(addr, data.len());
+        self.mem.lock().unwrap().backdoor_write(addr, data)
+    }
+}
+
+impl<SM: MBShareMemBlock> MBShareMemBlock for MBCacheShareMem<SM> {
+    fn base(&self) -> MBPtrT {
+        self.mem.lock().unwrap().base()
+    }
+    fn size(&self) -> MBPtrT {
+        self.mem.lock().unwrap().size()
+    }
+    fn used_ranges(&self) -> Vec<(MBPtrT, MBPtrT)> {
+        self.mem.lock().unwrap().used_ranges()
+    }
+    fn fill(&self) -> u8 {
+        self.mem.lock().unwrap().fill()
+    }
+    fn discard(&mut self) {
+        self.invalidate_all();
+        self.mem.lock().unwrap().discard()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::*;
+    use super::*;
+    #[test]
+    fn mb_cache_test() {
+        let mem = Arc::new(Mutex::new(MBSparseShareMem::new(0x1000, 0x1000)));
+        let mut core0 = MBCacheShareMem::with_line_size(&mem, 32);
+        let mut core1 = MBCacheShareMem::with_line_size(&mem, 32);
+        let mut data = 0u32;
+        core1.read_sized(0x1000, &mut data);
+        core0.write_sized(0x1000, &0x12345678u32);
+        assert_eq!(core0.dirty_lines(), vec![0x1000]);
+        mem.lock().unwrap().read_sized(0x1000, &mut data);
+        assert_eq!(data, 0);
+        core0.wfence(0x1000, 4);
+        assert!(core0.dirty_lines().is_empty());
+        mem.lock().unwrap().read_sized(0x1000, &mut data);
+        assert_eq!(data, 0x12345678);
+        //stale until invalidated
+        core1.read_sized(0x1000, &mut data);
+        assert_eq!(data, 0);
+        core1.rfence(0x1000, 4);
+        core1.read_sized(0x1000, &mut data);
+        assert_eq!(data, 0x12345678);
+        //the server's write sharing a line with a dirty line is lost on clean
+        core0.write_sized(0x1020, &1u32);
+        mem.lock().unwrap().write_sized(0x1030, &2u32);
+        core0.clean_all();
+        mem.lock().unwrap().read_sized(0x1030, &mut data);
+        assert_eq!(data, 0);
+        //atomics bypass the cache
+        core1.write_sized(0x1040, &5u32);
+        assert_eq!(core1.atomic_fetch_add(0x1040, 1u32), Ok(5));
+        mem.lock().unwrap().read_sized(0x1040, &mut data);
+        assert_eq!(data, 6);
+    }
+
+    #[test]
+    fn mb_cache_unaligned_block_test() {
+        let mut space = MBShareMemSpace::<MBSparseShareMem>::new();
+        let ram = Arc::new(Mutex::new(MBSparseShareMem::new(0x1010, 0x20)));
+        space.add_named_mem("ram", &ram).unwrap();
+        let mut core0 = MBCacheShareMem::with_line_size(&Arc::new(Mutex::new(space)), 32);
+        core0.write_slice(0x1010, &[1u8, 2]);
+        core0.write_slice(0x102e, &[3u8, 4]);
+        let mut data = [0u8; 4];
+        assert_eq!(core0.read(0x102e, &mut data), 2);
+        core0.clean_all();
+        ram.lock().unwrap().read_slice(0x1010, &mut data[..2]);
+        assert_eq!(data[..2], [1, 2]);
+        ram.lock().unwrap().read_slice(0x102e, &mut data[..2]);
+        assert_eq!(data[..2], [3, 4]);
+        //unmapped addrs are errors of try_*, not panics
+        assert_eq!(core0.read(0x1000, &mut data), 0);
+        assert_eq!(
+            core0.try_read(0x1000, &mut data),
+            Err(MBShareMemError::Unmapped(0x1000))
+        );
+        assert_eq!(
+            core0.try_write(0x102e, &[5u8, 6, 7, 8]),
+            Err(MBShareMemError::Unmapped(0x1030))
+        );
+        assert_eq!(
+            core0.try_read_slice_checked(0x1040, &mut data),
+            Err(MBShareMemError::Unmapped(0x1040))
+        );
+    }
+
+    #[cfg(feature = "test_client")]
+    #[test]
+    fn mb_cache_nb_client_test() {
+        use crate::mb_channel::*;
+        use crate::mb_no_std::*;
+        let mem = Arc::new(Mutex::new(MBSparseShareMem::new(0x1000, 0x1000)));
+        let cache = Arc::new(Mutex::new(MBCacheShareMem::with_line_size(&mem, 32)));
+        let client = Box::leak(Box::new(MBChannelShareMem::new(0x1000, &cache)));
+        //the server clears the channel
+        cache.lock().unwrap().invalidate_all();
+        let mut server = MBChannelShareMem::new(0x1000, &mem);
+        let fence = cache.clone();
+        MB_TEST_FENCE.with(|f| {
+            *f.borrow_mut() = Some(Box::new(move |is_wfence, start, size| {
+                let mut c = fence.lock().unwrap();
+                if is_wfence {
+                    c.wfence(start, size)
+                } else {
+                    c.rfence(start, size)
+                }
+            }))
+        });
+        let mut sender = MBNbRefSender::new(client);
+        sender.reset();
+        assert!(server.reset_ready());
+        server.reset_pre_ack();
+        server.reset_ack();
+
+        mb_exit(&mut sender, 3);
+        assert!(server.req_can_get());
+        let req = server.get_req();
+        assert_eq!((req.action, req.args[0]), (MBAction::EXIT as u32, 3));
+        server.ack_req();
+
+        let server = std::thread::spawn(move || {
+            while !server.req_can_get() {
+                std::thread::yield_now()
+            }
+            assert_eq!(server.get_req().action, MBAction::MEMCMP as u32);
+            server.ack_req();
+            server.put_resp(MBRespEntry {
+                words: 1,
+                rets: -1i32 as MBPtrT,
+            });
+            server.commit_resp();
+            server
+        });
+        assert_eq!(mb_memcmp(&mut sender, 0x1800, 0x1900, 4), -1);
+        let server = server.join().unwrap();
+
+        //without fences the request stays in the cache
+        MB_TEST_FENCE.with(|f| f.borrow_mut().take());
+        mb_exit(&mut sender, 4);
+        assert!(!server.req_can_get());
+        assert!(!cache.lock().unwrap().dirty_lines().is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use async_std::task::{Context, Poll};
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -216,6 +217,12 @@ impl<SM: MBShareMem> MBChannelShareMem<SM> {
         MBChannelShareMem::new(mb_address, mem)
     }
 
+    fn size(&self) -> usize {
+        (self.resp_queue.base - self.base) as usize
+            + MB_CACHE_LINE.map_or(std::mem::size_of::<MBQueue<MBRespEntry>>(), |cache_line| {
+                (std::mem::size_of::<MBQueue<MBRespEntry>>()).next_multiple_of(cache_line)
+            })
+    }
     fn state_offset(&self) -> MBPtrT {
         std::mem::size_of::<u32>() as MBPtrT
     }
@@ -319,4 +326,17 @@ impl<SM: MBShareMem> MBChannelIf for MBChannelShareMem<SM> {
         self.resp_queue.advance_p();
         self.resp_queue.idx_p_ptr()
     }
+    fn mem_range(&self) -> (MBPtrT, usize) {
+        (self.base, self.size())
+    }
+    fn poll_load(&mut self, cx: &mut Context) -> Poll<()> {
+        let size = self.size();
+        self.mem.lock().unwrap().poll_load(self.base, size, cx)
+    }
+    fn poll_store(&mut self, cx: &mut Context) -> Poll<()> {
+        self.mem.lock().unwrap().poll_store(cx)
+    }
+    fn release(&mut self) {
+        self.mem.lock().unwrap().release()
+    }
 }
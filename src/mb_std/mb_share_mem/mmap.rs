@@ -0,0 +1,203 @@
+use super::atomic::*;
+use super::share_mem::*;
+use crate::mb_rpcs::*;
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::{fence, Ordering};
+
+//shared memory mapped from a file or a posix shm object, other processes (e.g. qemu ivshmem, another simulator)
+//mapping the same object see the same bytes
+#[derive(Debug)]
+pub struct MBMmapShareMem {
+    base: MBPtrT,
+    size: MBPtrT,
+    ptr: *mut u8,
+}
+
+unsafe impl Send for MBMmapShareMem {}
+
+impl MBMmapShareMem {
+    pub fn from_file(file: &str, base: MBPtrT, size: MBPtrT) -> Result<MBMmapShareMem, String> {
+        let file_expand = shellexpand::full(file)
+            .map_err(|e| e.to_string())?
+            .to_string();
+        let err_handler = |e: std::io::Error| format!("mmap {} fail! {}", file_expand, e);
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&file_expand)
+            .map_err(err_handler)?;
+        Self::map(&f, base, size).map_err(err_handler)
+    }
+
+    pub fn from_shm(name: &str, base: MBPtrT, size: MBPtrT) -> Result<MBMmapShareMem, String> {
+        let err_handler = |e: std::io::Error| format!("mmap shm {} fail! {}", name, e);
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDWR | libc::O_CREAT, 0o666) };
+        if fd < 0 {
+            return Err(err_handler(std::io::Error::last_os_error()));
+        }
+        let f = unsafe { File::from_raw_fd(fd) };
+        Self::map(&f, base, size).map_err(err_handler)
+    }
+
+    pub fn unlink_shm(name: &str) -> Result<(), String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        if unsafe { libc::shm_unlink(c_name.as_ptr()) } < 0 {
+            return Err(format!(
+                "unlink shm {} fail! {}",
+                name,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    fn map(f: &File, base: MBPtrT, size: MBPtrT) -> std::io::Result<MBMmapShareMem> {
+        if size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "size should not be 0!",
+            ));
+        }
+        if f.metadata()?.len() < size as u64 {
+            f.set_len(size as u64)?;
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                f.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(MBMmapShareMem {
+            base,
+            size,
+            ptr: ptr as *mut u8,
+        })
+    }
+
+    fn valid_len(&self, addr: MBPtrT, len: usize) -> usize {
+        let offset = (addr - self.base) as usize;
+        std::cmp::min(len, self.size as usize - offset)
+    }
+}
+
+//use the widest naturally aligned access, so that aligned words written by the other side never tear
+fn access_width(addr: usize, len: usize) -> usize {
+    [8, 4, 2]
+        .into_iter()
+        .find(|w| addr % w == 0 && len >= *w)
+        .unwrap_or(1)
+}
+
+impl Drop for MBMmapShareMem {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.size as usize);
+        }
+    }
+}
+
+impl MBShareMemBlock for MBMmapShareMem {
+    fn base(&self) -> MBPtrT {
+        self.base
+    }
+    fn size(&self) -> MBPtrT {
+        self.size
+    }
+}
+
+impl MBShareMem for MBMmapShareMem {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        let len = self.valid_len(addr, data.len());
+        let ptr = unsafe { self.ptr.add((addr - self.base) as usize) };
+        let mut i = 0;
+        while i < len {
+            let p = unsafe { ptr.add(i) };
+            let w = access_width(p as usize, len - i);
+            let d = &data[i..i + w];
+            unsafe {
+                match w {
+                    8 => (p as *mut u64).write_volatile(u64::from_ne_bytes(d.try_into().unwrap())),
+                    4 => (p as *mut u32).write_volatile(u32::from_ne_bytes(d.try_into().unwrap())),
+                    2 => (p as *mut u16).write_volatile(u16::from_ne_bytes(d.try_into().unwrap())),
+                    _ => p.write_volatile(d[0]),
+                }
+            }
+            i += w;
+        }
+        fence(Ordering::SeqCst);
+        len
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        let len = self.valid_len(addr, data.len());
+        let ptr = unsafe { self.ptr.add((addr - self.base) as usize) };
+        fence(Ordering::SeqCst);
+        let mut i = 0;
+        while i < len {
+            let p = unsafe { ptr.add(i) };
+            let w = access_width(p as usize, len - i);
+            let d = &mut data[i..i + w];
+            unsafe {
+                match w {
+                    8 => d.copy_from_slice(&(p as *const u64).read_volatile().to_ne_bytes()),
+                    4 => d.copy_from_slice(&(p as *const u32).read_volatile().to_ne_bytes()),
+                    2 => d.copy_from_slice(&(p as *const u16).read_volatile().to_ne_bytes()),
+                    _ => d[0] = p.read_volatile(),
+                }
+            }
+            i += w;
+        }
+        len
+    }
+    fn atomic(
+        &mut self,
+        addr: MBPtrT,
+        size: usize,
+        op: MBAtomicOp,
+    ) -> Result<u64, MBShareMemError> {
+        let actual = self.valid_len(addr, size);
+        if actual < size {
+            return Err(MBShareMemError::Partial {
+                addr,
+                expected: size,
+                actual,
+            });
+        }
+        unsafe { mb_atomic_ptr(self.ptr.add((addr - self.base) as usize), size, op) }
+            .map_err(|_| MBShareMemError::Unaligned(addr))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn mb_mmap_file_test() {
+        let file = std::env::temp_dir().join(format!("mb_mmap_test_{}", std::process::id()));
+        let path = file.to_str().unwrap();
+        let mut mem0 = MBMmapShareMem::from_file(path, 0x1000, 0x100).unwrap();
+        let mem1 = MBMmapShareMem::from_file(path, 0x8000, 0x100).unwrap();
+        assert_eq!(mem0.write(0x10fd, &[1, 2, 3, 4, 5]), 3);
+        mem0.write_sized(0x1011, &0xdeadbeefu32);
+        let mut data = [0u8; 3];
+        mem1.read_slice(0x80fd, &mut data);
+        assert_eq!(data, [1, 2, 3]);
+        let mut word: u32 = 0;
+        mem1.read_sized(0x8011, &mut word);
+        assert_eq!(word, 0xdeadbeef);
+        drop(mem0);
+        drop(mem1);
+        std::fs::remove_file(file).unwrap();
+    }
+}
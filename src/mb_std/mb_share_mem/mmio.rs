@@ -0,0 +1,138 @@
+use super::share_mem::*;
+use crate::mb_rpcs::*;
+use std::fmt::{self, Debug, Formatter};
+
+pub type MBMmioReadFn = Box<dyn Fn(MBPtrT, &mut [u8]) -> usize + Send>;
+pub type MBMmioWriteFn = Box<dyn FnMut(MBPtrT, &[u8]) -> usize + Send>;
+
+//accesses are dispatched to callbacks with the offset from base instead of storing bytes,
+//callbacks return the accessed length like MBShareMem
+pub struct MBMmioShareMem {
+    base: MBPtrT,
+    size: MBPtrT,
+    read_f: MBMmioReadFn,
+    write_f: MBMmioWriteFn,
+}
+
+impl MBMmioShareMem {
+    pub fn new<
+        R: Fn(MBPtrT, &mut [u8]) -> usize + Send + 'static,
+        W: FnMut(MBPtrT, &[u8]) -> usize + Send + 'static,
+    >(
+        base: MBPtrT,
+        size: MBPtrT,
+        read_f: R,
+        write_f: W,
+    ) -> MBMmioShareMem {
+        MBMmioShareMem {
+            base,
+            size,
+            read_f: Box::new(read_f),
+            write_f: Box::new(write_f),
+        }
+    }
+    //writes are ignored
+    pub fn read_only<R: Fn(MBPtrT, &mut [u8]) -> usize + Send + 'static>(
+        base: MBPtrT,
+        size: MBPtrT,
+        read_f: R,
+    ) -> MBMmioShareMem {
+        Self::new(base, size, read_f, |_, data| data.len())
+    }
+    //reads return 0
+    pub fn write_only<W: FnMut(MBPtrT, &[u8]) -> usize + Send + 'static>(
+        base: MBPtrT,
+        size: MBPtrT,
+        write_f: W,
+    ) -> MBMmioShareMem {
+        Self::new(
+            base,
+            size,
+            |_, data| {
+                data.fill(0);
+                data.len()
+            },
+            write_f,
+        )
+    }
+    fn valid_len(&self, addr: MBPtrT, len: usize) -> usize {
+        std::cmp::min(len as MBPtrT, self.size - (addr - self.base)) as usize
+    }
+}
+
+impl Debug for MBMmioShareMem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("MBMmioShareMem")
+            .field("base", &self.base)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl MBShareMemBlock for MBMmioShareMem {
+    fn base(&self) -> MBPtrT {
+        self.base
+    }
+    fn size(&self) -> MBPtrT {
+        self.size
+    }
+    //registers are not saved to snapshots
+    fn used_ranges(&self) -> Vec<(MBPtrT, MBPtrT)> {
+        vec![]
+    }
+}
+
+impl MBShareMem for MBMmioShareMem {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        let len = self.valid_len(addr, data.len());
+        (self.write_f)(addr - self.base, &data[..len])
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        let len = self.valid_len(addr, data.len());
+        (self.read_f)(addr - self.base, &mut data[..len])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mb_std::mb_share_mem::MBDefaultShareMem;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    #[test]
+    fn mb_mmio_test() {
+        let status = Arc::new(Mutex::new(vec![]));
+        let status_w = status.clone();
+        let timer = AtomicU32::new(0);
+        let mmio = MBMmioShareMem::new(
+            0x1000,
+            0x10,
+            move |offset, data| {
+                if offset == 0 && data.len() == 4 {
+                    let t = timer.fetch_add(100, Ordering::SeqCst);
+                    data.copy_from_slice(&t.to_le_bytes());
+                } else {
+                    data.fill(0);
+                }
+                data.len()
+            },
+            move |offset, data| {
+                status_w.lock().unwrap().push((offset, data.to_vec()));
+                data.len()
+            },
+        );
+        let mut space = MBShareMemSpace::<MBDefaultShareMem>::new();
+        space
+            .add_named_mem("regs", &Arc::new(Mutex::new(MBDefaultShareMem::Mmio(mmio))))
+            .unwrap();
+        let mut t: u32 = 0;
+        space.read_sized(0x1000, &mut t);
+        space.read_sized(0x1000, &mut t);
+        assert_eq!(t, 100);
+        space.write_sized(0x1008, &0x5au32);
+        assert_eq!(
+            *status.lock().unwrap(),
+            vec![(8 as MBPtrT, 0x5au32.to_le_bytes().to_vec())]
+        );
+    }
+}
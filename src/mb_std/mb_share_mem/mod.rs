@@ -1,4 +1,22 @@
+mod async_mem;
+mod atomic;
+mod builtin;
+mod cache;
 mod channel;
+#[cfg(unix)]
+mod mmap;
+mod mmio;
 mod share_mem;
+mod snapshot;
+mod sparse;
+pub use async_mem::*;
+pub use atomic::*;
+pub use builtin::*;
+pub use cache::*;
 pub use channel::*;
+#[cfg(unix)]
+pub use mmap::*;
+pub use mmio::*;
 pub use share_mem::*;
+pub use snapshot::*;
+pub use sparse::*;
@@ -1,60 +1,397 @@
+use super::atomic::*;
 use crate::mb_rpcs::*;
+use async_std::task::{Context, Poll};
+use std::fmt::{self, Display, Formatter};
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::mb_std::mb_loader::*;
 use crate::mb_std::utils::*;
-use xmas_elf::program;
 use xmas_elf::ElfFile;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MBMemAccess {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MBShareMemError {
+    Unmapped(MBPtrT),
+    Partial {
+        addr: MBPtrT,
+        expected: usize,
+        actual: usize,
+    },
+    Permission(String),
+    //atomic access not aligned to its size
+    Unaligned(MBPtrT),
+}
+impl Display for MBShareMemError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MBShareMemError::Unmapped(addr) => write!(f, "no memory found for addr {:#x}!", addr),
+            MBShareMemError::Partial {
+                addr,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "only {} of {} bytes accessed @ {:#x}!",
+                actual, expected, addr
+            ),
+            MBShareMemError::Permission(e) => write!(f, "{}", e),
+            MBShareMemError::Unaligned(addr) => {
+                write!(f, "atomic access @ {:#x} is not aligned!", addr)
+            }
+        }
+    }
+}
+impl From<MBShareMemError> for std::io::Error {
+    fn from(e: MBShareMemError) -> Self {
+        let kind = match e {
+            MBShareMemError::Unmapped(_) => std::io::ErrorKind::AddrNotAvailable,
+            MBShareMemError::Partial { .. } => std::io::ErrorKind::UnexpectedEof,
+            MBShareMemError::Permission(_) => std::io::ErrorKind::PermissionDenied,
+            MBShareMemError::Unaligned(_) => std::io::ErrorKind::InvalidInput,
+        };
+        std::io::Error::new(kind, e.to_string())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MBShareMemPerm {
+    pub read: bool,
+    pub write: bool,
+}
+impl MBShareMemPerm {
+    pub const RW: MBShareMemPerm = MBShareMemPerm {
+        read: true,
+        write: true,
+    };
+    pub const RO: MBShareMemPerm = MBShareMemPerm {
+        read: true,
+        write: false,
+    };
+    pub const WO: MBShareMemPerm = MBShareMemPerm {
+        read: false,
+        write: true,
+    };
+    pub fn parse(s: &str) -> Result<MBShareMemPerm, String> {
+        match s {
+            "rw" => Ok(MBShareMemPerm::RW),
+            "r" => Ok(MBShareMemPerm::RO),
+            "w" => Ok(MBShareMemPerm::WO),
+            _ => Err(format!("invalid perm {:?}, should be r, w or rw!", s)),
+        }
+    }
+    pub fn allows(&self, access: MBMemAccess) -> bool {
+        match access {
+            MBMemAccess::Read => self.read,
+            MBMemAccess::Write => self.write,
+        }
+    }
+}
+impl Default for MBShareMemPerm {
+    fn default() -> Self {
+        MBShareMemPerm::RW
+    }
+}
+
+#[derive(Debug)]
+struct MBShareMemRegion<M: MBShareMemBlock> {
+    name: String,
+    base: MBPtrT,
+    size: MBPtrT,
+    offset: MBPtrT,
+    perm: MBShareMemPerm,
+    mem: Arc<Mutex<M>>,
+}
+impl<M: MBShareMemBlock> MBShareMemRegion<M> {
+    fn end_addr(&self) -> MBPtrT {
+        (self.size - 1) + self.base
+    }
+    fn in_range(&self, addr: MBPtrT) -> bool {
+        addr >= self.base && addr <= self.end_addr()
+    }
+    fn overlapped(&self, other: &MBShareMemRegion<M>) -> bool {
+        self.base <= other.end_addr() && other.base <= self.end_addr()
+    }
+    fn to_mem_addr(&self, addr: MBPtrT, mem: &M) -> MBPtrT {
+        addr - self.base + mem.base() + self.offset
+    }
+    fn check_perm(&self, addr: MBPtrT, access: MBMemAccess) -> Result<(), MBShareMemError> {
+        if self.perm.allows(access) {
+            return Ok(());
+        }
+        Err(MBShareMemError::Permission(format!(
+            "{:?} {} [{:#x} - {:#x}] @ {:#x} is not permitted!",
+            access,
+            self.name,
+            self.base,
+            self.end_addr(),
+            addr
+        )))
+    }
+}
+
 #[derive(Debug)]
 pub struct MBShareMemSpace<M: MBShareMemBlock> {
-    mems: Vec<Arc<Mutex<M>>>,
+    regions: Vec<MBShareMemRegion<M>>,
 }
 impl<M: MBShareMemBlock> MBShareMemSpace<M> {
     pub fn new() -> MBShareMemSpace<M> {
-        MBShareMemSpace { mems: vec![] }
+        MBShareMemSpace { regions: vec![] }
+    }
+    pub fn add_mem(&mut self, mem: &Arc<Mutex<M>>) -> Result<(), String> {
+        self.add_named_mem("<unnamed>", mem)
+    }
+    pub fn add_named_mem(&mut self, name: &str, mem: &Arc<Mutex<M>>) -> Result<(), String> {
+        let base = mem.lock().unwrap().base();
+        self.add_alias(name, mem, base)
+    }
+    //map the whole mem to [base, base + mem.size()), the same mem can be mapped several times as mirrors
+    pub fn add_alias(
+        &mut self,
+        name: &str,
+        mem: &Arc<Mutex<M>>,
+        base: MBPtrT,
+    ) -> Result<(), String> {
+        let size = mem.lock().unwrap().size();
+        self.add_window(name, mem, base, 0, size, MBShareMemPerm::default())
+    }
+    //map [mem.base() + offset, mem.base() + offset + size) to [base, base + size)
+    pub fn add_window(
+        &mut self,
+        name: &str,
+        mem: &Arc<Mutex<M>>,
+        base: MBPtrT,
+        offset: MBPtrT,
+        size: MBPtrT,
+        perm: MBShareMemPerm,
+    ) -> Result<(), String> {
+        let mem_size = mem.lock().unwrap().size();
+        if size == 0 {
+            return Err(format!("{} @ {:#x} is empty!", name, base));
+        }
+        if offset.checked_add(size).map_or(true, |end| end > mem_size) {
+            return Err(format!(
+                "{} window [{:#x} - {:#x}] exceeds memory size {:#x}!",
+                name,
+                offset,
+                offset.wrapping_add(size - 1),
+                mem_size
+            ));
+        }
+        if base.checked_add(size - 1).is_none() {
+            return Err(format!(
+                "{} @ {:#x} with size {:#x} exceeds address space!",
+                name, base, size
+            ));
+        }
+        let region = MBShareMemRegion {
+            name: name.to_string(),
+            base,
+            size,
+            offset,
+            perm,
+            mem: mem.clone(),
+        };
+        if let Some(r) = self.regions.iter().find(|r| r.overlapped(&region)) {
+            return Err(format!(
+                "{} [{:#x} - {:#x}] is overlapped with {} [{:#x} - {:#x}]!",
+                region.name,
+                region.base,
+                region.end_addr(),
+                r.name,
+                r.base,
+                r.end_addr()
+            ));
+        }
+        self.regions.push(region);
+        Ok(())
     }
-    pub fn add_mem(&mut self, mem: &Arc<Mutex<M>>) -> Result<(), &'static str> {
-        let m = mem.lock().unwrap();
-        if self.find_mem_by_addr(m.base()).is_some()
-            || self.find_mem_by_addr(m.end_addr()).is_some()
-        {
-            return Err("Overlapped!");
+    //set perm of all regions named name
+    pub fn set_perm(&mut self, name: &str, perm: MBShareMemPerm) -> Result<(), String> {
+        let mut found = false;
+        for r in self.regions.iter_mut().filter(|r| r.name == name) {
+            r.perm = perm;
+            found = true;
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(format!("no memory named {}!", name))
         }
-        Ok(self.mems.push(mem.clone()))
     }
-    fn find_mem_by_addr(&self, addr: MBPtrT) -> Option<&Arc<Mutex<M>>> {
-        self.mems.iter().find(|m| {
-            let mem = m.lock().unwrap();
-            mem.in_range(addr)
-        })
+    //distinct memories with the name of their first mapping
+    pub(super) fn blocks(&self) -> Vec<(&str, &Arc<Mutex<M>>)> {
+        let mut blocks: Vec<(&str, &Arc<Mutex<M>>)> = vec![];
+        for r in self.regions.iter() {
+            if !blocks.iter().any(|(_, m)| Arc::ptr_eq(m, &r.mem)) {
+                blocks.push((&r.name, &r.mem));
+            }
+        }
+        blocks
+    }
+    fn find_region_by_addr(&self, addr: MBPtrT) -> Option<&MBShareMemRegion<M>> {
+        self.regions.iter().find(|r| r.in_range(addr))
     }
 }
-impl<M: MBShareMemBlock> MBShareMem for MBShareMemSpace<M> {
-    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
-        if data.len() == 0 {
-            return 0;
-        }
-        if let Some(m) = self.find_mem_by_addr(addr) {
-            let r = m.lock().unwrap().write(addr, data);
-            if r > 0 && r < data.len() {
-                r + self.write(addr + r as MBPtrT, &data[r..])
+impl<M: MBShareMemBlock> MBShareMemSpace<M> {
+    //access len bytes from addr across regions, stop at an unmapped addr, a region not permitting it
+    //or a region accessing less bytes, perm is not checked without access
+    fn access<F: FnMut(&mut M, MBPtrT, std::ops::Range<usize>) -> usize>(
+        &self,
+        addr: MBPtrT,
+        len: usize,
+        access: Option<MBMemAccess>,
+        mut f: F,
+    ) -> (usize, Option<MBShareMemError>) {
+        let mut pos = 0;
+        while pos < len {
+            let cur = addr.wrapping_add(pos as MBPtrT);
+            let region = if let Some(region) = self.find_region_by_addr(cur) {
+                region
             } else {
-                r
+                return (pos, Some(MBShareMemError::Unmapped(cur)));
+            };
+            if let Some(Err(e)) = access.map(|a| region.check_perm(cur, a)) {
+                return (pos, Some(e));
+            }
+            let mut m = region.mem.lock().unwrap();
+            let l = std::cmp::min(
+                (len - pos) as MBPtrT,
+                (region.end_addr() - cur).saturating_add(1),
+            ) as usize;
+            let mem_addr = region.to_mem_addr(cur, &m);
+            let done = f(&mut m, mem_addr, pos..pos + l);
+            pos += done;
+            if done < l {
+                break;
             }
-        } else {
-            panic!("no memory found for addr {:x}!", addr)
         }
+        (pos, None)
+    }
+}
+//result of a try_* access stopped at (actual, why), partial if the why is unknown
+pub(super) fn mb_access_result(
+    addr: MBPtrT,
+    expected: usize,
+    (actual, e): (usize, Option<MBShareMemError>),
+) -> Result<(), MBShareMemError> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(e.unwrap_or(MBShareMemError::Partial {
+            addr,
+            expected,
+            actual,
+        }))
+    }
+}
+//ignores perm, images are loaded into read-only memories through it
+struct MBShareMemSpaceBackdoor<'a, M: MBShareMemBlock>(&'a mut MBShareMemSpace<M>);
+impl<M: MBShareMemBlock> MBShareMem for MBShareMemSpaceBackdoor<'_, M> {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        self.0
+            .access(addr, data.len(), None, |m, a, range| {
+                m.write(a, &data[range])
+            })
+            .0
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        let len = data.len();
+        self.0
+            .access(addr, len, None, |m, a, range| m.read(a, &mut data[range]))
+            .0
+    }
+}
+
+//accesses stop short at unmapped or not permitted addrs, try_* tell why
+impl<M: MBShareMemBlock> MBShareMem for MBShareMemSpace<M> {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        self.access(addr, data.len(), Some(MBMemAccess::Write), |m, a, range| {
+            m.write(a, &data[range])
+        })
+        .0
     }
     fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
-        if data.len() == 0 {
-            return 0;
+        let len = data.len();
+        self.access(addr, len, Some(MBMemAccess::Read), |m, a, range| {
+            m.read(a, &mut data[range])
+        })
+        .0
+    }
+    fn try_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        let r = self.access(addr, data.len(), Some(MBMemAccess::Write), |m, a, range| {
+            m.write(a, &data[range])
+        });
+        mb_access_result(addr, data.len(), r)
+    }
+    fn try_read(&self, addr: MBPtrT, data: &mut [u8]) -> Result<(), MBShareMemError> {
+        let len = data.len();
+        let r = self.access(addr, len, Some(MBMemAccess::Read), |m, a, range| {
+            m.read(a, &mut data[range])
+        });
+        mb_access_result(addr, len, r)
+    }
+    fn atomic(
+        &mut self,
+        addr: MBPtrT,
+        size: usize,
+        op: MBAtomicOp,
+    ) -> Result<u64, MBShareMemError> {
+        let region = self
+            .find_region_by_addr(addr)
+            .ok_or(MBShareMemError::Unmapped(addr))?;
+        region.check_perm(addr, MBMemAccess::Write)?;
+        let available = (region.end_addr() - addr).saturating_add(1);
+        if available < size as MBPtrT {
+            return Err(MBShareMemError::Partial {
+                addr,
+                expected: size,
+                actual: available as usize,
+            });
         }
-        if let Some(m) = self.find_mem_by_addr(addr) {
-            m.lock().unwrap().read(addr, data)
-        } else {
-            panic!("no memory found for addr {:x}!", addr)
+        let mut m = region.mem.lock().unwrap();
+        let mem_addr = region.to_mem_addr(addr, &m);
+        m.atomic(mem_addr, size, op)
+    }
+    fn check_access(
+        &self,
+        addr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        let mut cur = addr;
+        let mut remain = len as MBPtrT;
+        while remain > 0 {
+            let region = self
+                .find_region_by_addr(cur)
+                .ok_or(MBShareMemError::Unmapped(cur))?;
+            region.check_perm(cur, access)?;
+            let l = std::cmp::min(remain, (region.end_addr() - cur).saturating_add(1));
+            remain -= l;
+            cur = cur.wrapping_add(l);
         }
+        Ok(())
+    }
+    fn backdoor_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        let r = self.access(addr, data.len(), None, |m, a, range| {
+            m.write(a, &data[range])
+        });
+        mb_access_result(addr, data.len(), r)
+    }
+    fn load_image(&mut self, file: &str, opts: &MBLoadOpts) -> Result<MBLoadReport, String> {
+        mb_load_image(&mut MBShareMemSpaceBackdoor(self), file, opts)
+    }
+    fn load_elf_with<F: FnMut(&ElfFile, &str) -> Result<(), String>>(
+        &mut self,
+        file: &str,
+        f: F,
+    ) -> Result<(), String> {
+        MBShareMemSpaceBackdoor(self).load_elf_with(file, f)
     }
 }
 pub trait MBShareMemBlock: MBShareMem {
@@ -66,47 +403,94 @@ pub trait MBShareMemBlock: MBShareMem {
     fn in_range(&self, addr: MBPtrT) -> bool {
         addr >= self.base() && addr <= self.end_addr()
     }
+    //(addr, size) holding data, snapshots only save these, the others read as fill()
+    fn used_ranges(&self) -> Vec<(MBPtrT, MBPtrT)> {
+        vec![(self.base(), self.size())]
+    }
+    fn fill(&self) -> u8 {
+        0
+    }
+    //drop data so that unused ranges read as initial, called before restoring a snapshot
+    fn discard(&mut self) {}
 }
 pub trait MBShareMem {
     fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize;
     fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize;
+    fn check_access(
+        &self,
+        _addr: MBPtrT,
+        _len: usize,
+        _access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        Ok(())
+    }
+    fn try_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        let actual = self.write(addr, data);
+        if actual != data.len() {
+            return Err(MBShareMemError::Partial {
+                addr,
+                expected: data.len(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+    fn try_read(&self, addr: MBPtrT, data: &mut [u8]) -> Result<(), MBShareMemError> {
+        let actual = self.read(addr, data);
+        if actual != data.len() {
+            return Err(MBShareMemError::Partial {
+                addr,
+                expected: data.len(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+    //write ignoring the permissions, e.g. to restore read-only memories
+    fn backdoor_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        self.try_write(addr, data)
+    }
     fn write_sized<T: Sized>(&mut self, addr: MBPtrT, data: &T) {
-        let len = self.write(addr, unsafe {
-            std::slice::from_raw_parts((data as *const T) as *const u8, std::mem::size_of::<T>())
-        });
-        if len != std::mem::size_of::<T>() {
-            panic!("write_sized @ {:#x} misatched!", addr)
+        if let Err(e) = self.try_write_sized(addr, data) {
+            panic!("write_sized @ {:#x} misatched! {}", addr, e)
         }
     }
 
     fn read_sized<T: Sized>(&self, addr: MBPtrT, data: &mut T) {
-        let len = self.read(addr, unsafe {
-            std::slice::from_raw_parts_mut((data as *mut T) as *mut u8, std::mem::size_of::<T>())
-        });
-        if len != std::mem::size_of::<T>() {
-            panic!("read_sized @ {:#x} misatched!", addr)
+        if let Err(e) = self.try_read_sized(addr, data) {
+            panic!("read_sized @ {:#x} misatched! {}", addr, e)
         }
     }
 
     fn write_slice<T: Sized>(&mut self, addr: MBPtrT, data: &[T]) {
-        let len = self.try_write_slice(addr, data);
-        if len != std::mem::size_of::<T>() * data.len() {
-            panic!("write_slice @ {:#x} misatched!", addr)
+        if let Err(e) = self.try_write_slice_checked(addr, data) {
+            panic!("write_slice @ {:#x} misatched! {}", addr, e)
         }
     }
 
     fn read_slice<T: Sized>(&self, addr: MBPtrT, data: &mut [T]) {
-        let len = self.try_read_slice(addr, data);
-        if len != std::mem::size_of::<T>() * data.len() {
-            panic!("read_slice @ {:#x} misatched!", addr)
+        if let Err(e) = self.try_read_slice_checked(addr, data) {
+            panic!("read_slice @ {:#x} misatched! {}", addr, e)
         }
     }
 
+    fn try_write_sized<T: Sized>(&mut self, addr: MBPtrT, data: &T) -> Result<(), MBShareMemError> {
+        self.try_write(addr, unsafe {
+            std::slice::from_raw_parts((data as *const T) as *const u8, std::mem::size_of::<T>())
+        })
+    }
+
+    fn try_read_sized<T: Sized>(&self, addr: MBPtrT, data: &mut T) -> Result<(), MBShareMemError> {
+        self.try_read(addr, unsafe {
+            std::slice::from_raw_parts_mut((data as *mut T) as *mut u8, std::mem::size_of::<T>())
+        })
+    }
+
     fn try_write_slice<T: Sized>(&mut self, addr: MBPtrT, data: &[T]) -> usize {
         self.write(addr, unsafe {
             std::slice::from_raw_parts(
                 (data.as_ptr() as *const T) as *const u8,
-                std::mem::size_of::<T>() * data.len(),
+                std::mem::size_of_val(data),
             )
         })
     }
@@ -115,11 +499,116 @@ pub trait MBShareMem {
         self.read(addr, unsafe {
             std::slice::from_raw_parts_mut(
                 (data.as_mut_ptr() as *mut T) as *mut u8,
-                std::mem::size_of::<T>() * data.len(),
+                std::mem::size_of_val(data),
+            )
+        })
+    }
+
+    fn try_write_slice_checked<T: Sized>(
+        &mut self,
+        addr: MBPtrT,
+        data: &[T],
+    ) -> Result<(), MBShareMemError> {
+        self.try_write(addr, unsafe {
+            std::slice::from_raw_parts(
+                (data.as_ptr() as *const T) as *const u8,
+                std::mem::size_of_val(data),
+            )
+        })
+    }
+
+    fn try_read_slice_checked<T: Sized>(
+        &self,
+        addr: MBPtrT,
+        data: &mut [T],
+    ) -> Result<(), MBShareMemError> {
+        self.try_read(addr, unsafe {
+            std::slice::from_raw_parts_mut(
+                (data.as_mut_ptr() as *mut T) as *mut u8,
+                std::mem::size_of_val(data),
             )
         })
     }
 
+    //hooks of memories staging an async one, see MBStagedShareMem
+    fn poll_load(&mut self, _addr: MBPtrT, _len: usize, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn poll_store(&mut self, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn release(&mut self) {}
+
+    //apply op to the size bytes word at addr as one access and return the old value,
+    //backends shared with other agents should override it with real atomics
+    fn atomic(
+        &mut self,
+        addr: MBPtrT,
+        size: usize,
+        op: MBAtomicOp,
+    ) -> Result<u64, MBShareMemError> {
+        check_atomic_size(size);
+        let mut bytes = [0u8; 8];
+        self.try_read(addr, &mut bytes[..size])?;
+        let old = atomic_from_bytes(&bytes[..size]);
+        if let Some(new) = op.apply(old, size) {
+            atomic_to_bytes(new, &mut bytes[..size]);
+            self.try_write(addr, &bytes[..size])?;
+        }
+        Ok(old)
+    }
+
+    //returns the old value, new is stored only if the old value equals current
+    fn atomic_cas<T: MBAtomicInt>(
+        &mut self,
+        addr: MBPtrT,
+        current: T,
+        new: T,
+    ) -> Result<T, MBShareMemError> {
+        self.atomic(
+            addr,
+            std::mem::size_of::<T>(),
+            MBAtomicOp::Cas(current.to_u64(), new.to_u64()),
+        )
+        .map(T::from_u64)
+    }
+
+    fn atomic_swap<T: MBAtomicInt>(&mut self, addr: MBPtrT, v: T) -> Result<T, MBShareMemError> {
+        self.atomic(addr, std::mem::size_of::<T>(), MBAtomicOp::Swap(v.to_u64()))
+            .map(T::from_u64)
+    }
+
+    fn atomic_fetch_add<T: MBAtomicInt>(
+        &mut self,
+        addr: MBPtrT,
+        v: T,
+    ) -> Result<T, MBShareMemError> {
+        self.atomic(addr, std::mem::size_of::<T>(), MBAtomicOp::Add(v.to_u64()))
+            .map(T::from_u64)
+    }
+
+    fn atomic_fetch_and<T: MBAtomicInt>(
+        &mut self,
+        addr: MBPtrT,
+        v: T,
+    ) -> Result<T, MBShareMemError> {
+        self.atomic(addr, std::mem::size_of::<T>(), MBAtomicOp::And(v.to_u64()))
+            .map(T::from_u64)
+    }
+
+    fn atomic_fetch_or<T: MBAtomicInt>(
+        &mut self,
+        addr: MBPtrT,
+        v: T,
+    ) -> Result<T, MBShareMemError> {
+        self.atomic(addr, std::mem::size_of::<T>(), MBAtomicOp::Or(v.to_u64()))
+            .map(T::from_u64)
+    }
+
+    fn load_image(&mut self, file: &str, opts: &MBLoadOpts) -> Result<MBLoadReport, String> {
+        mb_load_image(self, file, opts)
+    }
+
     fn load_elf(&mut self, file: &str) -> Result<(), String> {
         self.load_elf_with(file, |_, _| Ok(()))
     }
@@ -131,22 +620,7 @@ pub trait MBShareMem {
     ) -> Result<(), String> {
         process_elf(file, |elf, file| {
             f(elf, file)?;
-            elf.program_iter().for_each(|p| {
-                if let Ok(program::Type::Load) = p.get_type() {
-                    if let Ok(program::SegmentData::Undefined(d)) = p.get_data(&elf) {
-                        let addr = p.physical_addr() as MBPtrT;
-                        println!(
-                            "load elf {} segment({}) @ {:#x} - {:#x}!",
-                            file,
-                            d.len() as MBPtrT,
-                            addr,
-                            addr + d.len() as MBPtrT
-                        );
-                        self.write(addr, d);
-                    }
-                }
-            });
-            Ok(())
+            mb_load_elf(self, elf, file, &MBLoadOpts::default()).map(|_| ())
         })
     }
 }
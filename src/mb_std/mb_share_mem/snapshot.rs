@@ -0,0 +1,422 @@
+use super::share_mem::*;
+use crate::mb_rpcs::*;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::sync::{Arc, Mutex};
+
+pub const MB_SNAPSHOT_CHUNK_SIZE: usize = 4096;
+const MB_SNAPSHOT_MAGIC: &[u8; 8] = b"MBSNAP\0\x01";
+
+//data is None for all zero chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBSnapshotChunk {
+    pub addr: MBPtrT,
+    pub size: usize,
+    pub data: Option<Vec<u8>>,
+}
+
+impl MBSnapshotChunk {
+    fn end_addr(&self) -> MBPtrT {
+        self.addr + self.size as MBPtrT
+    }
+}
+
+//bytes of a range in a single chunk or gap
+#[derive(PartialEq, Eq)]
+enum MBSnapshotSpan<'a> {
+    Data(&'a [u8]),
+    Fill(u8),
+}
+
+impl MBSnapshotSpan<'_> {
+    fn byte(&self, i: usize) -> u8 {
+        match self {
+            MBSnapshotSpan::Data(d) => d[i],
+            MBSnapshotSpan::Fill(f) => *f,
+        }
+    }
+    fn same(&self, other: &MBSnapshotSpan) -> bool {
+        match (self, other) {
+            (MBSnapshotSpan::Data(d), MBSnapshotSpan::Fill(f))
+            | (MBSnapshotSpan::Fill(f), MBSnapshotSpan::Data(d)) => d.iter().all(|b| b == f),
+            _ => self == other,
+        }
+    }
+}
+
+//addresses are local addresses of the memory block, bytes out of chunks read as fill
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBSnapshotBlock {
+    pub name: String,
+    pub base: MBPtrT,
+    pub size: MBPtrT,
+    pub fill: u8,
+    pub chunks: Vec<MBSnapshotChunk>,
+}
+
+impl MBSnapshotBlock {
+    fn push(&mut self, addr: MBPtrT, data: &[u8]) {
+        let zero = data.iter().all(|b| *b == 0);
+        if let Some(last) = self.chunks.last_mut() {
+            if last.end_addr() == addr && last.data.is_none() == zero {
+                last.size += data.len();
+                if let Some(d) = last.data.as_mut() {
+                    d.extend_from_slice(data)
+                }
+                return;
+            }
+        }
+        self.chunks.push(MBSnapshotChunk {
+            addr,
+            size: data.len(),
+            data: if zero { None } else { Some(data.to_vec()) },
+        })
+    }
+    //[start, end) should not cross chunk boundaries
+    fn span(&self, start: MBPtrT, end: MBPtrT) -> MBSnapshotSpan<'_> {
+        let i = self.chunks.partition_point(|c| c.end_addr() <= start);
+        match self.chunks.get(i).filter(|c| c.addr <= start) {
+            Some(c) => c.data.as_ref().map_or(MBSnapshotSpan::Fill(0), |d| {
+                MBSnapshotSpan::Data(&d[(start - c.addr) as usize..(end - c.addr) as usize])
+            }),
+            None => MBSnapshotSpan::Fill(self.fill),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBSnapshotDiff {
+    pub name: String,
+    pub addr: MBPtrT,
+    pub size: usize,
+}
+
+impl Display for MBSnapshotDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} [{:#x} - {:#x}] {} bytes changed",
+            self.name,
+            self.addr,
+            self.addr + self.size as MBPtrT - 1,
+            self.size
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MBSnapshot {
+    pub blocks: Vec<MBSnapshotBlock>,
+}
+
+impl MBSnapshot {
+    pub fn get_block(&self, name: &str) -> Option<&MBSnapshotBlock> {
+        self.blocks.iter().find(|b| b.name == name)
+    }
+
+    //file layout, little endian:
+    //magic, block count(u32), blocks of
+    //  name len(u32), name, base(u64), size(u64), fill(u8), chunk count(u32), chunks of
+    //    addr(u64), size(u64), zero(u8), data if not zero
+    pub fn save(&self, file: &str) -> Result<(), String> {
+        let file_expand = shellexpand::full(file)
+            .map_err(|e| e.to_string())?
+            .to_string();
+        let err_handler = |e: std::io::Error| format!("save snapshot {} fail! {}", file_expand, e);
+        let mut w = BufWriter::new(fs::File::create(&file_expand).map_err(err_handler)?);
+        let mut write = || -> std::io::Result<()> {
+            w.write_all(MB_SNAPSHOT_MAGIC)?;
+            w.write_all(&(self.blocks.len() as u32).to_le_bytes())?;
+            for b in self.blocks.iter() {
+                w.write_all(&(b.name.len() as u32).to_le_bytes())?;
+                w.write_all(b.name.as_bytes())?;
+                w.write_all(&(b.base as u64).to_le_bytes())?;
+                w.write_all(&(b.size as u64).to_le_bytes())?;
+                w.write_all(&[b.fill])?;
+                w.write_all(&(b.chunks.len() as u32).to_le_bytes())?;
+                for c in b.chunks.iter() {
+                    w.write_all(&(c.addr as u64).to_le_bytes())?;
+                    w.write_all(&(c.size as u64).to_le_bytes())?;
+                    w.write_all(&[c.data.is_none() as u8])?;
+                    if let Some(d) = c.data.as_ref() {
+                        w.write_all(d)?;
+                    }
+                }
+            }
+            w.flush()
+        };
+        write().map_err(err_handler)
+    }
+
+    pub fn load(file: &str) -> Result<MBSnapshot, String> {
+        let file_expand = shellexpand::full(file)
+            .map_err(|e| e.to_string())?
+            .to_string();
+        let err_handler = |e: std::io::Error| format!("load snapshot {} fail! {}", file_expand, e);
+        let mut r = BufReader::new(fs::File::open(&file_expand).map_err(err_handler)?);
+        let mut read = || -> std::io::Result<MBSnapshot> {
+            if read_bytes(&mut r, MB_SNAPSHOT_MAGIC.len())? != MB_SNAPSHOT_MAGIC {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "not a snapshot file!",
+                ));
+            }
+            let mut snapshot = MBSnapshot::default();
+            for _ in 0..read_u32(&mut r)? {
+                let name_len = read_u32(&mut r)? as usize;
+                let name = String::from_utf8(read_bytes(&mut r, name_len)?)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                let mut block = MBSnapshotBlock {
+                    name,
+                    base: read_u64(&mut r)? as MBPtrT,
+                    size: read_u64(&mut r)? as MBPtrT,
+                    fill: read_bytes(&mut r, 1)?[0],
+                    chunks: vec![],
+                };
+                for _ in 0..read_u32(&mut r)? {
+                    let addr = read_u64(&mut r)? as MBPtrT;
+                    let size = read_u64(&mut r)? as usize;
+                    let zero = read_bytes(&mut r, 1)?[0] != 0;
+                    block.chunks.push(MBSnapshotChunk {
+                        addr,
+                        size,
+                        data: if zero {
+                            None
+                        } else {
+                            Some(read_bytes(&mut r, size)?)
+                        },
+                    });
+                }
+                snapshot.blocks.push(block);
+            }
+            Ok(snapshot)
+        };
+        read().map_err(err_handler)
+    }
+
+    //changed ranges of blocks in both snapshots
+    pub fn diff(&self, other: &MBSnapshot) -> Vec<MBSnapshotDiff> {
+        let mut diffs = vec![];
+        for b in self.blocks.iter() {
+            let o = if let Some(o) = other.get_block(&b.name) {
+                o
+            } else {
+                continue;
+            };
+            //split at boundaries of chunks in both blocks, only scan ranges differ
+            let mut addrs = b
+                .chunks
+                .iter()
+                .chain(o.chunks.iter())
+                .flat_map(|c| [c.addr, c.end_addr()])
+                .collect::<Vec<_>>();
+            addrs.sort();
+            addrs.dedup();
+            let mut cur: Option<MBSnapshotDiff> = None;
+            for w in addrs.windows(2) {
+                let (start, end) = (w[0], w[1]);
+                let (bs, os) = (b.span(start, end), o.span(start, end));
+                if bs.same(&os) {
+                    continue;
+                }
+                for i in 0..(end - start) as usize {
+                    if bs.byte(i) == os.byte(i) {
+                        continue;
+                    }
+                    let addr = start + i as MBPtrT;
+                    match cur.as_mut() {
+                        Some(d) if d.addr + d.size as MBPtrT == addr => d.size += 1,
+                        _ => {
+                            diffs.extend(cur.take());
+                            cur = Some(MBSnapshotDiff {
+                                name: b.name.clone(),
+                                addr,
+                                size: 1,
+                            })
+                        }
+                    }
+                }
+            }
+            diffs.extend(cur.take());
+        }
+        diffs
+    }
+}
+
+type MBNamedBlocks<'a, M> = Vec<(&'a str, &'a Arc<Mutex<M>>)>;
+
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl<M: MBShareMemBlock> MBShareMemSpace<M> {
+    //snapshot blocks named in names, or all blocks if names is empty
+    pub fn snapshot(&self, names: &[&str]) -> Result<MBSnapshot, String> {
+        let blocks = self.selected_blocks(names)?;
+        let mut snapshot = MBSnapshot::default();
+        let mut buf = vec![0u8; MB_SNAPSHOT_CHUNK_SIZE];
+        for (name, mem) in blocks {
+            let m = mem.lock().unwrap();
+            let mut block = MBSnapshotBlock {
+                name: name.to_string(),
+                base: m.base(),
+                size: m.size(),
+                fill: m.fill(),
+                chunks: vec![],
+            };
+            for (addr, size) in m.used_ranges() {
+                let mut pos: MBPtrT = 0;
+                while pos < size {
+                    let l = std::cmp::min(MB_SNAPSHOT_CHUNK_SIZE as MBPtrT, size - pos) as usize;
+                    let len = m.read(addr + pos, &mut buf[..l]);
+                    if len != l {
+                        return Err(format!(
+                            "snapshot {} @ {:#x} fail, only {} of {} bytes read!",
+                            name,
+                            addr + pos,
+                            len,
+                            l
+                        ));
+                    }
+                    block.push(addr + pos, &buf[..l]);
+                    pos += l as MBPtrT;
+                }
+            }
+            snapshot.blocks.push(block);
+        }
+        Ok(snapshot)
+    }
+
+    //restore blocks named in names, or all blocks in the snapshot if names is empty
+    pub fn restore(&mut self, snapshot: &MBSnapshot, names: &[&str]) -> Result<(), String> {
+        for (name, mem) in self.selected_blocks(names)? {
+            let b = if let Some(b) = snapshot.get_block(name) {
+                b
+            } else if names.is_empty() {
+                continue;
+            } else {
+                return Err(format!("{} not found in snapshot!", name));
+            };
+            let mut m = mem.lock().unwrap();
+            if m.base() != b.base || m.size() != b.size {
+                return Err(format!(
+                    "{} [{:#x} - {:#x}] mismatches snapshot [{:#x} - {:#x}]!",
+                    name,
+                    m.base(),
+                    m.end_addr(),
+                    b.base,
+                    b.base + (b.size - 1)
+                ));
+            }
+            m.discard();
+            let zeros = vec![0u8; MB_SNAPSHOT_CHUNK_SIZE];
+            for c in b.chunks.iter() {
+                if let Some(d) = c.data.as_ref() {
+                    m.write(c.addr, d);
+                } else {
+                    let mut pos = 0;
+                    while pos < c.size {
+                        let l = std::cmp::min(zeros.len(), c.size - pos);
+                        m.write(c.addr + pos as MBPtrT, &zeros[..l]);
+                        pos += l;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn selected_blocks(&self, names: &[&str]) -> Result<MBNamedBlocks<'_, M>, String> {
+        let blocks = self.blocks();
+        for n in names.iter() {
+            if !blocks.iter().any(|(name, _)| name == n) {
+                return Err(format!("no memory named {}!", n));
+            }
+        }
+        Ok(blocks
+            .into_iter()
+            .filter(|(name, _)| names.is_empty() || names.contains(name))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mb_std::mb_share_mem::{MBDefaultShareMem, MBSparseShareMem};
+    #[test]
+    fn mb_snapshot_test() {
+        let mut space = MBShareMemSpace::<MBDefaultShareMem>::new();
+        for (name, base) in [("ram0", 0x1000), ("ram1", 0x80000000)] {
+            space
+                .add_named_mem(
+                    name,
+                    &Arc::new(Mutex::new(MBDefaultShareMem::Sparse(
+                        MBSparseShareMem::with_page_size(base, 0x40000000, 0x100, 0xa5),
+                    ))),
+                )
+                .unwrap();
+        }
+        space.write_slice(0x10fe, &[1u8, 2, 3, 4]);
+        space.write_slice(0x80000000, &[0u8; 0x100]);
+        let s0 = space.snapshot(&[]).unwrap();
+        assert_eq!(s0.get_block("ram0").unwrap().chunks.len(), 1);
+        assert_eq!(s0.get_block("ram1").unwrap().chunks[0].data, None);
+        let file = std::env::temp_dir().join(format!("mb_snapshot_test_{}", std::process::id()));
+        let path = file.to_str().unwrap();
+        s0.save(path).unwrap();
+        let s0 = MBSnapshot::load(path).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        space.write_slice(0x10ff, &[5u8, 6]);
+        space.write_slice(0x2000, &[7u8]);
+        space.write_slice(0x80000010, &[8u8]);
+        let s1 = space.snapshot(&["ram0"]).unwrap();
+        assert!(s0.diff(&s0).is_empty());
+        let diffs = s0.diff(&s1);
+        assert_eq!(
+            diffs.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            vec![
+                "ram0 [0x10ff - 0x1100] 2 bytes changed",
+                "ram0 [0x2000 - 0x2000] 1 bytes changed"
+            ]
+        );
+
+        //zero chunk against written zeros
+        space.write_slice(0x80000000, &[0u8; 0x100]);
+        space.write_slice(0x80000020, &[9u8]);
+        let s2 = space.snapshot(&["ram1"]).unwrap();
+        assert_eq!(
+            s0.diff(&s2)
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>(),
+            vec!["ram1 [0x80000020 - 0x80000020] 1 bytes changed"]
+        );
+
+        space.restore(&s0, &[]).unwrap();
+        let mut data = [0u8; 4];
+        space.read_slice(0x10fe, &mut data);
+        assert_eq!(data, [1, 2, 3, 4]);
+        space.read_slice(0x2000, &mut data[..1]);
+        assert_eq!(data[0], 0xa5);
+        space.read_slice(0x80000010, &mut data[..1]);
+        assert_eq!(data[0], 0);
+        assert!(space.restore(&s1, &["ram1"]).is_err());
+    }
+}
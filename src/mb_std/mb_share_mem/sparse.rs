@@ -0,0 +1,115 @@
+use super::share_mem::*;
+use crate::mb_rpcs::*;
+use std::collections::BTreeMap;
+
+pub const MB_SPARSE_PAGE_SIZE: usize = 4096;
+
+//pages are allocated on first write, unwritten pages read as fill
+#[derive(Debug)]
+pub struct MBSparseShareMem {
+    base: MBPtrT,
+    size: MBPtrT,
+    page_size: usize,
+    fill: u8,
+    pages: BTreeMap<usize, Box<[u8]>>,
+}
+
+impl MBSparseShareMem {
+    pub fn new(base: MBPtrT, size: MBPtrT) -> MBSparseShareMem {
+        Self::with_page_size(base, size, MB_SPARSE_PAGE_SIZE, 0)
+    }
+    pub fn with_page_size(
+        base: MBPtrT,
+        size: MBPtrT,
+        page_size: usize,
+        fill: u8,
+    ) -> MBSparseShareMem {
+        assert!(page_size > 0, "page_size should not be 0!");
+        MBSparseShareMem {
+            base,
+            size,
+            page_size,
+            fill,
+            pages: BTreeMap::new(),
+        }
+    }
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+    //allocated pages as (page address, content)
+    pub fn pages(&self) -> impl Iterator<Item = (MBPtrT, &[u8])> + '_ {
+        self.pages
+            .iter()
+            .map(|(idx, p)| (self.base + (idx * self.page_size) as MBPtrT, &p[..]))
+    }
+    pub fn clear(&mut self) {
+        self.pages.clear()
+    }
+    fn valid_len(&self, addr: MBPtrT, len: usize) -> usize {
+        let offset = addr - self.base;
+        std::cmp::min(len as MBPtrT, self.size - offset) as usize
+    }
+}
+
+impl MBShareMemBlock for MBSparseShareMem {
+    fn base(&self) -> MBPtrT {
+        self.base
+    }
+    fn size(&self) -> MBPtrT {
+        self.size
+    }
+    fn used_ranges(&self) -> Vec<(MBPtrT, MBPtrT)> {
+        self.pages()
+            .map(|(addr, p)| {
+                (
+                    addr,
+                    std::cmp::min(p.len() as MBPtrT, self.end_addr() - addr + 1),
+                )
+            })
+            .collect()
+    }
+    fn fill(&self) -> u8 {
+        self.fill
+    }
+    fn discard(&mut self) {
+        self.clear()
+    }
+}
+
+impl MBShareMem for MBSparseShareMem {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        let len = self.valid_len(addr, data.len());
+        let mut offset = (addr - self.base) as usize;
+        let mut pos = 0;
+        while pos < len {
+            let page_offset = offset % self.page_size;
+            let l = std::cmp::min(self.page_size - page_offset, len - pos);
+            let (page_size, fill) = (self.page_size, self.fill);
+            let page = self
+                .pages
+                .entry(offset / page_size)
+                .or_insert_with(|| vec![fill; page_size].into_boxed_slice());
+            page[page_offset..page_offset + l].copy_from_slice(&data[pos..pos + l]);
+            pos += l;
+            offset += l;
+        }
+        len
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        let len = self.valid_len(addr, data.len());
+        let mut offset = (addr - self.base) as usize;
+        let mut pos = 0;
+        while pos < len {
+            let page_offset = offset % self.page_size;
+            let l = std::cmp::min(self.page_size - page_offset, len - pos);
+            if let Some(page) = self.pages.get(&(offset / self.page_size)) {
+                data[pos..pos + l].copy_from_slice(&page[page_offset..page_offset + l]);
+            } else {
+                data[pos..pos + l].fill(self.fill);
+            }
+            pos += l;
+            offset += l;
+        }
+        len
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//receives the text of PRINT and CPRINT, flushed on '\n'
+pub trait MBOutputSink: Send + Sync {
+    fn write(&self, server_name: &str, text: &str);
+}
+
+pub type MBOutputSinks = Vec<Arc<dyn MBOutputSink>>;
+
+pub fn mb_default_sinks() -> MBOutputSinks {
+    vec![Arc::new(MBStdoutSink::default())]
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MBSinkFormat {
+    //"[server_name] " before each line
+    pub prefix: bool,
+    //seconds since the unix epoch before each line
+    pub timestamp: bool,
+    //ansi colored prefix, picked by the server name
+    pub color: bool,
+}
+
+impl Default for MBSinkFormat {
+    fn default() -> Self {
+        MBSinkFormat {
+            prefix: true,
+            timestamp: false,
+            color: false,
+        }
+    }
+}
+
+impl MBSinkFormat {
+    pub fn format(&self, server_name: &str, text: &str) -> String {
+        if !self.prefix && !self.timestamp {
+            return text.to_string();
+        }
+        let mut head = String::new();
+        if self.timestamp {
+            let t = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            head += &format!("[{}.{:06}] ", t.as_secs(), t.subsec_micros());
+        }
+        if self.prefix {
+            if self.color {
+                let mut hasher = DefaultHasher::new();
+                server_name.hash(&mut hasher);
+                head += &format!(
+                    "\x1b[{}m[{}]\x1b[0m ",
+                    31 + hasher.finish() % 6,
+                    server_name
+                );
+            } else {
+                head += &format!("[{}] ", server_name);
+            }
+        }
+        text.split_inclusive('\n')
+            .map(|l| format!("{}{}", head, l))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MBStdoutSink {
+    pub format: MBSinkFormat,
+}
+
+impl MBStdoutSink {
+    pub fn new(format: MBSinkFormat) -> MBStdoutSink {
+        MBStdoutSink { format }
+    }
+}
+
+impl MBOutputSink for MBStdoutSink {
+    fn write(&self, server_name: &str, text: &str) {
+        print!("{}", self.format.format(server_name, text));
+    }
+}
+
+//share one sink between channels for a combined log
+pub struct MBFileSink {
+    file: Mutex<File>,
+    format: MBSinkFormat,
+}
+
+impl MBFileSink {
+    pub fn new(path: &str, format: MBSinkFormat) -> Result<MBFileSink, String> {
+        let path = shellexpand::full(path).map_err(|e| e.to_string())?;
+        let file = File::create(path.as_ref()).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(MBFileSink {
+            file: Mutex::new(file),
+            format,
+        })
+    }
+    pub fn format(&self) -> MBSinkFormat {
+        self.format
+    }
+}
+
+impl MBOutputSink for MBFileSink {
+    fn write(&self, server_name: &str, text: &str) {
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(self.format.format(server_name, text).as_bytes()) {
+            println!("[{}(server)] failed to write log: {}", server_name, e);
+        }
+    }
+}
+
+//keeps the output in memory, e.g. to check it in tests
+#[derive(Debug, Default)]
+pub struct MBCaptureSink {
+    lines: Mutex<Vec<(String, String)>>,
+}
+
+impl MBCaptureSink {
+    pub fn new() -> MBCaptureSink {
+        MBCaptureSink::default()
+    }
+    //(server_name, text) in the order written
+    pub fn take(&self) -> Vec<(String, String)> {
+        std::mem::take(&mut *self.lines.lock().unwrap())
+    }
+}
+
+impl MBOutputSink for MBCaptureSink {
+    fn write(&self, server_name: &str, text: &str) {
+        self.lines
+            .lock()
+            .unwrap()
+            .push((server_name.to_string(), text.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn sink_format_test() {
+        let format = MBSinkFormat::default();
+        assert_eq!(format.format("core0", "a\nb\n"), "[core0] a\n[core0] b\n");
+        let format = MBSinkFormat {
+            prefix: false,
+            ..Default::default()
+        };
+        assert_eq!(format.format("core0", "a\nb\n"), "a\nb\n");
+        let format = MBSinkFormat {
+            color: true,
+            ..Default::default()
+        };
+        let s = format.format("core0", "a\n");
+        assert!(s.starts_with("\x1b[3") && s.ends_with("[core0]\x1b[0m a\n"));
+        let format = MBSinkFormat {
+            timestamp: true,
+            ..Default::default()
+        };
+        let s = format.format("core0", "a\n");
+        assert!(s.starts_with('[') && s.ends_with("] [core0] a\n"));
+    }
+}
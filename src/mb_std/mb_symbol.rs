@@ -0,0 +1,169 @@
+use super::utils::*;
+use crate::mb_rpcs::*;
+use std::collections::{BTreeMap, HashMap};
+use xmas_elf::sections::SectionData;
+use xmas_elf::symbol_table::{Entry, Type};
+use xmas_elf::ElfFile;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBSymbol {
+    pub name: String,
+    pub addr: MBPtrT,
+    pub size: usize,
+}
+
+impl MBSymbol {
+    pub fn contains(&self, addr: MBPtrT) -> bool {
+        addr >= self.addr && addr - self.addr < std::cmp::max(self.size, 1) as MBPtrT
+    }
+}
+
+//object and function symbols of loaded elfs, symbols from later elfs override earlier ones
+#[derive(Debug, Default, Clone)]
+pub struct MBSymbolTable {
+    symbols: HashMap<String, MBSymbol>,
+    //names at each addr in the order inserted, elfs often alias symbols
+    addrs: BTreeMap<MBPtrT, Vec<String>>,
+}
+
+impl MBSymbolTable {
+    pub fn new() -> MBSymbolTable {
+        MBSymbolTable::default()
+    }
+
+    pub fn from_file(file: &str) -> Result<MBSymbolTable, String> {
+        let mut table = MBSymbolTable::new();
+        table.add_file(file)?;
+        Ok(table)
+    }
+
+    pub fn add_file(&mut self, file: &str) -> Result<(), String> {
+        process_elf(file, |elf, _| self.add_elf(elf))
+    }
+
+    pub fn add_elf(&mut self, elf: &ElfFile) -> Result<(), String> {
+        fn add<E: Entry>(
+            table: &mut MBSymbolTable,
+            elf: &ElfFile,
+            entries: &[E],
+        ) -> Result<(), String> {
+            for e in entries.iter() {
+                if !matches!(e.get_type(), Ok(Type::Object) | Ok(Type::Func)) {
+                    continue;
+                }
+                let name = e.get_name(elf)?;
+                if name.is_empty() {
+                    continue;
+                }
+                table.insert(MBSymbol {
+                    name: name.to_string(),
+                    addr: e.value() as MBPtrT,
+                    size: e.size() as usize,
+                });
+            }
+            Ok(())
+        }
+        for s in elf.section_iter() {
+            match s.get_data(elf) {
+                Ok(SectionData::SymbolTable32(entries)) => add(self, elf, entries)?,
+                Ok(SectionData::SymbolTable64(entries)) => add(self, elf, entries)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, symbol: MBSymbol) {
+        if let Some(old) = self.symbols.get(&symbol.name) {
+            if let Some(names) = self.addrs.get_mut(&old.addr) {
+                names.retain(|n| n != &symbol.name);
+                if names.is_empty() {
+                    self.addrs.remove(&old.addr);
+                }
+            }
+        }
+        self.addrs
+            .entry(symbol.addr)
+            .or_default()
+            .push(symbol.name.clone());
+        self.symbols.insert(symbol.name.clone(), symbol);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MBSymbol> {
+        self.symbols.get(name)
+    }
+
+    //the symbol containing addr, the first inserted one of aliases
+    pub fn lookup(&self, addr: MBPtrT) -> Option<&MBSymbol> {
+        self.lookup_all(addr).into_iter().next()
+    }
+
+    //all the aliased symbols containing addr
+    pub fn lookup_all(&self, addr: MBPtrT) -> Vec<&MBSymbol> {
+        self.addrs
+            .range(..=addr)
+            .next_back()
+            .map_or(vec![], |(_, names)| {
+                names
+                    .iter()
+                    .filter_map(|n| self.symbols.get(n))
+                    .filter(|s| s.contains(addr))
+                    .collect()
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MBSymbol> {
+        self.symbols.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn mb_symbol_table_test() {
+        let mut table = MBSymbolTable::new();
+        table.insert(MBSymbol {
+            name: "g_config".to_string(),
+            addr: 0x1000,
+            size: 8,
+        });
+        table.insert(MBSymbol {
+            name: "g_test_result".to_string(),
+            addr: 0x1010,
+            size: 4,
+        });
+        assert_eq!(table.get("g_test_result").unwrap().addr, 0x1010);
+        assert_eq!(table.lookup(0x1007).unwrap().name, "g_config");
+        assert!(table.lookup(0x1008).is_none());
+        table.insert(MBSymbol {
+            name: "g_config".to_string(),
+            addr: 0x2000,
+            size: 8,
+        });
+        assert!(table.lookup(0x1000).is_none());
+        assert_eq!(table.lookup(0x2004).unwrap().name, "g_config");
+        table.insert(MBSymbol {
+            name: "g_config_alias".to_string(),
+            addr: 0x2000,
+            size: 4,
+        });
+        let names = |addr| {
+            table
+                .lookup_all(addr)
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(0x2000), ["g_config", "g_config_alias"]);
+        assert_eq!(names(0x2004), ["g_config"]);
+    }
+}
@@ -0,0 +1,697 @@
+use super::mb_loader::*;
+use super::mb_rpcs::{MBAsyncRPCError, MBAsyncRPCResult};
+use super::mb_share_mem::*;
+use crate::mb_channel::*;
+use crate::mb_rpcs::*;
+use async_std::future::Future;
+use async_std::task::{Context, Poll};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use xmas_elf::ElfFile;
+
+//the last byte is the version, accesses have no request id in version 1
+const MB_TRACE_MAGIC: &[u8; 8] = b"MBTRACE\x02";
+const MB_TRACE_NAME: u8 = 0;
+const MB_TRACE_READ: u8 = 1;
+const MB_TRACE_WRITE: u8 = 2;
+const MB_TRACE_REQ: u8 = 3;
+const MB_TRACE_RESP: u8 = 4;
+const MB_TRACE_NONE_CH: u16 = u16::MAX;
+const MB_TRACE_NONE_ACTION: u32 = u32::MAX;
+const MB_TRACE_NONE_REQ: u32 = u32::MAX;
+
+#[derive(Debug, Clone)]
+struct MBTraceCtx {
+    channel: Arc<str>,
+    action: Option<u32>,
+    req: Option<u32>,
+}
+
+thread_local! {
+    static MB_TRACE_CTX: RefCell<Option<MBTraceCtx>> = const { RefCell::new(None) };
+}
+
+struct MBTraceScope<F> {
+    ctx: MBTraceCtx,
+    f: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for MBTraceScope<F> {
+    type Output = F::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let prev = MB_TRACE_CTX.with(|c| c.replace(Some(self.ctx.clone())));
+        let r = self.f.as_mut().poll(cx);
+        MB_TRACE_CTX.with(|c| *c.borrow_mut() = prev);
+        r
+    }
+}
+
+//accesses made while polling f are traced as from channel and action
+pub fn mb_trace_scope<'a, T: 'a>(
+    channel: &Arc<str>,
+    action: Option<u32>,
+    f: impl Future<Output = T> + 'a,
+) -> impl Future<Output = T> + 'a {
+    MBTraceScope {
+        ctx: MBTraceCtx {
+            channel: channel.clone(),
+            action,
+            req: None,
+        },
+        f: Box::pin(f),
+    }
+}
+
+//as mb_trace_scope, accesses are also tagged with the request id recorded by MBTracer::record_req
+pub fn mb_trace_req_scope<'a, T: 'a>(
+    channel: &Arc<str>,
+    action: u32,
+    req: u32,
+    f: impl Future<Output = T> + 'a,
+) -> impl Future<Output = T> + 'a {
+    MBTraceScope {
+        ctx: MBTraceCtx {
+            channel: channel.clone(),
+            action: Some(action),
+            req: Some(req),
+        },
+        f: Box::pin(f),
+    }
+}
+
+struct MBTracerInner {
+    file: String,
+    w: BufWriter<fs::File>,
+    names: HashMap<Arc<str>, u16>,
+}
+
+impl MBTracerInner {
+    fn channel_id(&mut self, channel: &Arc<str>) -> std::io::Result<u16> {
+        if let Some(id) = self.names.get(channel) {
+            return Ok(*id);
+        }
+        let id = self.names.len() as u16;
+        self.w.write_all(&[MB_TRACE_NAME])?;
+        self.w.write_all(&id.to_le_bytes())?;
+        self.w.write_all(&(channel.len() as u16).to_le_bytes())?;
+        self.w.write_all(channel.as_bytes())?;
+        self.names.insert(channel.clone(), id);
+        Ok(id)
+    }
+    fn record(&mut self, access: MBMemAccess, addr: MBPtrT, data: &[u8]) -> std::io::Result<()> {
+        let ctx = MB_TRACE_CTX.with(|c| c.borrow().clone());
+        let ch = if let Some(ctx) = ctx.as_ref() {
+            self.channel_id(&ctx.channel)?
+        } else {
+            MB_TRACE_NONE_CH
+        };
+        let action = ctx
+            .as_ref()
+            .and_then(|c| c.action)
+            .unwrap_or(MB_TRACE_NONE_ACTION);
+        let req = ctx.and_then(|c| c.req).unwrap_or(MB_TRACE_NONE_REQ);
+        self.w.write_all(&[match access {
+            MBMemAccess::Read => MB_TRACE_READ,
+            MBMemAccess::Write => MB_TRACE_WRITE,
+        }])?;
+        self.w.write_all(&ch.to_le_bytes())?;
+        self.w.write_all(&action.to_le_bytes())?;
+        self.w.write_all(&req.to_le_bytes())?;
+        self.w.write_all(&(addr as u64).to_le_bytes())?;
+        self.w.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.w.write_all(data)
+    }
+    fn record_req(&mut self, channel: &Arc<str>, id: u32, req: &MBReqEntry) -> std::io::Result<()> {
+        let ch = self.channel_id(channel)?;
+        self.w.write_all(&[MB_TRACE_REQ])?;
+        self.w.write_all(&ch.to_le_bytes())?;
+        self.w.write_all(&id.to_le_bytes())?;
+        self.w.write_all(&req.action.to_le_bytes())?;
+        self.w.write_all(&req.words.to_le_bytes())?;
+        self.w.write_all(&(req.args.len() as u16).to_le_bytes())?;
+        for a in req.args.iter() {
+            self.w.write_all(&(*a as u64).to_le_bytes())?;
+        }
+        Ok(())
+    }
+    fn record_resp(
+        &mut self,
+        channel: &Arc<str>,
+        id: u32,
+        resp: &MBTraceResp,
+    ) -> std::io::Result<()> {
+        let ch = self.channel_id(channel)?;
+        let (kind, words, value, msg) = match resp {
+            MBTraceResp::Resp { words, rets } => (0u8, *words, *rets as u64, ""),
+            MBTraceResp::NoResp => (1, 0, 0, ""),
+            MBTraceResp::Stop(code) => (2, 0, *code as u64, ""),
+            MBTraceResp::Error(e) => (3, 0, 0, e.as_str()),
+        };
+        self.w.write_all(&[MB_TRACE_RESP])?;
+        self.w.write_all(&ch.to_le_bytes())?;
+        self.w.write_all(&id.to_le_bytes())?;
+        self.w.write_all(&[kind])?;
+        self.w.write_all(&words.to_le_bytes())?;
+        self.w.write_all(&value.to_le_bytes())?;
+        self.w.write_all(&(msg.len() as u32).to_le_bytes())?;
+        self.w.write_all(msg.as_bytes())
+    }
+}
+
+//file layout, little endian:
+//magic, records of
+//  name: 0(u8), channel id(u16), len(u16), name
+//  access: 1 for read or 2 for write(u8), channel id(u16), action(u32), req id(u32), addr(u64),
+//          len(u32), data
+//  req: 3(u8), channel id(u16), req id(u32), action(u32), words(u32), args len(u16), args(u64s)
+//  resp: 4(u8), channel id(u16), req id(u32), kind(u8), words(u32), rets or code(u64), len(u32),
+//        error
+#[derive(Clone)]
+pub struct MBTracer {
+    inner: Arc<Mutex<MBTracerInner>>,
+}
+
+impl MBTracer {
+    pub fn new(file: &str) -> Result<MBTracer, String> {
+        let file_expand = shellexpand::full(file)
+            .map_err(|e| e.to_string())?
+            .to_string();
+        let err_handler = |e: std::io::Error| format!("create trace {} fail! {}", file_expand, e);
+        let mut w = BufWriter::new(fs::File::create(&file_expand).map_err(err_handler)?);
+        w.write_all(MB_TRACE_MAGIC).map_err(err_handler)?;
+        Ok(MBTracer {
+            inner: Arc::new(Mutex::new(MBTracerInner {
+                file: file_expand.clone(),
+                w,
+                names: HashMap::new(),
+            })),
+        })
+    }
+    pub fn flush(&self) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let file = inner.file.clone();
+        inner
+            .w
+            .flush()
+            .map_err(|e| format!("flush trace {} fail! {}", file, e))
+    }
+    fn record(&self, access: MBMemAccess, addr: MBPtrT, data: &[u8]) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Err(e) = inner.record(access, addr, data) {
+            panic!("write trace {} fail! {}", inner.file, e)
+        }
+    }
+    //the request as received by the server, before the middlewares
+    pub fn record_req(&self, channel: &Arc<str>, id: u32, req: &MBReqEntry) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Err(e) = inner.record_req(channel, id, req) {
+            panic!("write trace {} fail! {}", inner.file, e)
+        }
+    }
+    pub fn record_resp(&self, channel: &Arc<str>, id: u32, ret: &MBAsyncRPCResult) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Err(e) = inner.record_resp(channel, id, &MBTraceResp::from(ret)) {
+            panic!("write trace {} fail! {}", inner.file, e)
+        }
+    }
+}
+
+//record every access to mem with the channel and action from mb_trace_scope
+pub struct MBTraceShareMem<SM: MBShareMem> {
+    mem: SM,
+    tracer: MBTracer,
+}
+
+impl<SM: MBShareMem> MBTraceShareMem<SM> {
+    pub fn new(mem: SM, tracer: &MBTracer) -> MBTraceShareMem<SM> {
+        MBTraceShareMem {
+            mem,
+            tracer: tracer.clone(),
+        }
+    }
+    pub fn inner(&self) -> &SM {
+        &self.mem
+    }
+    pub fn inner_mut(&mut self) -> &mut SM {
+        &mut self.mem
+    }
+    pub fn into_inner(self) -> SM {
+        self.mem
+    }
+}
+
+impl<SM: MBShareMem> MBShareMem for MBTraceShareMem<SM> {
+    fn write(&mut self, addr: MBPtrT, data: &[u8]) -> usize {
+        let len = self.mem.write(addr, data);
+        self.tracer.record(MBMemAccess::Write, addr, &data[..len]);
+        len
+    }
+    fn read(&self, addr: MBPtrT, data: &mut [u8]) -> usize {
+        let len = self.mem.read(addr, data);
+        self.tracer.record(MBMemAccess::Read, addr, &data[..len]);
+        len
+    }
+    fn try_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        let r = self.mem.try_write(addr, data);
+        let len = accessed_len(&self.mem, addr, data.len(), MBMemAccess::Write, &r);
+        self.tracer.record(MBMemAccess::Write, addr, &data[..len]);
+        r
+    }
+    fn try_read(&self, addr: MBPtrT, data: &mut [u8]) -> Result<(), MBShareMemError> {
+        let r = self.mem.try_read(addr, data);
+        let len = accessed_len(&self.mem, addr, data.len(), MBMemAccess::Read, &r);
+        self.tracer.record(MBMemAccess::Read, addr, &data[..len]);
+        r
+    }
+    fn atomic(
+        &mut self,
+        addr: MBPtrT,
+        size: usize,
+        op: MBAtomicOp,
+    ) -> Result<u64, MBShareMemError> {
+        let old = self.mem.atomic(addr, size, op)?;
+        let mut bytes = [0u8; 8];
+        atomic_to_bytes(old, &mut bytes[..size]);
+        self.tracer.record(MBMemAccess::Read, addr, &bytes[..size]);
+        if let Some(new) = op.apply(old, size) {
+            atomic_to_bytes(new, &mut bytes[..size]);
+            self.tracer.record(MBMemAccess::Write, addr, &bytes[..size]);
+        }
+        Ok(old)
+    }
+    fn check_access(
+        &self,
+        addr: MBPtrT,
+        len: usize,
+        access: MBMemAccess,
+    ) -> Result<(), MBShareMemError> {
+        self.mem.check_access(addr, len, access)
+    }
+    fn poll_load(&mut self, addr: MBPtrT, len: usize, cx: &mut Context) -> Poll<()> {
+        self.mem.poll_load(addr, len, cx)
+    }
+    fn poll_store(&mut self, cx: &mut Context) -> Poll<()> {
+        self.mem.poll_store(cx)
+    }
+    fn release(&mut self) {
+        self.mem.release()
+    }
+    //loads are not requests and not recorded, mem may bypass its permissions
+    fn backdoor_write(&mut self, addr: MBPtrT, data: &[u8]) -> Result<(), MBShareMemError> {
+        self.mem.backdoor_write(addr, data)
+    }
+    fn load_image(&mut self, file: &str, opts: &MBLoadOpts) -> Result<MBLoadReport, String> {
+        self.mem.load_image(file, opts)
+    }
+    fn load_elf_with<F: FnMut(&ElfFile, &str) -> Result<(), String>>(
+        &mut self,
+        file: &str,
+        f: F,
+    ) -> Result<(), String> {
+        self.mem.load_elf_with(file, f)
+    }
+}
+
+//bytes a failed try_read/try_write of mem got through before stopping
+fn accessed_len<SM: MBShareMem>(
+    mem: &SM,
+    addr: MBPtrT,
+    len: usize,
+    access: MBMemAccess,
+    r: &Result<(), MBShareMemError>,
+) -> usize {
+    match r {
+        Ok(_) => len,
+        Err(MBShareMemError::Partial { actual, .. }) => *actual,
+        Err(MBShareMemError::Unmapped(a)) => std::cmp::min(a.wrapping_sub(addr) as usize, len),
+        Err(_) => {
+            //the longest prefix permitted
+            let (mut lo, mut hi) = (0, len);
+            while lo < hi {
+                let mid = hi - (hi - lo) / 2;
+                if mem.check_access(addr, mid, access).is_ok() {
+                    lo = mid;
+                } else {
+                    hi = mid - 1;
+                }
+            }
+            lo
+        }
+    }
+}
+
+impl<SM: MBShareMemBlock> MBShareMemBlock for MBTraceShareMem<SM> {
+    fn base(&self) -> MBPtrT {
+        self.mem.base()
+    }
+    fn size(&self) -> MBPtrT {
+        self.mem.size()
+    }
+    fn used_ranges(&self) -> Vec<(MBPtrT, MBPtrT)> {
+        self.mem.used_ranges()
+    }
+    fn fill(&self) -> u8 {
+        self.mem.fill()
+    }
+    fn discard(&mut self) {
+        self.mem.discard()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MBTraceRecord {
+    pub channel: Option<String>,
+    pub action: Option<u32>,
+    pub req: Option<u32>,
+    pub access: MBMemAccess,
+    pub addr: MBPtrT,
+    pub data: Vec<u8>,
+}
+
+impl Display for MBTraceRecord {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "[{}] ", self.channel.as_deref().unwrap_or("-"))?;
+        if let Some(id) = self.req {
+            write!(f, "#{} ", id)?;
+        }
+        write_action(f, self.action)?;
+        write!(
+            f,
+            "{} @ {:#x} ({}):",
+            match self.access {
+                MBMemAccess::Read => "R",
+                MBMemAccess::Write => "W",
+            },
+            self.addr,
+            self.data.len()
+        )?;
+        for b in self.data.iter() {
+            write!(f, " {:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_action(f: &mut Formatter, action: Option<u32>) -> fmt::Result {
+    match action.map(|a| (a, MBAction::from(a))) {
+        Some((a, MBAction::OTHER)) => write!(f, "OTHER({:#x}) ", a),
+        Some((_, action)) => write!(f, "{:?} ", action),
+        None => write!(f, "- "),
+    }
+}
+
+//what the server returned for a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MBTraceResp {
+    Resp { words: u32, rets: MBPtrT },
+    NoResp,
+    Stop(u32),
+    Error(String),
+}
+
+impl From<&MBAsyncRPCResult> for MBTraceResp {
+    fn from(ret: &MBAsyncRPCResult) -> Self {
+        match ret {
+            Ok(resp) => MBTraceResp::Resp {
+                words: resp.words,
+                rets: resp.rets,
+            },
+            Err(MBAsyncRPCError::NoResp) => MBTraceResp::NoResp,
+            Err(MBAsyncRPCError::Stop(_, code)) => MBTraceResp::Stop(*code),
+            Err(e) => MBTraceResp::Error(e.to_string()),
+        }
+    }
+}
+
+impl Display for MBTraceResp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MBTraceResp::Resp { words, rets } => write!(f, "RESP({}) {:#x}", words, rets),
+            MBTraceResp::NoResp => write!(f, "NORESP"),
+            MBTraceResp::Stop(code) => write!(f, "STOP {}", code),
+            MBTraceResp::Error(e) => write!(f, "ERROR {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MBTraceEvent {
+    Access(MBTraceRecord),
+    Req {
+        channel: String,
+        id: u32,
+        req: MBReqEntry,
+    },
+    Resp {
+        channel: String,
+        id: u32,
+        resp: MBTraceResp,
+    },
+}
+
+impl Display for MBTraceEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MBTraceEvent::Access(r) => write!(f, "{}", r),
+            MBTraceEvent::Req { channel, id, req } => {
+                write!(f, "[{}] #{} ", channel, id)?;
+                write_action(f, Some(req.action))?;
+                write!(f, "REQ({}):", req.words)?;
+                for a in req.args[..std::cmp::min(req.words as usize, req.args.len())].iter() {
+                    write!(f, " {:#x}", a)?;
+                }
+                Ok(())
+            }
+            MBTraceEvent::Resp { channel, id, resp } => write!(f, "[{}] #{} {}", channel, id, resp),
+        }
+    }
+}
+
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+//accesses, requests and responses in the order recorded
+pub fn mb_decode_trace_events(file: &str) -> Result<Vec<MBTraceEvent>, String> {
+    let file_expand = shellexpand::full(file)
+        .map_err(|e| e.to_string())?
+        .to_string();
+    let err_handler = |e: std::io::Error| format!("decode trace {} fail! {}", file_expand, e);
+    let mut r = BufReader::new(fs::File::open(&file_expand).map_err(err_handler)?);
+    let mut decode = || -> std::io::Result<Vec<MBTraceEvent>> {
+        let invalid = |e: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string());
+        let magic = read_bytes(&mut r, MB_TRACE_MAGIC.len())?;
+        let version = magic[MB_TRACE_MAGIC.len() - 1];
+        if magic[..MB_TRACE_MAGIC.len() - 1] != MB_TRACE_MAGIC[..MB_TRACE_MAGIC.len() - 1]
+            || version == 0
+            || version > MB_TRACE_MAGIC[MB_TRACE_MAGIC.len() - 1]
+        {
+            return Err(invalid("not a trace file!"));
+        }
+        let mut names: HashMap<u16, String> = HashMap::new();
+        let get_name = |names: &HashMap<u16, String>, ch: u16| {
+            names
+                .get(&ch)
+                .cloned()
+                .ok_or(invalid(&format!("unknown channel id {}!", ch)))
+        };
+        let read_u32 = |r: &mut BufReader<fs::File>| -> std::io::Result<u32> {
+            Ok(u32::from_le_bytes(read_bytes(r, 4)?.try_into().unwrap()))
+        };
+        let read_u64 = |r: &mut BufReader<fs::File>| -> std::io::Result<u64> {
+            Ok(u64::from_le_bytes(read_bytes(r, 8)?.try_into().unwrap()))
+        };
+        let mut events = vec![];
+        let mut tag = [0u8; 1];
+        while r.read(&mut tag)? == 1 {
+            let ch = u16::from_le_bytes(read_bytes(&mut r, 2)?.try_into().unwrap());
+            match tag[0] {
+                MB_TRACE_NAME => {
+                    let len = u16::from_le_bytes(read_bytes(&mut r, 2)?.try_into().unwrap());
+                    let name = String::from_utf8(read_bytes(&mut r, len as usize)?)
+                        .map_err(|e| invalid(&e.to_string()))?;
+                    names.insert(ch, name);
+                }
+                MB_TRACE_READ | MB_TRACE_WRITE => {
+                    let action = read_u32(&mut r)?;
+                    let req = if version > 1 {
+                        read_u32(&mut r)?
+                    } else {
+                        MB_TRACE_NONE_REQ
+                    };
+                    let addr = read_u64(&mut r)?;
+                    let len = read_u32(&mut r)?;
+                    events.push(MBTraceEvent::Access(MBTraceRecord {
+                        channel: if ch == MB_TRACE_NONE_CH {
+                            None
+                        } else {
+                            Some(get_name(&names, ch)?)
+                        },
+                        action: if action == MB_TRACE_NONE_ACTION {
+                            None
+                        } else {
+                            Some(action)
+                        },
+                        req: if req == MB_TRACE_NONE_REQ {
+                            None
+                        } else {
+                            Some(req)
+                        },
+                        access: if tag[0] == MB_TRACE_READ {
+                            MBMemAccess::Read
+                        } else {
+                            MBMemAccess::Write
+                        },
+                        addr: addr as MBPtrT,
+                        data: read_bytes(&mut r, len as usize)?,
+                    }))
+                }
+                MB_TRACE_REQ => {
+                    let id = read_u32(&mut r)?;
+                    let mut req = MBReqEntry {
+                        action: read_u32(&mut r)?,
+                        words: read_u32(&mut r)?,
+                        ..Default::default()
+                    };
+                    let len = u16::from_le_bytes(read_bytes(&mut r, 2)?.try_into().unwrap());
+                    for i in 0..len as usize {
+                        let a = read_u64(&mut r)?;
+                        if i < req.args.len() {
+                            req.args[i] = a as MBPtrT;
+                        }
+                    }
+                    events.push(MBTraceEvent::Req {
+                        channel: get_name(&names, ch)?,
+                        id,
+                        req,
+                    })
+                }
+                MB_TRACE_RESP => {
+                    let id = read_u32(&mut r)?;
+                    let kind = read_bytes(&mut r, 1)?[0];
+                    let words = read_u32(&mut r)?;
+                    let value = read_u64(&mut r)?;
+                    let len = read_u32(&mut r)?;
+                    let msg = String::from_utf8(read_bytes(&mut r, len as usize)?)
+                        .map_err(|e| invalid(&e.to_string()))?;
+                    let resp = match kind {
+                        0 => MBTraceResp::Resp {
+                            words,
+                            rets: value as MBPtrT,
+                        },
+                        1 => MBTraceResp::NoResp,
+                        2 => MBTraceResp::Stop(value as u32),
+                        3 => MBTraceResp::Error(msg),
+                        k => return Err(invalid(&format!("invalid resp kind {}!", k))),
+                    };
+                    events.push(MBTraceEvent::Resp {
+                        channel: get_name(&names, ch)?,
+                        id,
+                        resp,
+                    })
+                }
+                t => return Err(invalid(&format!("invalid record tag {}!", t))),
+            }
+        }
+        Ok(events)
+    };
+    decode().map_err(err_handler)
+}
+
+pub fn mb_decode_trace(file: &str) -> Result<Vec<MBTraceRecord>, String> {
+    Ok(mb_decode_trace_events(file)?
+        .into_iter()
+        .filter_map(|e| match e {
+            MBTraceEvent::Access(r) => Some(r),
+            _ => None,
+        })
+        .collect())
+}
+
+//render the trace as text, one record per line
+pub fn mb_dump_trace<W: Write>(file: &str, out: &mut W) -> Result<(), String> {
+    for e in mb_decode_trace_events(file)? {
+        writeln!(out, "{}", e).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn mb_trace_test() {
+        let file = std::env::temp_dir().join(format!("mb_trace_test_{}", std::process::id()));
+        let path = file.to_str().unwrap();
+        let tracer = MBTracer::new(path).unwrap();
+        let mut mem = MBTraceShareMem::new(MBSparseShareMem::new(0x1000, 0x1000), &tracer);
+        mem.write_sized(0x1000, &0xdeadbeefu32);
+        let channel: Arc<str> = Arc::from("core0");
+        async_std::task::block_on(mb_trace_scope(
+            &channel,
+            Some(MBAction::PRINT as u32),
+            async {
+                let mut data = [0u8; 2];
+                mem.read_slice(0x1001, &mut data);
+            },
+        ));
+        tracer.flush().unwrap();
+        let mut text = vec![];
+        mb_dump_trace(path, &mut text).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "[-] - W @ 0x1000 (4): ef be ad de\n[core0] PRINT R @ 0x1001 (2): be ad\n"
+        );
+    }
+    #[test]
+    fn mb_trace_space_test() {
+        let file = std::env::temp_dir().join(format!("mb_trace_space_{}", std::process::id()));
+        let path = file.to_str().unwrap();
+        let image = std::env::temp_dir().join(format!("mb_trace_image_{}", std::process::id()));
+        fs::write(&image, [1u8, 2]).unwrap();
+        let tracer = MBTracer::new(path).unwrap();
+        let mut space = MBShareMemSpace::<MBSparseShareMem>::new();
+        let ram = Arc::new(Mutex::new(MBSparseShareMem::new(0x1000, 0x10)));
+        let rom = Arc::new(Mutex::new(MBSparseShareMem::new(0x1010, 0x10)));
+        space.add_named_mem("ram", &ram).unwrap();
+        space.add_named_mem("rom", &rom).unwrap();
+        space.set_perm("rom", MBShareMemPerm::RO).unwrap();
+        let mut mem = MBTraceShareMem::new(space, &tracer);
+        //read-only memories are loaded
+        let opts = MBLoadOpts {
+            format: Some(MBImageFormat::Bin),
+            addr: Some(0x1010),
+            ..Default::default()
+        };
+        mem.load_image(image.to_str().unwrap(), &opts).unwrap();
+        fs::remove_file(&image).unwrap();
+        //errors of the space are kept, the bytes got through are recorded
+        let mut data = [0u8; 4];
+        assert!(matches!(
+            mem.try_read(0x101e, &mut data),
+            Err(MBShareMemError::Unmapped(0x1020))
+        ));
+        assert!(matches!(
+            mem.try_write(0x100e, &[3u8, 4, 5, 6]),
+            Err(MBShareMemError::Permission(_))
+        ));
+        assert_eq!(mem.try_read(0x1010, &mut data[..2]), Ok(()));
+        assert_eq!(data[..2], [1, 2]);
+        tracer.flush().unwrap();
+        let mut text = vec![];
+        mb_dump_trace(path, &mut text).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "[-] - R @ 0x101e (2): 00 00\n[-] - W @ 0x100e (2): 03 04\n[-] - R @ 0x1010 (2): 01 02\n"
+        );
+    }
+}
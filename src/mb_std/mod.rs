@@ -5,26 +5,41 @@ pub extern crate xmas_elf;
 mod macros;
 mod mb_async_channel;
 mod mb_builder;
+mod mb_device;
 mod mb_fs;
+mod mb_loader;
+mod mb_page_table;
 mod mb_ptr_resolver;
+mod mb_replay;
 mod mb_rpcs;
 mod mb_server;
 mod mb_share_mem;
+mod mb_sink;
+mod mb_symbol;
+mod mb_trace;
 mod utils;
 pub use macros::*;
 pub use mb_async_channel::*;
 pub use mb_builder::*;
+pub use mb_device::*;
 pub use mb_fs::*;
+pub use mb_loader::*;
+pub use mb_page_table::*;
 pub use mb_ptr_resolver::*;
+pub use mb_replay::*;
 pub use mb_rpcs::*;
 pub use mb_server::*;
 pub use mb_share_mem::*;
+pub use mb_sink::*;
+pub use mb_symbol::*;
+pub use mb_trace::*;
 #[cfg(test)]
 mod tests {
     use super::mb_async_channel::*;
     use super::mb_ptr_resolver::*;
     use super::mb_rpcs::*;
     use super::mb_server::*;
+    use super::mb_sink::*;
     use crate::mb_channel::*;
     use crate::mb_rpcs::*;
     use async_std::future::Future;
@@ -66,6 +81,7 @@ mod tests {
         })
     }
 
+    use super::mb_loader::*;
     use super::mb_share_mem::*;
     #[derive(Debug)]
     struct ShareMem {
@@ -120,8 +136,8 @@ mod tests {
         space.lock().unwrap().add_mem(&share_mem1).unwrap();
         assert_eq!(space.lock().unwrap().write(2046, &[1, 2, 3]), 3);
         let mut data: [u8; 3] = [0; 3];
-        assert_eq!(space.lock().unwrap().read(2046, &mut data), 2);
-        assert_eq!(data, [1, 2, 0]);
+        assert_eq!(space.lock().unwrap().read(2046, &mut data), 3);
+        assert_eq!(data, [1, 2, 3]);
         let channel = Arc::new(Mutex::new(MBAsyncChannel::new(MBChannelShareMem::new(
             0, &space,
         ))));
@@ -156,6 +172,141 @@ mod tests {
         })
     }
 
+    #[test]
+    fn mb_std_share_mem_overlap_alias() {
+        let mut space = MBShareMemSpace::<ShareMem>::new();
+        let small = Arc::new(Mutex::new(ShareMem::new(0x1000, 0x100)));
+        let large = Arc::new(Mutex::new(ShareMem::new(0x800, 0x1000)));
+        space.add_named_mem("small", &small).unwrap();
+        let e = space.add_named_mem("large", &large).unwrap_err();
+        assert_eq!(
+            e,
+            "large [0x800 - 0x17ff] is overlapped with small [0x1000 - 0x10ff]!"
+        );
+        space.add_alias("small_mirror", &small, 0x2000).unwrap();
+        assert!(space.add_alias("small_mirror2", &small, 0x20ff).is_err());
+        assert_eq!(space.write(0x20fe, &[1, 2]), 2);
+        let mut data: [u8; 2] = [0; 2];
+        assert_eq!(space.read(0x10fe, &mut data), 2);
+        assert_eq!(data, [1, 2]);
+    }
+
+    #[test]
+    fn mb_std_share_mem_try_access() {
+        let mut space = MBShareMemSpace::<ShareMem>::new();
+        let low = Arc::new(Mutex::new(ShareMem::new(0x1000, 0x100)));
+        let high = Arc::new(Mutex::new(ShareMem::new(0x1100, 0x100)));
+        space.add_named_mem("low", &low).unwrap();
+        space.add_named_mem("high", &high).unwrap();
+        space.write_slice(0x10fe, &[1u8, 2, 3, 4]);
+        let mut data = [0u8; 4];
+        assert_eq!(space.read(0x10fe, &mut data), 4);
+        assert_eq!(data, [1, 2, 3, 4]);
+        assert_eq!(
+            space.try_read_slice_checked(0x11fe, &mut data),
+            Err(MBShareMemError::Unmapped(0x1200))
+        );
+        assert_eq!(space.try_read_slice(0x11fe, &mut data), 2);
+        assert_eq!(
+            space.try_write_sized(0x2000, &0u32),
+            Err(MBShareMemError::Unmapped(0x2000))
+        );
+        assert_eq!(
+            space.check_access(0x10fe, 0x104, MBMemAccess::Read),
+            Err(MBShareMemError::Unmapped(0x1200))
+        );
+        assert!(space.try_read_sized(0x10fe, &mut 0u32).is_ok());
+    }
+
+    #[test]
+    fn mb_sm_c_str_at_end() {
+        let space = Arc::new(Mutex::new(MBShareMemSpace::<ShareMem>::new()));
+        let mem = Arc::new(Mutex::new(ShareMem::new(0x1000, 0x1000)));
+        space.lock().unwrap().add_named_mem("mem", &mem).unwrap();
+        space.lock().unwrap().write(0x1ffc, b"hi\0");
+        let resolver = MBSMPtrResolver::new(&space);
+        assert_eq!(resolver.read_c_str(0x1ffc as *const u8).unwrap(), "hi");
+        space.lock().unwrap().write(0x1ffc, b"hiho");
+        assert!(resolver.read_c_str(0x1ffc as *const u8).is_err());
+        let mut data = [0u8; 8];
+        assert_eq!(resolver.try_read_slice(0x1ffc as *const u8, &mut data), 4);
+        assert_eq!(data[..4], *b"hiho");
+        assert_eq!(resolver.try_read_slice(0x2000 as *const u8, &mut data), 0);
+        let waker = futures::task::noop_waker();
+        let limits = MBStrLimits::default();
+        assert!(resolver
+            .poll_load_c_str(0x1ffc, &limits, &mut Context::from_waker(&waker))
+            .is_ready());
+    }
+
+    #[test]
+    fn mb_std_share_mem_perm() {
+        let space = Arc::new(Mutex::new(MBShareMemSpace::<ShareMem>::new()));
+        let rom = Arc::new(Mutex::new(ShareMem::new(0, 0x100)));
+        let ram = Arc::new(Mutex::new(ShareMem::new(0x100, 0x100)));
+        space
+            .lock()
+            .unwrap()
+            .add_window("rom", &rom, 0, 0, 0x100, MBShareMemPerm::RO)
+            .unwrap();
+        space.lock().unwrap().add_named_mem("ram", &ram).unwrap();
+        let server = MBSMServer::new("server", &Arc::new(None), &space);
+        let mut req = MBReqEntry::default();
+        req.action = MBAction::MEMSET as u32;
+        req.words = 3;
+        req.args[..3].copy_from_slice(&[0xf0, 0x5a, 0x20]);
+        match async_std::task::block_on(server.do_cmd(&req)) {
+            Err(MBAsyncRPCError::Violation(server_name, MBAction::MEMSET, 0xf0, e)) => {
+                assert_eq!(server_name, "server");
+                assert_eq!(
+                    e,
+                    MBShareMemError::Permission(
+                        "Write rom [0x0 - 0xff] @ 0xf0 is not permitted!".to_string()
+                    )
+                );
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        let mut data = [0u8; 0x10];
+        space.lock().unwrap().read(0xf0, &mut data);
+        assert_eq!(data, [0; 0x10]);
+        //plain accesses are checked too, stopping at the rom
+        assert_eq!(space.lock().unwrap().write(0xf0, &[0x5a; 0x10]), 0);
+        space
+            .lock()
+            .unwrap()
+            .set_perm("ram", MBShareMemPerm::WO)
+            .unwrap();
+        assert_eq!(space.lock().unwrap().read(0xf8, &mut data), 8);
+        assert!(matches!(
+            space.lock().unwrap().try_read(0xf8, &mut data),
+            Err(MBShareMemError::Permission(_))
+        ));
+        space
+            .lock()
+            .unwrap()
+            .set_perm("ram", MBShareMemPerm::RW)
+            .unwrap();
+        //images are still loaded into the rom
+        let file = std::env::temp_dir().join(format!("mb_perm_rom_{}.bin", std::process::id()));
+        std::fs::write(&file, [0xa5u8; 4]).unwrap();
+        let opts = MBLoadOpts {
+            addr: Some(0x10),
+            ..Default::default()
+        };
+        let r = space
+            .lock()
+            .unwrap()
+            .load_image(file.to_str().unwrap(), &opts);
+        std::fs::remove_file(&file).unwrap();
+        r.unwrap();
+        space.lock().unwrap().read(0x10, &mut data[..4]);
+        assert_eq!(data[..4], [0xa5; 4]);
+        req.action = MBAction::MEMMOVE as u32;
+        req.args[..3].copy_from_slice(&[0x100, 0, 0x10]);
+        assert!(async_std::task::block_on(server.do_cmd(&req)).is_ok());
+    }
+
     #[test]
     fn mb_cprint_test() {
         let channel = Arc::new(Mutex::new(MBAsyncChannel::new(MBChannel::default())));
@@ -236,7 +387,7 @@ mod tests {
         type RESP = u32;
         fn put_req(&self, req: Self::REQ, entry: &mut MBReqEntry) {
             entry.words = 2;
-            entry.action = MBAction::OTHER;
+            entry.action = MBAction::OTHER as u32;
             entry.args[0] = 8;
             entry.args[1] = req as MBPtrT;
         }
@@ -284,6 +435,267 @@ mod tests {
         })
     }
 
+    struct MyPrintCounter(Mutex<usize>);
+    impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
+        MBAsyncRPC<RA, WA, R> for MyPrintCounter
+    {
+        fn poll_cmd(
+            &self,
+            _server_name: &str,
+            _r: &R,
+            req: &MBReqEntry,
+            _cx: &mut Context,
+        ) -> Poll<MBAsyncRPCResult> {
+            let mut count = self.0.lock().unwrap();
+            *count += req.args[0] as usize;
+            Poll::Ready(Ok(MBRespEntry {
+                words: 1,
+                rets: *count as MBPtrT,
+            }))
+        }
+    }
+    #[test]
+    fn mb_register_cmd_test() {
+        let server = MBLocalServer::new("server", &Arc::new(None));
+        assert!(server
+            .register_cmd(0x100u32, Arc::new(MyPrintCounter(Mutex::new(0))))
+            .is_none());
+        let print = server
+            .register_cmd(MBAction::PRINT, MyPrintCounter(Mutex::new(10)))
+            .unwrap();
+        let mut req = MBReqEntry {
+            action: 0x100,
+            words: 1,
+            ..Default::default()
+        };
+        req.args[0] = 3;
+        async_std::task::block_on(async {
+            assert_eq!(server.do_cmd(&req).await.unwrap().rets, 3);
+            assert_eq!(server.do_cmd(&req).await.unwrap().rets, 6);
+            req.set_action(MBAction::PRINT);
+            assert_eq!(server.do_cmd(&req).await.unwrap().rets, 13);
+            //back to the built-in
+            server.register_cmd(MBAction::PRINT, print);
+            let s = "restored\n";
+            MBPrint::new().put_req(
+                &MBStringArgs {
+                    len: s.len() as u32,
+                    ptr: s.as_ptr() as MBPtrT,
+                },
+                &mut req,
+            );
+            assert_eq!(server.do_cmd(&req).await.unwrap().rets, 0);
+            assert!(server.unregister_cmd(0x100u32).is_some());
+            req.set_action(0x100u32);
+            assert!(matches!(
+                server.do_cmd(&req).await,
+                Err(MBAsyncRPCError::Illegal(0x100))
+            ));
+        });
+    }
+    #[test]
+    fn mb_sink_test() {
+        let server = MBLocalServer::new("server", &Arc::new(None));
+        let capture = Arc::new(MBCaptureSink::new());
+        server.set_sinks(vec![capture.clone()]);
+        let mut req = MBReqEntry::default();
+        async_std::task::block_on(async {
+            for s in ["hello ", "world\n"] {
+                MBPrint::new().put_req(
+                    &MBStringArgs {
+                        len: s.len() as u32,
+                        ptr: s.as_ptr() as MBPtrT,
+                    },
+                    &mut req,
+                );
+                server.do_cmd(&req).await.unwrap();
+            }
+        });
+        assert_eq!(
+            capture.take(),
+            vec![("server".to_string(), "hello world\n".to_string())]
+        );
+    }
+    #[test]
+    fn mb_error_policy_test() {
+        let server = MBLocalServer::new("server", &Arc::new(None));
+        let mut req = MBReqEntry {
+            action: MBAction::FILEACCESS as u32,
+            words: 1,
+            ..Default::default()
+        };
+        let e = async_std::task::block_on(server.do_cmd(&req)).unwrap_err();
+        assert_eq!(e.to_string(), "FILEACCESS failed: No mb_fs!");
+        assert_eq!(MBErrorPolicy::Log.apply("server", &e), None);
+        assert_eq!(MBErrorPolicy::StopChannel(3).apply("server", &e), Some(3));
+        assert_eq!(MBErrorPolicy::StopAll(4).apply("server", &e), Some(4));
+        assert!(std::panic::catch_unwind(|| MBErrorPolicy::Panic.apply("server", &e)).is_err());
+        //unknown OTHER ids fail instead of panicking the server
+        req.set_action(MBAction::OTHER);
+        req.args[0] = 0x1234;
+        assert!(matches!(
+            async_std::task::block_on(server.do_cmd(&req)),
+            Err(MBAsyncRPCError::Failed(_, a, _)) if a == MBAction::OTHER as u32
+        ));
+    }
+    struct MyLogMiddleware(Arc<Mutex<Vec<String>>>);
+    impl MBMiddleware for MyLogMiddleware {
+        fn before(&self, _server_name: &str, req: &mut MBReqEntry) -> Option<MBAsyncRPCResult> {
+            self.0.lock().unwrap().push(format!("req {}", req.args[0]));
+            None
+        }
+        fn after(&self, _server_name: &str, _req: &MBReqEntry, ret: &mut MBAsyncRPCResult) {
+            self.0.lock().unwrap().push(match ret {
+                Ok(resp) => format!("resp {}", resp.rets),
+                Err(e) => e.to_string(),
+            });
+        }
+    }
+    //doubles the argument and fails on 0
+    struct MyRewriteMiddleware;
+    impl MBMiddleware for MyRewriteMiddleware {
+        fn before(&self, server_name: &str, req: &mut MBReqEntry) -> Option<MBAsyncRPCResult> {
+            if req.args[0] == 0 {
+                return Some(Err(MBAsyncRPCError::Failed(
+                    server_name.to_string(),
+                    req.action,
+                    "injected".to_string(),
+                )));
+            }
+            req.args[0] *= 2;
+            None
+        }
+    }
+    #[test]
+    fn mb_middleware_test() {
+        let server = MBLocalServer::new("server", &Arc::new(None));
+        let log = Arc::new(Mutex::new(vec![]));
+        server.register_cmd(0x100u32, MyPrintCounter(Mutex::new(0)));
+        server.add_middleware(MyLogMiddleware(log.clone()));
+        server.add_middleware(MyRewriteMiddleware);
+        let mut req = MBReqEntry {
+            action: 0x100,
+            words: 1,
+            ..Default::default()
+        };
+        req.args[0] = 3;
+        async_std::task::block_on(async {
+            assert_eq!(server.do_cmd(&req).await.unwrap().rets, 6);
+            req.args[0] = 0;
+            assert!(server.do_cmd(&req).await.is_err());
+        });
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["req 3", "resp 6", "req 0", "action 0x100 failed: injected"]
+        );
+    }
+    //pending until opened by MyOpenRPC
+    struct MyGateRPC(Arc<Mutex<(bool, Option<std::task::Waker>)>>);
+    impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
+        MBAsyncRPC<RA, WA, R> for MyGateRPC
+    {
+        fn poll_cmd(
+            &self,
+            _server_name: &str,
+            _r: &R,
+            req: &MBReqEntry,
+            cx: &mut Context,
+        ) -> Poll<MBAsyncRPCResult> {
+            let mut gate = self.0.lock().unwrap();
+            if !gate.0 {
+                gate.1 = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            Poll::Ready(Ok(MBRespEntry {
+                words: 1,
+                rets: req.action as MBPtrT,
+            }))
+        }
+    }
+    struct MyOpenRPC(Arc<Mutex<(bool, Option<std::task::Waker>)>>);
+    impl<RA: MBPtrReader, WA: MBPtrWriter, R: MBPtrResolver<READER = RA, WRITER = WA>>
+        MBAsyncRPC<RA, WA, R> for MyOpenRPC
+    {
+        fn poll_cmd(
+            &self,
+            _server_name: &str,
+            _r: &R,
+            req: &MBReqEntry,
+            _cx: &mut Context,
+        ) -> Poll<MBAsyncRPCResult> {
+            let mut gate = self.0.lock().unwrap();
+            gate.0 = true;
+            if let Some(w) = gate.1.take() {
+                w.wake();
+            }
+            Poll::Ready(Ok(MBRespEntry {
+                words: 1,
+                rets: req.action as MBPtrT,
+            }))
+        }
+    }
+    struct MyIdRPC(u32);
+    impl MBRpc for MyIdRPC {
+        type REQ = ();
+        type RESP = MBPtrT;
+        fn put_req(&self, _req: Self::REQ, entry: &mut MBReqEntry) {
+            entry.set_words(0);
+            entry.set_action(self.0);
+        }
+        fn get_resp(&self, resp: &MBRespEntry) -> Self::RESP {
+            resp.rets
+        }
+    }
+    #[test]
+    fn mb_unordered_test() {
+        let channel = Arc::new(Mutex::new(MBAsyncChannel::new(MBChannel::default())));
+        let server = MBLocalServer::new("server", &Arc::new(None));
+        let gate = Arc::new(Mutex::new((false, None)));
+        server.register_cmd(0x100u32, MyGateRPC(gate.clone()));
+        server.register_cmd(0x101u32, MyOpenRPC(gate));
+        server.set_ordering(0x101u32, MBOrdering::Unordered);
+        let sender = MBAsyncSender::new(&channel);
+        let receiver = MBAsyncReceiver::new(&channel);
+        let client = async {
+            sender.reset().await;
+            //the gate is only opened if the second request is served while the first is pending
+            sender.send_req(&MyIdRPC(0x100), ()).await;
+            sender.send_req(&MyIdRPC(0x101), ()).await;
+            assert_eq!(sender.recv_resp(&MyIdRPC(0x100)).await, 0x100);
+            assert_eq!(sender.recv_resp(&MyIdRPC(0x101)).await, 0x101);
+            mb_exit(&sender, 3).await;
+        };
+        let (_, (name, code)) = async_std::task::block_on(futures::future::join(
+            client,
+            server.serve(&receiver, MBErrorPolicy::Panic, MBStopAll::new()),
+        ));
+        assert_eq!((name.as_str(), code), ("server", 3));
+    }
+    #[test]
+    fn mb_log_policy_test() {
+        let channel = Arc::new(Mutex::new(MBAsyncChannel::new(MBChannel::default())));
+        //no region registered, the method name of CALL is a violation
+        let server = MBLocalServer::with_resolver(
+            "server",
+            &Arc::new(None),
+            MBCheckedLocalPtrResolver::new(),
+        );
+        let sender = MBAsyncSender::new(&channel);
+        let receiver = MBAsyncReceiver::new(&channel);
+        let client = async {
+            sender.reset().await;
+            assert_eq!(mb_call(&sender, "method\0", &[1, 2]).await, MBPtrT::MAX);
+            //CPRINT is not responded, the next request still is
+            mb_cprint(&sender, "%d\n\0", "log.c\0", 1, &[1]).await;
+            assert_eq!(mb_call(&sender, "method\0", &[]).await, MBPtrT::MAX);
+            mb_exit(&sender, 3).await;
+        };
+        let (_, (name, code)) = async_std::task::block_on(futures::future::join(
+            client,
+            server.serve(&receiver, MBErrorPolicy::Log, MBStopAll::new()),
+        ));
+        assert_eq!((name.as_str(), code), ("server", 3));
+    }
     #[test]
     fn mb_memmove_test() {
         let share_mem = Arc::new(Mutex::new(ShareMem::new(0, 4096)));
@@ -393,4 +805,226 @@ mod tests {
             c.await;
         })
     }
+
+    #[test]
+    fn mb_async_mem_test() {
+        use super::mb_share_mem::*;
+        let mem = Arc::new(Mutex::new(MBLatencyShareMem::new(
+            MBSparseShareMem::new(0, 0x2000),
+            3,
+        )));
+        mem.lock()
+            .unwrap()
+            .inner_mut()
+            .write(0x1020, &[0x5a, 0x5a, 0x12, 0x5a]);
+        let channel_mem = Arc::new(Mutex::new(MBStagedShareMem::new(&mem)));
+        let channel = Arc::new(Mutex::new(MBAsyncChannel::new(MBChannelShareMem::new(
+            0,
+            &channel_mem,
+        ))));
+        let server_mem = Arc::new(Mutex::new(MBStagedShareMem::new(&mem)));
+        let server = MBSMServer::new("server", &Arc::new(None), &server_mem);
+        let sender = MBAsyncSender::new(&channel);
+        let receiver = MBAsyncReceiver::new(&channel);
+        async_std::task::block_on(async {
+            let c = async_std::task::spawn(async move {
+                sender.reset().await;
+                mb_memset(&sender, 0x1000, 0x5a, 8).await;
+                mb_memmove(&sender, 0x1010, 0x1004, 4).await;
+                assert_eq!(mb_memcmp(&sender, 0x1000, 0x1010, 4).await, 0);
+                assert_eq!(mb_memcmp(&sender, 0x1000, 0x1020, 4).await, 0x48);
+            });
+            async_std::task::spawn(async move {
+                receiver.reset().await;
+                receiver.check_version("server");
+                loop {
+                    let req = receiver.recv_req("").await;
+                    match server.do_cmd(&req).await {
+                        Ok(r) => receiver.send_resp(r, "").await,
+                        Err(MBAsyncRPCError::Stop(server_name, code)) => break (server_name, code),
+                        Err(MBAsyncRPCError::Illegal(action)) => {
+                            panic!("Illegal cmd {:?}", action)
+                        }
+                        _ => {}
+                    }
+                }
+            });
+            c.await;
+        });
+        let mut data = [0u8; 8];
+        mem.lock().unwrap().inner().read(0x1010, &mut data);
+        assert_eq!(data, [0x5a, 0x5a, 0x5a, 0x5a, 0, 0, 0, 0]);
+        //reads stop short at ranges not loaded
+        let mut staged = MBStagedShareMem::new(&mem);
+        staged.write(0x1016, &[1u8, 2, 3, 4]);
+        assert_eq!(staged.read(0x1010, &mut data), 0);
+        assert_eq!(staged.read(0x1018, &mut data), 2);
+        assert!(staged.try_read(0x1010, &mut data).is_err());
+        async_std::task::block_on(futures::future::poll_fn(|cx| {
+            staged.poll_load(0x1010, 8, cx)
+        }));
+        assert_eq!(staged.read(0x1010, &mut data), 8);
+        assert_eq!(data, [0x5a, 0x5a, 0x5a, 0x5a, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn mb_checked_local_test() {
+        use super::mb_share_mem::*;
+        let resolver = MBCheckedLocalPtrResolver::new();
+        let server = MBLocalServer::with_resolver("server", &Arc::new(None), resolver.clone());
+        let buffer1: [u8; 8] = [8, 7, 6, 4, 5, 1, 2, 3];
+        let buffer2: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+        resolver.add_slice(&buffer1, MBShareMemPerm::RO).unwrap();
+        assert!(resolver
+            .add_slice(&buffer1[4..], MBShareMemPerm::RO)
+            .is_err());
+        let mut req = MBReqEntry {
+            action: MBAction::MEMCMP as u32,
+            words: 3,
+            ..Default::default()
+        };
+        req.args[0] = buffer1.as_ptr() as MBPtrT + 3;
+        req.args[1] = buffer2.as_ptr() as MBPtrT + 3;
+        req.args[2] = 4;
+        async_std::task::block_on(async {
+            match server.do_cmd(&req).await {
+                Err(MBAsyncRPCError::Violation(
+                    _,
+                    MBAction::MEMCMP,
+                    ptr,
+                    MBShareMemError::Unmapped(addr),
+                )) => {
+                    assert_eq!(ptr, req.args[1]);
+                    assert_eq!(addr, req.args[1]);
+                }
+                r => panic!("unexpected {:?}", r),
+            }
+            resolver.add_slice(&buffer2, MBShareMemPerm::RO).unwrap();
+            assert_eq!(server.do_cmd(&req).await.unwrap().rets, -5i32 as MBPtrT);
+            //crossing the end of the region
+            resolver.remove_region(buffer1.as_ptr() as MBPtrT).unwrap();
+            resolver
+                .add_slice(&buffer1[..6], MBShareMemPerm::RO)
+                .unwrap();
+            match server.do_cmd(&req).await {
+                Err(MBAsyncRPCError::Violation(_, _, _, MBShareMemError::Unmapped(addr))) => {
+                    assert_eq!(addr, buffer1.as_ptr() as MBPtrT + 6)
+                }
+                r => panic!("unexpected {:?}", r),
+            }
+            resolver.remove_region(buffer2.as_ptr() as MBPtrT).unwrap();
+            req.args[0] = buffer1.as_ptr() as MBPtrT;
+            assert!(server.do_cmd(&req).await.is_err());
+        });
+        //reads stop at the end of the region
+        let mut data = vec![];
+        std::io::Read::read_to_end(&mut resolver.reader(buffer1.as_ptr()), &mut data).unwrap_err();
+        let mut data = [0u8; 8];
+        let mut reader = resolver.reader(buffer1.as_ptr());
+        assert_eq!(std::io::Read::read(&mut reader, &mut data[..4]).unwrap(), 4);
+        assert_eq!(std::io::Read::read(&mut reader, &mut data[4..]).unwrap(), 2);
+        assert_eq!(data[..6], buffer1[..6]);
+        assert_eq!(
+            resolver.atomic_swap(buffer1.as_ptr() as *mut u8, 0),
+            Err(MBShareMemError::Permission(format!(
+                "Write [{:#x} - {:#x}] @ {:#x} is not permitted!",
+                buffer1.as_ptr() as MBPtrT,
+                buffer1.as_ptr() as MBPtrT + 5,
+                buffer1.as_ptr() as MBPtrT
+            )))
+        );
+    }
+    #[test]
+    fn mb_str_limits_test() {
+        let resolver = MBLocalPtrResolver;
+        let c_str = b"ab\xffcdefgh\0";
+        let mut limits = MBStrLimits {
+            max_len: 4,
+            batch_len: 3,
+            decode: MBStrDecode::Strict,
+        };
+        assert!(resolver.read_c_str_with(c_str.as_ptr(), &limits).is_err());
+        limits.decode = MBStrDecode::Lossy;
+        assert_eq!(
+            resolver.read_c_str_with(c_str.as_ptr(), &limits).unwrap(),
+            MBStr {
+                s: "ab\u{fffd}c".to_string(),
+                truncated: true
+            }
+        );
+        limits.decode = MBStrDecode::HexEscape;
+        limits.max_len = 64;
+        assert_eq!(
+            resolver.read_c_str_with(c_str.as_ptr(), &limits).unwrap(),
+            MBStr {
+                s: "ab\\xffcdefgh".to_string(),
+                truncated: false
+            }
+        );
+        //nul right after max_len bytes
+        limits.max_len = 9;
+        assert_eq!(
+            resolver.read_c_str_with(c_str.as_ptr(), &limits).unwrap(),
+            MBStr {
+                s: "ab\\xffcdefgh".to_string(),
+                truncated: false
+            }
+        );
+        limits.max_len = 8;
+        assert_eq!(
+            resolver.read_c_str_with(c_str.as_ptr(), &limits).unwrap(),
+            MBStr {
+                s: "ab\\xffcdefg".to_string(),
+                truncated: true
+            }
+        );
+        let str_args = MBStringArgs {
+            len: 8,
+            ptr: c_str.as_ptr() as MBPtrT,
+        };
+        limits.max_len = 2;
+        assert_eq!(
+            resolver.read_str_with(&str_args, &limits).unwrap(),
+            MBStr {
+                s: "ab".to_string(),
+                truncated: true
+            }
+        );
+        //a multi-byte char split at max_len is dropped whole
+        let c_str = "ab\u{20ac}\u{20ac}\0".as_bytes();
+        limits.decode = MBStrDecode::Strict;
+        for (max_len, s) in [(3, "ab"), (4, "ab"), (5, "ab\u{20ac}"), (7, "ab\u{20ac}")] {
+            limits.max_len = max_len;
+            assert_eq!(
+                resolver.read_c_str_with(c_str.as_ptr(), &limits).unwrap(),
+                MBStr {
+                    s: s.to_string(),
+                    truncated: true
+                }
+            );
+            let str_args = MBStringArgs {
+                len: 8,
+                ptr: c_str.as_ptr() as MBPtrT,
+            };
+            assert_eq!(
+                resolver.read_str_with(&str_args, &limits).unwrap(),
+                MBStr {
+                    s: s.to_string(),
+                    truncated: true
+                }
+            );
+        }
+        //invalid utf-8 is printed instead of panicking the server
+        let server = MBLocalServer::new("server", &Arc::new(None));
+        server.set_str_limits(MBStrLimits {
+            decode: MBStrDecode::Lossy,
+            ..Default::default()
+        });
+        let print = MBPrint::new();
+        let mut req = MBReqEntry::default();
+        print.put_req(&str_args, &mut req);
+        async_std::task::block_on(async {
+            assert!(server.do_cmd(&req).await.is_ok());
+        });
+    }
 }